@@ -1 +1 @@
-if("__TAURI__"in window){var __TAURI_PLUGIN_HTTP__=function(e){"use strict";async function t(e,t={},r){return window.__TAURI_INTERNALS__.invoke(e,t,r)}"function"==typeof SuppressedError&&SuppressedError;const r="Request canceled";return e.fetch=async function(e,n){const a=n?.signal;if(a?.aborted)throw new Error(r);const o=n?.maxRedirections,s=n?.connectTimeout,i=n?.proxy;n&&(delete n.maxRedirections,delete n.connectTimeout,delete n.proxy);const d=n?.headers?n.headers instanceof Headers?n.headers:new Headers(n.headers):new Headers,c=new Request(e,n),u=await c.arrayBuffer(),f=0!==u.byteLength?Array.from(new Uint8Array(u)):null;for(const[e,t]of c.headers)d.get(e)||d.set(e,t);const _=(d instanceof Headers?Array.from(d.entries()):Array.isArray(d)?d:Object.entries(d)).map((([e,t])=>[e,"string"==typeof t?t:t.toString()]));if(a?.aborted)throw new Error(r);const h=await t("plugin:http|fetch",{clientConfig:{method:c.method,url:c.url,headers:_,data:f,maxRedirections:o,connectTimeout:s,proxy:i}}),l=()=>t("plugin:http|fetch_cancel",{rid:h});if(a?.aborted)throw l(),new Error(r);a?.addEventListener("abort",(()=>{l()}));const{status:p,statusText:w,url:y,headers:T,rid:A}=await t("plugin:http|fetch_send",{rid:h}),g=await t("plugin:http|fetch_read_body",{rid:A}),R=new Response(g instanceof ArrayBuffer&&0!==g.byteLength?g:g instanceof Array&&g.length>0?new Uint8Array(g):null,{status:p,statusText:w});return Object.defineProperty(R,"url",{value:y}),Object.defineProperty(R,"headers",{value:new Headers(T)}),R},e}({});Object.defineProperty(window.__TAURI__,"http",{value:__TAURI_PLUGIN_HTTP__})}
+if("__TAURI__"in window){var __TAURI_PLUGIN_HTTP__=function(t){"use strict";function e(t,e,n,s){if("a"===n&&!s)throw new TypeError("Private accessor was defined without a getter");if("function"==typeof e?t!==e||!s:!e.has(t))throw new TypeError("Cannot read private member from an object whose class did not declare it");return"m"===n?s:"a"===n?s.call(t):s?s.value:e.get(t)}function n(t,e,n,s,o){if("function"==typeof e?t!==e||!o:!e.has(t))throw new TypeError("Cannot write private member to an object whose class did not declare it");return e.set(t,n),n}var s,o,r;"function"==typeof SuppressedError&&SuppressedError;const i="__TAURI_TO_IPC_KEY__";class a{constructor(){this.__TAURI_CHANNEL_MARKER__=!0,s.set(this,(()=>{})),o.set(this,0),r.set(this,{}),this.id=function(t,e=!1){return window.__TAURI_INTERNALS__.transformCallback(t,e)}((({message:t,id:i})=>{if(i===e(this,o,"f")){n(this,o,i+1),e(this,s,"f").call(this,t);const a=Object.keys(e(this,r,"f"));if(a.length>0){let t=i+1;for(const n of a.sort()){if(parseInt(n)!==t)break;{const o=e(this,r,"f")[n];delete e(this,r,"f")[n],e(this,s,"f").call(this,o),t+=1}}n(this,o,t)}}else e(this,r,"f")[i.toString()]=t}))}set onmessage(t){n(this,s,t)}get onmessage(){return e(this,s,"f")}[(s=new WeakMap,o=new WeakMap,r=new WeakMap,i)](){return`__CHANNEL__:${this.id}`}toJSON(){return this[i]()}}async function _(t,e={},n){return window.__TAURI_INTERNALS__.invoke(t,e,n)}const c="Request canceled";class l{constructor(t,e,n=[]){this.rid=t,this.listeners=e,this.buffered=n}static async connect(t,e){const n=[],s=[],o=new a;o.onmessage=t=>{0!==n.length?n.forEach((e=>{e(t)})):s.push(t)};const r=Array.from(new Headers(e?.headers).entries()),i=await _("plugin:http|sse_connect",{clientConfig:{method:"GET",url:t.toString(),headers:r,data:null,maxRedirections:e?.maxRedirections,connectTimeout:e?.connectTimeout,proxy:e?.proxy,decompression:e?.decompression},onEvent:o});return new l(i,n,s)}addListener(t){this.listeners.push(t),this.buffered.splice(0).forEach((e=>{t(e)}))}async close(){await _("plugin:http|sse_close",{rid:this.rid})}}return t.HttpEventSource=l,t.fetch=async function(t,e){const n=e?.signal;if(n?.aborted)throw new Error(c);const s=e?.maxRedirections,o=e?.connectTimeout,r=e?.proxy,i=e?.decompression,a=e?.responseType;e&&(delete e.maxRedirections,delete e.connectTimeout,delete e.proxy,delete e.decompression,delete e.responseType);const d=e?.headers?e.headers instanceof Headers?e.headers:new Headers(e.headers):new Headers,u=new Request(t,e),f=await u.arrayBuffer(),h=0!==f.byteLength?Array.from(new Uint8Array(f)):null;for(const[t,e]of u.headers)d.get(t)||d.set(t,e);const p=(d instanceof Headers?Array.from(d.entries()):Array.isArray(d)?d:Object.entries(d)).map((([t,e])=>[t,"string"==typeof e?e:e.toString()]));if(n?.aborted)throw new Error(c);const w=await _("plugin:http|fetch",{clientConfig:{method:u.method,url:u.url,headers:p,data:h,maxRedirections:s,connectTimeout:o,proxy:r,decompression:i}}),y=()=>_("plugin:http|fetch_cancel",{rid:w});if(n?.aborted)throw y(),new Error(c);n?.addEventListener("abort",(()=>{y()}));const{status:T,statusText:g,url:m,headers:A,rid:R}=await _("plugin:http|fetch_send",{rid:w});let b;if("text"===a){const t=await _("plugin:http|fetch_read_text",{rid:R});b=t.length>0?t:null}else{const t=await _("plugin:http|fetch_read_body",{rid:R});b=t instanceof ArrayBuffer&&0!==t.byteLength?t:t instanceof Array&&t.length>0?new Uint8Array(t):null}const E=new Response(b,{status:T,statusText:g});return Object.defineProperty(E,"url",{value:m}),Object.defineProperty(E,"headers",{value:new Headers(A)}),E},t}({});Object.defineProperty(window.__TAURI__,"http",{value:__TAURI_PLUGIN_HTTP__})}
@@ -6,7 +6,15 @@
 #[allow(dead_code)]
 mod scope;
 
-const COMMANDS: &[&str] = &["fetch", "fetch_cancel", "fetch_send", "fetch_read_body"];
+const COMMANDS: &[&str] = &[
+    "fetch",
+    "fetch_cancel",
+    "fetch_send",
+    "fetch_read_body",
+    "fetch_read_text",
+    "sse_connect",
+    "sse_close",
+];
 
 /// HTTP scope entry.
 #[derive(schemars::JsonSchema)]
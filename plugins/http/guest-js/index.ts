@@ -26,7 +26,7 @@
  * @module
  */
 
-import { invoke } from '@tauri-apps/api/core'
+import { invoke, Channel } from '@tauri-apps/api/core'
 
 /**
  * Configuration of a proxy that a Client should pass requests to.
@@ -67,6 +67,18 @@ export interface ProxyConfig {
   noProxy?: string
 }
 
+/**
+ * Enables or disables decoding of specific `Content-Encoding`s.
+ *
+ * @since 2.1.0
+ */
+export interface DecompressionConfig {
+  gzip?: boolean
+  brotli?: boolean
+  deflate?: boolean
+  zstd?: boolean
+}
+
 /**
  * Options to configure the Rust client used to make fetch requests
  *
@@ -84,6 +96,26 @@ export interface ClientOptions {
    * Configuration of a proxy that a Client should pass requests to.
    */
   proxy?: Proxy
+  /**
+   * Whether compressed response bodies are decoded by the Rust client.
+   * Pass a boolean to toggle all encodings at once.
+   *
+   * Only the encodings enabled through the `gzip`, `brotli`, `deflate`
+   * and `zstd` cargo features can be decoded.
+   *
+   * @since 2.1.0
+   */
+  decompression?: boolean | DecompressionConfig
+  /**
+   * How the response body is transferred from the Rust side.
+   *
+   * - `binary` (default): the raw bytes are sent over and decoded by the webview.
+   * - `text`: the body is decoded in Rust using the charset of the `Content-Type` header
+   * (requires the `charset` cargo feature, otherwise UTF-8 is assumed).
+   *
+   * @since 2.1.0
+   */
+  responseType?: 'binary' | 'text'
 }
 
 const ERROR_REQUEST_CANCELLED = 'Request canceled'
@@ -115,12 +147,16 @@ export async function fetch(
   const maxRedirections = init?.maxRedirections
   const connectTimeout = init?.connectTimeout
   const proxy = init?.proxy
+  const decompression = init?.decompression
+  const responseType = init?.responseType
 
   // Remove these fields before creating the request
   if (init) {
     delete init.maxRedirections
     delete init.connectTimeout
     delete init.proxy
+    delete init.decompression
+    delete init.responseType
   }
 
   const headers = init?.headers
@@ -172,7 +208,8 @@ export async function fetch(
       data,
       maxRedirections,
       connectTimeout,
-      proxy
+      proxy,
+      decompression
     }
   })
 
@@ -206,24 +243,31 @@ export async function fetch(
     rid
   })
 
-  const body = await invoke<ArrayBuffer | number[]>(
-    'plugin:http|fetch_read_body',
-    {
+  let body: BodyInit | null
+  if (responseType === 'text') {
+    const text = await invoke<string>('plugin:http|fetch_read_text', {
       rid: responseRid
-    }
-  )
+    })
+    body = text.length > 0 ? text : null
+  } else {
+    const bytes = await invoke<ArrayBuffer | number[]>(
+      'plugin:http|fetch_read_body',
+      {
+        rid: responseRid
+      }
+    )
+    body =
+      bytes instanceof ArrayBuffer && bytes.byteLength !== 0
+        ? bytes
+        : bytes instanceof Array && bytes.length > 0
+          ? new Uint8Array(bytes)
+          : null
+  }
 
-  const res = new Response(
-    body instanceof ArrayBuffer && body.byteLength !== 0
-      ? body
-      : body instanceof Array && body.length > 0
-        ? new Uint8Array(body)
-        : null,
-    {
-      status,
-      statusText
-    }
-  )
+  const res = new Response(body, {
+    status,
+    statusText
+  })
 
   // url and headers are read only properties
   // but seems like we can set them like this
@@ -238,3 +282,135 @@ export async function fetch(
 
   return res
 }
+
+/**
+ * A message received from a server-sent events stream.
+ *
+ * @since 2.1.0
+ */
+export interface ServerSentEvent {
+  /** The event type, `message` if the server did not set one. */
+  event: string
+  data: string
+  lastEventId: string
+}
+
+export interface EventSourceEventKind<T, D> {
+  type: T
+  data: D
+}
+
+/**
+ * Events dispatched to the listeners of an {@linkcode HttpEventSource}.
+ *
+ * An `Error` event is followed by a reconnection attempt unless the event source
+ * is `Closed` right after it.
+ *
+ * @since 2.1.0
+ */
+export type EventSourceEvent =
+  | EventSourceEventKind<'Open', undefined>
+  | EventSourceEventKind<'Message', ServerSentEvent>
+  | EventSourceEventKind<'Error', string>
+  | EventSourceEventKind<'Closed', undefined>
+
+/**
+ * Options to configure the Rust client used to connect to an event stream.
+ *
+ * @since 2.1.0
+ */
+export interface EventSourceOptions
+  extends Pick<
+    ClientOptions,
+    'maxRedirections' | 'connectTimeout' | 'proxy' | 'decompression'
+  > {
+  headers?: HeadersInit
+}
+
+/**
+ * A server-sent events client running on the Rust side, not subject to the webview CORS policy.
+ *
+ * The connection is re-established automatically when it is lost, honoring the
+ * `retry` field and sending the `Last-Event-ID` header, until {@linkcode HttpEventSource.close} is called.
+ *
+ * @example
+ * ```typescript
+ * const source = await HttpEventSource.connect('https://my.api/events')
+ * source.addListener((event) => {
+ *   if (event.type === 'Message') {
+ *     console.log(event.data.event, event.data.data)
+ *   }
+ * })
+ * await source.close()
+ * ```
+ *
+ * @since 2.1.0
+ */
+export class HttpEventSource {
+  rid: number
+  private readonly listeners: Array<(event: EventSourceEvent) => void>
+  private readonly buffered: EventSourceEvent[]
+
+  constructor(
+    rid: number,
+    listeners: Array<(event: EventSourceEvent) => void>,
+    buffered: EventSourceEvent[] = []
+  ) {
+    this.rid = rid
+    this.listeners = listeners
+    this.buffered = buffered
+  }
+
+  static async connect(
+    url: URL | string,
+    options?: EventSourceOptions
+  ): Promise<HttpEventSource> {
+    const listeners: Array<(event: EventSourceEvent) => void> = []
+    // the stream starts before a listener can be added
+    const buffered: EventSourceEvent[] = []
+
+    const onEvent = new Channel<EventSourceEvent>()
+    onEvent.onmessage = (event: EventSourceEvent): void => {
+      if (listeners.length === 0) {
+        buffered.push(event)
+        return
+      }
+      listeners.forEach((l) => {
+        l(event)
+      })
+    }
+
+    const headers = Array.from(new Headers(options?.headers).entries())
+
+    const rid = await invoke<number>('plugin:http|sse_connect', {
+      clientConfig: {
+        method: 'GET',
+        url: url.toString(),
+        headers,
+        data: null,
+        maxRedirections: options?.maxRedirections,
+        connectTimeout: options?.connectTimeout,
+        proxy: options?.proxy,
+        decompression: options?.decompression
+      },
+      onEvent
+    })
+
+    return new HttpEventSource(rid, listeners, buffered)
+  }
+
+  /**
+   * Adds a listener for the events of the stream.
+   * The events received before the first listener was added are delivered to it.
+   */
+  addListener(cb: (event: EventSourceEvent) => void): void {
+    this.listeners.push(cb)
+    this.buffered.splice(0).forEach((event) => {
+      cb(event)
+    })
+  }
+
+  async close(): Promise<void> {
+    await invoke('plugin:http|sse_close', { rid: this.rid })
+  }
+}
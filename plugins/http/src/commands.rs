@@ -85,6 +85,7 @@ pub struct ClientConfig {
     connect_timeout: Option<u64>,
     max_redirections: Option<usize>,
     proxy: Option<Proxy>,
+    decompression: Option<Decompression>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -117,6 +118,25 @@ pub struct BasicAuth {
     password: String,
 }
 
+/// Controls which `Content-Encoding`s of the response are transparently decoded.
+///
+/// Only the encodings whose cargo feature (`gzip`, `brotli`, `deflate` or `zstd`)
+/// is enabled can be decoded, the others are ignored.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Decompression {
+    All(bool),
+    Config(DecompressionConfig),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecompressionConfig {
+    gzip: Option<bool>,
+    brotli: Option<bool>,
+    deflate: Option<bool>,
+    zstd: Option<bool>,
+}
+
 #[inline]
 fn proxy_creator(
     url_or_config: UrlOrConfig,
@@ -165,14 +185,57 @@ fn attach_proxy(
     Ok(builder)
 }
 
-#[command]
-pub async fn fetch<R: Runtime>(
-    webview: Webview<R>,
-    state: State<'_, Http>,
+#[allow(unused_mut, unused_variables)]
+fn attach_decompression(
+    decompression: Decompression,
+    mut builder: reqwest::ClientBuilder,
+) -> reqwest::ClientBuilder {
+    let DecompressionConfig {
+        gzip,
+        brotli,
+        deflate,
+        zstd,
+    } = match decompression {
+        Decompression::All(enabled) => DecompressionConfig {
+            gzip: Some(enabled),
+            brotli: Some(enabled),
+            deflate: Some(enabled),
+            zstd: Some(enabled),
+        },
+        Decompression::Config(config) => config,
+    };
+
+    #[cfg(feature = "gzip")]
+    if let Some(gzip) = gzip {
+        builder = builder.gzip(gzip);
+    }
+
+    #[cfg(feature = "brotli")]
+    if let Some(brotli) = brotli {
+        builder = builder.brotli(brotli);
+    }
+
+    #[cfg(feature = "deflate")]
+    if let Some(deflate) = deflate {
+        builder = builder.deflate(deflate);
+    }
+
+    #[cfg(feature = "zstd")]
+    if let Some(zstd) = zstd {
+        builder = builder.zstd(zstd);
+    }
+
+    builder
+}
+
+/// Builds a request for an `http` or `https` URL allowed by the given scope,
+/// configuring the client and headers the same way `fetch` does.
+pub(crate) fn build_request<R: Runtime>(
+    webview: &Webview<R>,
+    state: &Http,
     client_config: ClientConfig,
-    command_scope: CommandScope<Entry>,
-    global_scope: GlobalScope<Entry>,
-) -> crate::Result<ResourceId> {
+    scope: &Scope<'_>,
+) -> crate::Result<reqwest::RequestBuilder> {
     let ClientConfig {
         method,
         url,
@@ -181,9 +244,17 @@ pub async fn fetch<R: Runtime>(
         connect_timeout,
         max_redirections,
         proxy,
+        decompression,
     } = client_config;
 
-    let scheme = url.scheme();
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::SchemeNotSupport(url.scheme().to_string()));
+    }
+
+    if !scope.is_allowed(&url) {
+        return Err(Error::UrlNotAllowed(url));
+    }
+
     let method = Method::from_bytes(method.as_bytes())?;
 
     let mut headers = HeaderMap::new();
@@ -202,9 +273,90 @@ pub async fn fetch<R: Runtime>(
         headers.append(name, HeaderValue::from_str(&v)?);
     }
 
+    let mut builder = reqwest::ClientBuilder::new();
+
+    if let Some(timeout) = connect_timeout {
+        builder = builder.connect_timeout(Duration::from_millis(timeout));
+    }
+
+    if let Some(max_redirections) = max_redirections {
+        builder = builder.redirect(if max_redirections == 0 {
+            Policy::none()
+        } else {
+            Policy::limited(max_redirections)
+        });
+    }
+
+    if let Some(proxy_config) = proxy {
+        builder = attach_proxy(proxy_config, builder)?;
+    }
+
+    if let Some(decompression) = decompression {
+        builder = attach_decompression(decompression, builder);
+    }
+
+    #[cfg(feature = "cookies")]
+    {
+        builder = builder.cookie_provider(state.cookies_jar.clone());
+    }
+
+    let mut request = builder.build()?.request(method.clone(), url);
+
+    // POST and PUT requests should always have a 0 length content-length,
+    // if there is no body. https://fetch.spec.whatwg.org/#http-network-or-cache-fetch
+    if data.is_none() && matches!(method, Method::POST | Method::PUT) {
+        headers.append(header::CONTENT_LENGTH, HeaderValue::from_str("0")?);
+    }
+
+    if headers.contains_key(header::RANGE) {
+        // https://fetch.spec.whatwg.org/#http-network-or-cache-fetch step 18
+        // If httpRequest’s header list contains `Range`, then append (`Accept-Encoding`, `identity`)
+        headers.append(header::ACCEPT_ENCODING, HeaderValue::from_str("identity")?);
+    }
+
+    if !headers.contains_key(header::USER_AGENT) {
+        headers.append(header::USER_AGENT, HeaderValue::from_str(HTTP_USER_AGENT)?);
+    }
+
+    // ensure we have an Origin header set
+    if cfg!(not(feature = "unsafe-headers")) || !headers.contains_key(header::ORIGIN) {
+        if let Ok(url) = webview.url() {
+            headers.append(
+                header::ORIGIN,
+                HeaderValue::from_str(&url.origin().ascii_serialization())?,
+            );
+        }
+    }
+
+    // In case empty origin is passed, remove it. Some services do not like Origin header
+    // so this way we can remove it in explicit way. The default behaviour is still to set it
+    if cfg!(feature = "unsafe-headers")
+        && headers.get(header::ORIGIN) == Some(&HeaderValue::from_static(""))
+    {
+        headers.remove(header::ORIGIN);
+    };
+
+    if let Some(data) = data {
+        request = request.body(data);
+    }
+
+    Ok(request.headers(headers))
+}
+
+#[command]
+pub async fn fetch<R: Runtime>(
+    webview: Webview<R>,
+    state: State<'_, Http>,
+    client_config: ClientConfig,
+    command_scope: CommandScope<Entry>,
+    global_scope: GlobalScope<Entry>,
+) -> crate::Result<ResourceId> {
+    let url = client_config.url.clone();
+    let scheme = url.scheme();
+
     match scheme {
         "http" | "https" => {
-            if Scope::new(
+            let scope = Scope::new(
                 command_scope
                     .allows()
                     .iter()
@@ -215,82 +367,14 @@ pub async fn fetch<R: Runtime>(
                     .iter()
                     .chain(global_scope.denies())
                     .collect(),
-            )
-            .is_allowed(&url)
-            {
-                let mut builder = reqwest::ClientBuilder::new();
-
-                if let Some(timeout) = connect_timeout {
-                    builder = builder.connect_timeout(Duration::from_millis(timeout));
-                }
-
-                if let Some(max_redirections) = max_redirections {
-                    builder = builder.redirect(if max_redirections == 0 {
-                        Policy::none()
-                    } else {
-                        Policy::limited(max_redirections)
-                    });
-                }
-
-                if let Some(proxy_config) = proxy {
-                    builder = attach_proxy(proxy_config, builder)?;
-                }
-
-                #[cfg(feature = "cookies")]
-                {
-                    builder = builder.cookie_provider(state.cookies_jar.clone());
-                }
-
-                let mut request = builder.build()?.request(method.clone(), url);
-
-                // POST and PUT requests should always have a 0 length content-length,
-                // if there is no body. https://fetch.spec.whatwg.org/#http-network-or-cache-fetch
-                if data.is_none() && matches!(method, Method::POST | Method::PUT) {
-                    headers.append(header::CONTENT_LENGTH, HeaderValue::from_str("0")?);
-                }
-
-                if headers.contains_key(header::RANGE) {
-                    // https://fetch.spec.whatwg.org/#http-network-or-cache-fetch step 18
-                    // If httpRequest’s header list contains `Range`, then append (`Accept-Encoding`, `identity`)
-                    headers.append(header::ACCEPT_ENCODING, HeaderValue::from_str("identity")?);
-                }
-
-                if !headers.contains_key(header::USER_AGENT) {
-                    headers.append(header::USER_AGENT, HeaderValue::from_str(HTTP_USER_AGENT)?);
-                }
-
-                // ensure we have an Origin header set
-                if cfg!(not(feature = "unsafe-headers")) || !headers.contains_key(header::ORIGIN) {
-                    if let Ok(url) = webview.url() {
-                        headers.append(
-                            header::ORIGIN,
-                            HeaderValue::from_str(&url.origin().ascii_serialization())?,
-                        );
-                    }
-                }
-
-                // In case empty origin is passed, remove it. Some services do not like Origin header
-                // so this way we can remove it in explicit way. The default behaviour is still to set it
-                if cfg!(feature = "unsafe-headers")
-                    && headers.get(header::ORIGIN) == Some(&HeaderValue::from_static(""))
-                {
-                    headers.remove(header::ORIGIN);
-                };
-
-                if let Some(data) = data {
-                    request = request.body(data);
-                }
-
-                request = request.headers(headers);
-
-                let fut = async move { request.send().await.map_err(Into::into) };
-                let mut resources_table = webview.resources_table();
-                let rid = resources_table.add_request(Box::pin(fut));
-
-                Ok(rid)
-            } else {
-                Err(Error::UrlNotAllowed(url))
-            }
+            );
+            let request = build_request(&webview, &state, client_config, &scope)?;
+
+            let fut = async move { request.send().await.map_err(Into::into) };
+            let mut resources_table = webview.resources_table();
+            let rid = resources_table.add_request(Box::pin(fut));
+
+            Ok(rid)
         }
         "data" => {
             let data_url =
@@ -386,6 +470,23 @@ pub(crate) async fn fetch_read_body<R: Runtime>(
     Ok(tauri::ipc::Response::new(res.bytes().await?.to_vec()))
 }
 
+/// Reads the response body as text.
+///
+/// With the `charset` feature enabled, the body is decoded using the charset
+/// of the `Content-Type` header, falling back to UTF-8.
+#[tauri::command]
+pub(crate) async fn fetch_read_text<R: Runtime>(
+    webview: Webview<R>,
+    rid: ResourceId,
+) -> crate::Result<String> {
+    let res = {
+        let mut resources_table = webview.resources_table();
+        resources_table.take::<ReqwestResponse>(rid)?
+    };
+    let res = Arc::into_inner(res).unwrap().0;
+    Ok(res.text().await?)
+}
+
 // forbidden headers per fetch spec https://fetch.spec.whatwg.org/#terminology-headers
 #[cfg(not(feature = "unsafe-headers"))]
 fn is_unsafe_header(header: &HeaderName) -> bool {
@@ -415,3 +516,130 @@ fn is_unsafe_header(header: &HeaderName) -> bool {
         lower.starts_with("proxy-") || lower.starts_with("sec-")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tauri::{
+        test::{mock_builder, mock_context, noop_assets, MockRuntime},
+        App, WebviewWindowBuilder,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Serves `body` with the given headers to every request, returning the server URL.
+    async fn serve(headers: &'static str, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\n{headers}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(&body).await.unwrap();
+            }
+        });
+        url
+    }
+
+    fn webview() -> (App<MockRuntime>, Webview<MockRuntime>) {
+        let app = mock_builder().build(mock_context(noop_assets())).unwrap();
+        let window = WebviewWindowBuilder::new(&app, "main", Default::default())
+            .build()
+            .unwrap();
+        let webview = window.as_ref().clone();
+        (app, webview)
+    }
+
+    /// Sends a request the way `fetch` and `fetch_send` do, with the URL in scope.
+    async fn send(
+        webview: &Webview<MockRuntime>,
+        client_config: serde_json::Value,
+    ) -> FetchResponse {
+        let client_config: ClientConfig = serde_json::from_value(client_config).unwrap();
+        let entry = Arc::new(client_config.url.as_str().parse::<Entry>().unwrap());
+        let scope = Scope::new(vec![&entry], Vec::new());
+        let state = Http {
+            #[cfg(feature = "cookies")]
+            cookies_jar: Default::default(),
+        };
+        let request = build_request(webview, &state, client_config, &scope).unwrap();
+        let fut = async move { request.send().await.map_err(Into::into) };
+        let rid = webview.resources_table().add_request(Box::pin(fut));
+        fetch_send(webview.clone(), rid).await.unwrap()
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn decompresses_gzip_bodies_unless_disabled() {
+        use std::io::Write;
+        use tauri::ipc::{InvokeResponseBody, IpcResponse};
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(b"hello world").unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let url = serve(
+            "content-type: text/plain\r\ncontent-encoding: gzip",
+            gzipped.clone(),
+        )
+        .await;
+        let (_app, webview) = webview();
+        let config = |decompression| {
+            json!({
+                "method": "GET",
+                "url": url,
+                "headers": [],
+                "data": null,
+                "decompression": decompression
+            })
+        };
+
+        let response = send(&webview, config(json!(false))).await;
+        assert!(response
+            .headers
+            .contains(&("content-encoding".into(), "gzip".into())));
+        let body = fetch_read_body(webview.clone(), response.rid)
+            .await
+            .unwrap();
+        assert!(matches!(body.body().unwrap(), InvokeResponseBody::Raw(body) if body == gzipped));
+
+        let response = send(&webview, config(json!({ "gzip": true }))).await;
+        // the encoding headers are removed once the body is decoded
+        assert!(!response
+            .headers
+            .iter()
+            .any(|(name, _)| name == "content-encoding"));
+        let text = fetch_read_text(webview.clone(), response.rid)
+            .await
+            .unwrap();
+        assert_eq!(text, "hello world");
+    }
+
+    #[cfg(feature = "charset")]
+    #[tokio::test]
+    async fn decodes_text_with_the_response_charset() {
+        let url = serve(
+            "content-type: text/plain; charset=windows-1252",
+            b"caf\xe9 \x80".to_vec(),
+        )
+        .await;
+        let (_app, webview) = webview();
+
+        let response = send(
+            &webview,
+            json!({ "method": "GET", "url": url, "headers": [], "data": null }),
+        )
+        .await;
+        let text = fetch_read_text(webview.clone(), response.rid)
+            .await
+            .unwrap();
+        assert_eq!(text, "café €");
+    }
+}
@@ -41,6 +41,12 @@ pub enum Error {
     Tauri(#[from] tauri::Error),
     #[error(transparent)]
     Utf8(#[from] std::string::FromUtf8Error),
+    /// The event stream responded with a status other than `200 OK`.
+    #[error("event stream responded with status code {0}")]
+    EventStreamStatus(u16),
+    /// The event stream responded with a content type other than `text/event-stream`.
+    #[error("event stream responded with unsupported content type `{0}`")]
+    EventStreamContentType(String),
 }
 
 impl Serialize for Error {
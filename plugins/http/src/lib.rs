@@ -15,6 +15,7 @@ pub use error::{Error, Result};
 mod commands;
 mod error;
 mod scope;
+mod sse;
 
 pub(crate) struct Http {
     #[cfg(feature = "cookies")]
@@ -38,6 +39,9 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
             commands::fetch_cancel,
             commands::fetch_send,
             commands::fetch_read_body,
+            commands::fetch_read_text,
+            sse::sse_connect,
+            sse::sse_close,
         ])
         .build()
 }
@@ -0,0 +1,449 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Server-Sent Events client following the
+//! [HTML spec](https://html.spec.whatwg.org/multipage/server-sent-events.html) processing model.
+
+use std::{sync::Arc, time::Duration};
+
+use http::{header, HeaderName, HeaderValue, StatusCode};
+use serde::Serialize;
+use tauri::{
+    command,
+    ipc::{Channel, CommandScope, GlobalScope},
+    Manager, ResourceId, Runtime, State, Webview,
+};
+use tokio::sync::oneshot::{channel, Sender};
+
+use crate::{
+    commands::{build_request, ClientConfig},
+    scope::{Entry, Scope},
+    Error, Http, Result,
+};
+
+const LAST_EVENT_ID: HeaderName = HeaderName::from_static("last-event-id");
+const DEFAULT_RETRY: Duration = Duration::from_millis(3000);
+
+/// A message dispatched by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerSentEvent {
+    /// The event type, `message` if the server did not set one.
+    pub event: String,
+    pub data: String,
+    pub last_event_id: String,
+}
+
+/// Payload sent to the `onEvent` channel of an event source.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum EventSourceEvent {
+    /// The connection has been (re-)established.
+    Open,
+    Message(ServerSentEvent),
+    /// The connection failed or was lost. Unless followed by [`Self::Closed`],
+    /// a reconnection is scheduled.
+    Error(String),
+    /// The event source is closed and will not reconnect.
+    Closed,
+}
+
+struct EventSource(std::sync::Mutex<Option<Sender<()>>>);
+
+impl tauri::Resource for EventSource {
+    fn close(self: Arc<Self>) {
+        if let Some(abort_tx) = self.0.lock().unwrap().take() {
+            let _ = abort_tx.send(());
+        }
+    }
+}
+
+/// Incremental parser for the `text/event-stream` format.
+#[derive(Debug, Default)]
+struct EventStreamParser {
+    line: Vec<u8>,
+    last_was_cr: bool,
+    first_line: bool,
+    data: String,
+    event_type: String,
+    last_event_id: String,
+    retry: Option<Duration>,
+}
+
+impl EventStreamParser {
+    fn new() -> Self {
+        Self {
+            first_line: true,
+            ..Default::default()
+        }
+    }
+
+    /// Feeds a chunk of the response body and returns the events it completed.
+    fn feed(&mut self, chunk: &[u8]) -> Vec<ServerSentEvent> {
+        let mut events = Vec::new();
+        for &byte in chunk {
+            match byte {
+                b'\n' if self.last_was_cr => {
+                    self.last_was_cr = false;
+                }
+                b'\r' | b'\n' => {
+                    self.last_was_cr = byte == b'\r';
+                    let line = std::mem::take(&mut self.line);
+                    if let Some(event) = self.process_line(&String::from_utf8_lossy(&line)) {
+                        events.push(event);
+                    }
+                }
+                _ => {
+                    self.last_was_cr = false;
+                    self.line.push(byte);
+                }
+            }
+        }
+        events
+    }
+
+    /// Discards any partially received event, keeping the last event ID and
+    /// reconnection time for the next connection.
+    fn reset(&mut self) {
+        self.line.clear();
+        self.last_was_cr = false;
+        self.first_line = true;
+        self.data.clear();
+        self.event_type.clear();
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<ServerSentEvent> {
+        let line = if std::mem::take(&mut self.first_line) {
+            line.strip_prefix('\u{feff}').unwrap_or(line)
+        } else {
+            line
+        };
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event_type = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = value.to_string(),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<ServerSentEvent> {
+        let event_type = std::mem::take(&mut self.event_type);
+        let mut data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return None;
+        }
+        data.pop();
+
+        Some(ServerSentEvent {
+            event: if event_type.is_empty() {
+                "message".into()
+            } else {
+                event_type
+            },
+            data,
+            last_event_id: self.last_event_id.clone(),
+        })
+    }
+}
+
+fn validate_response(response: &reqwest::Response) -> Result<()> {
+    let status = response.status();
+    if status != StatusCode::OK {
+        return Err(Error::EventStreamStatus(status.as_u16()));
+    }
+
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    if !essence.eq_ignore_ascii_case("text/event-stream") {
+        return Err(Error::EventStreamContentType(content_type.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Keeps the event source connected until the server fails the connection
+/// or the webview stops listening.
+async fn run_event_source(
+    client: reqwest::Client,
+    request: reqwest::Request,
+    on_event: &Channel<EventSourceEvent>,
+) {
+    let mut parser = EventStreamParser::new();
+
+    loop {
+        // event stream requests never have a streaming body so they can always be cloned
+        let Some(mut request) = request.try_clone() else {
+            return;
+        };
+
+        if !parser.last_event_id.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(&parser.last_event_id) {
+                request.headers_mut().insert(LAST_EVENT_ID, value);
+            }
+        }
+
+        match client.execute(request).await {
+            Ok(mut response) => {
+                if let Err(e) = validate_response(&response) {
+                    let _ = on_event.send(EventSourceEvent::Error(e.to_string()));
+                    return;
+                }
+
+                if on_event.send(EventSourceEvent::Open).is_err() {
+                    return;
+                }
+
+                let error = loop {
+                    match response.chunk().await {
+                        Ok(Some(chunk)) => {
+                            for event in parser.feed(&chunk) {
+                                if on_event.send(EventSourceEvent::Message(event)).is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                        Ok(None) => break "connection closed by the server".to_string(),
+                        Err(e) => break e.to_string(),
+                    }
+                };
+
+                if on_event.send(EventSourceEvent::Error(error)).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                if on_event
+                    .send(EventSourceEvent::Error(e.to_string()))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+
+        parser.reset();
+        tokio::time::sleep(parser.retry.unwrap_or(DEFAULT_RETRY)).await;
+    }
+}
+
+#[command]
+pub async fn sse_connect<R: Runtime>(
+    webview: Webview<R>,
+    state: State<'_, Http>,
+    client_config: ClientConfig,
+    on_event: Channel<EventSourceEvent>,
+    command_scope: CommandScope<Entry>,
+    global_scope: GlobalScope<Entry>,
+) -> Result<ResourceId> {
+    let scope = Scope::new(
+        command_scope
+            .allows()
+            .iter()
+            .chain(global_scope.allows())
+            .collect(),
+        command_scope
+            .denies()
+            .iter()
+            .chain(global_scope.denies())
+            .collect(),
+    );
+
+    let (client, request) = build_request(&webview, &state, client_config, &scope)?
+        .header(header::ACCEPT, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .build_split();
+    let request = request?;
+
+    let (abort_tx, abort_rx) = channel();
+    let rid = webview
+        .resources_table()
+        .add(EventSource(std::sync::Mutex::new(Some(abort_tx))));
+
+    tauri::async_runtime::spawn(async move {
+        let ended = tokio::select! {
+            _ = run_event_source(client, request, &on_event) => true,
+            _ = abort_rx => false,
+        };
+        // the resource is only removed by `sse_close` otherwise
+        if ended {
+            let _ = webview.resources_table().take::<EventSource>(rid);
+        }
+        let _ = on_event.send(EventSourceEvent::Closed);
+    });
+
+    Ok(rid)
+}
+
+#[command]
+pub fn sse_close<R: Runtime>(webview: Webview<R>, rid: ResourceId) -> Result<()> {
+    // the event source may have already stopped on its own, e.g. on a fatal error
+    let _ = webview.resources_table().close(rid);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::ipc::InvokeResponseBody;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    fn event(event: &str, data: &str, last_event_id: &str) -> ServerSentEvent {
+        ServerSentEvent {
+            event: event.into(),
+            data: data.into(),
+            last_event_id: last_event_id.into(),
+        }
+    }
+
+    #[test]
+    fn parses_events() {
+        let mut parser = EventStreamParser::new();
+        let events =
+            parser.feed(b"\xEF\xBB\xBFdata: first\n\nevent: update\ndata:a\ndata: b\nid: 7\n\n");
+        assert_eq!(
+            events,
+            vec![event("message", "first", ""), event("update", "a\nb", "7")]
+        );
+    }
+
+    #[test]
+    fn handles_split_chunks_and_line_endings() {
+        let mut parser = EventStreamParser::new();
+        assert!(parser.feed(b"data: hel").is_empty());
+        assert!(parser.feed(b"lo\r").is_empty());
+        assert_eq!(parser.feed(b"\n\r\n"), vec![event("message", "hello", "")]);
+        assert_eq!(
+            parser.feed(b"data: cr\r\r"),
+            vec![event("message", "cr", "")]
+        );
+    }
+
+    #[test]
+    fn ignores_comments_and_empty_data() {
+        let mut parser = EventStreamParser::new();
+        assert!(parser.feed(b": keep-alive\n\nevent: ping\n\n").is_empty());
+        // the event type is reset even if nothing was dispatched
+        assert_eq!(parser.feed(b"data\n\n"), vec![event("message", "", "")]);
+    }
+
+    #[test]
+    fn keeps_last_event_id_and_retry() {
+        let mut parser = EventStreamParser::new();
+        parser.feed(b"id: 42\nretry: 1500\nretry: soon\ndata: x\n\nid: a\0b\n\n");
+        assert_eq!(parser.last_event_id, "42");
+        assert_eq!(parser.retry, Some(Duration::from_millis(1500)));
+
+        parser.feed(b"data: incomplete\n");
+        parser.reset();
+        assert_eq!(parser.feed(b"\n"), Vec::new());
+        assert_eq!(
+            parser.feed(b"data: y\n\n"),
+            vec![event("message", "y", "42")]
+        );
+    }
+
+    /// Reads a request up to the end of its headers, returning them.
+    async fn read_head(stream: &mut tokio::net::TcpStream) -> String {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            if stream.read(&mut byte).await.unwrap() == 0 {
+                break;
+            }
+            head.push(byte[0]);
+        }
+        String::from_utf8(head).unwrap().to_ascii_lowercase()
+    }
+
+    #[tokio::test]
+    async fn reconnects_with_the_last_event_id() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let (heads_tx, mut heads) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let bodies = [
+                // the connection drops once the body is sent
+                "retry: 10\nid: 1\ndata: first\n\n",
+                "data: second\n\n",
+            ];
+            for body in bodies {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                heads_tx.send(read_head(&mut stream).await).unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n{body}"
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let on_event = Channel::new(move |body| {
+            if let InvokeResponseBody::Json(json) = body {
+                let _ = events_tx.send(serde_json::from_str::<serde_json::Value>(&json).unwrap());
+            }
+            Ok(())
+        });
+        let client = reqwest::Client::new();
+        let request = client.get(url).build().unwrap();
+        let source =
+            tokio::spawn(async move { run_event_source(client, request, &on_event).await });
+
+        let mut received = Vec::new();
+        while received.len() < 5 {
+            received.push(events.recv().await.unwrap());
+        }
+        source.abort();
+
+        assert_eq!(
+            received,
+            vec![
+                serde_json::json!({ "type": "Open" }),
+                serde_json::json!({
+                    "type": "Message",
+                    "data": { "event": "message", "data": "first", "lastEventId": "1" }
+                }),
+                serde_json::json!({ "type": "Error", "data": "connection closed by the server" }),
+                serde_json::json!({ "type": "Open" }),
+                serde_json::json!({
+                    "type": "Message",
+                    "data": { "event": "message", "data": "second", "lastEventId": "1" }
+                }),
+            ]
+        );
+        assert!(!heads.recv().await.unwrap().contains("last-event-id"));
+        assert!(heads.recv().await.unwrap().contains("last-event-id: 1\r\n"));
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Optional checks keeping other local processes and web pages away from the server.
+
+use http::{
+    header::{AUTHORIZATION, COOKIE, HOST, ORIGIN},
+    Request,
+};
+use rand::{distributions::Alphanumeric, Rng};
+
+const TOKEN_LENGTH: usize = 32;
+pub(crate) const TOKEN_COOKIE: &str = "tauri-localhost-token";
+const TOKEN_QUERY_PARAMETER: &str = "token";
+const LOOPBACK_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+
+#[derive(Debug, Default)]
+pub(crate) struct AccessControl {
+    /// Secret generated on every launch.
+    pub token: Option<String>,
+    /// Host names accepted in the `Host` header, in addition to the loopback names.
+    /// `None` disables the check.
+    pub hosts: Option<Vec<String>>,
+    /// `None` accepts any origin.
+    pub allowed_origins: Option<Vec<String>>,
+}
+
+/// How an allowed request was authenticated.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Access {
+    Allowed,
+    /// The token was sent in the query or a header, so it should be stored in a cookie
+    /// to authenticate the requests of the page.
+    AllowedWithToken,
+}
+
+pub(crate) fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+impl AccessControl {
+    /// Checks the request to the server bound to `port`, returning `None` if it must be rejected.
+    pub fn check<B>(&self, request: &Request<B>, port: u16) -> Option<Access> {
+        let header = |name| request.headers().get(name).and_then(|v| v.to_str().ok());
+
+        // against DNS rebinding, where another domain resolves to the loopback address
+        if let Some(hosts) = &self.hosts {
+            let host = header(HOST)?.to_ascii_lowercase();
+            if !is_allowed_host(hosts, &host, port) {
+                return None;
+            }
+        }
+
+        if let (Some(allowed_origins), Some(origin)) = (&self.allowed_origins, header(ORIGIN)) {
+            let same_origin = origin.strip_prefix("http://").is_some_and(|host| {
+                is_allowed_host(self.hosts.as_deref().unwrap_or_default(), host, port)
+            });
+            if !same_origin && !allowed_origins.iter().any(|allowed| allowed == origin) {
+                return None;
+            }
+        }
+
+        let Some(token) = &self.token else {
+            return Some(Access::Allowed);
+        };
+        let query = request.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(TOKEN_QUERY_PARAMETER)?.strip_prefix('='))
+        });
+        let bearer = header(AUTHORIZATION).and_then(|v| v.strip_prefix("Bearer "));
+        if query
+            .or(bearer)
+            .is_some_and(|provided| tokens_match(provided, token))
+        {
+            return Some(Access::AllowedWithToken);
+        }
+        let cookie = request
+            .headers()
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .find_map(|cookie| {
+                let (name, value) = cookie.trim().split_once('=')?;
+                (name == TOKEN_COOKIE).then_some(value)
+            });
+        cookie
+            .is_some_and(|provided| tokens_match(provided, token))
+            .then_some(Access::Allowed)
+    }
+}
+
+/// Whether `host`, with its port, designates this server.
+fn is_allowed_host(hosts: &[String], host: &str, port: u16) -> bool {
+    let (name, host_port) = match host.rsplit_once(':') {
+        // `[::1]` has no port
+        Some((name, host_port)) if !host_port.ends_with(']') => (name, host_port.parse().ok()),
+        _ => (host, Some(80)),
+    };
+    host_port == Some(port)
+        && (LOOPBACK_HOSTS.contains(&name) || hosts.iter().any(|allowed| allowed == name))
+}
+
+/// Compares in constant time so the token can't be guessed from response times.
+fn tokens_match(provided: &str, token: &str) -> bool {
+    provided.len() == token.len()
+        && provided
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PORT: u16 = 1430;
+
+    fn request(uri: &str, headers: &[(&str, &str)]) -> Request<()> {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(()).unwrap()
+    }
+
+    #[test]
+    fn allows_everything_by_default() {
+        let access = AccessControl::default();
+        assert_eq!(
+            access.check(&request("/", &[("host", "evil.example")]), PORT),
+            Some(Access::Allowed)
+        );
+    }
+
+    #[test]
+    fn checks_token() {
+        let token = generate_token();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        let access = AccessControl {
+            token: Some(token.clone()),
+            ..Default::default()
+        };
+
+        assert_eq!(access.check(&request("/", &[]), PORT), None);
+        assert_eq!(access.check(&request("/?token=wrong", &[]), PORT), None);
+        assert_eq!(
+            access.check(&request(&format!("/?lang=en&token={token}"), &[]), PORT),
+            Some(Access::AllowedWithToken)
+        );
+        assert_eq!(
+            access.check(
+                &request("/", &[("authorization", &format!("Bearer {token}"))]),
+                PORT
+            ),
+            Some(Access::AllowedWithToken)
+        );
+        assert_eq!(
+            access.check(
+                &request(
+                    "/index.js",
+                    &[("cookie", &format!("theme=dark; {TOKEN_COOKIE}={token}"))]
+                ),
+                PORT
+            ),
+            Some(Access::Allowed)
+        );
+    }
+
+    #[test]
+    fn checks_host_against_dns_rebinding() {
+        let access = AccessControl {
+            hosts: Some(vec!["app.local".into()]),
+            ..Default::default()
+        };
+        for host in [
+            "localhost:1430",
+            "127.0.0.1:1430",
+            "[::1]:1430",
+            "app.local:1430",
+        ] {
+            assert!(
+                access
+                    .check(&request("/", &[("host", host)]), PORT)
+                    .is_some(),
+                "{host}"
+            );
+        }
+        for host in ["evil.example:1430", "localhost:8080", "localhost", "[::1]"] {
+            assert!(
+                access
+                    .check(&request("/", &[("host", host)]), PORT)
+                    .is_none(),
+                "{host}"
+            );
+        }
+        assert!(access.check(&request("/", &[]), PORT).is_none());
+    }
+
+    #[test]
+    fn checks_origin() {
+        let access = AccessControl {
+            allowed_origins: Some(vec!["https://tauri.app".into()]),
+            ..Default::default()
+        };
+        // navigations don't send an origin
+        assert!(access.check(&request("/", &[]), PORT).is_some());
+        assert!(access
+            .check(&request("/", &[("origin", "https://tauri.app")]), PORT)
+            .is_some());
+        assert!(access
+            .check(&request("/", &[("origin", "http://localhost:1430")]), PORT)
+            .is_some());
+        assert!(access
+            .check(&request("/", &[("origin", "https://evil.example")]), PORT)
+            .is_none());
+    }
+}
@@ -0,0 +1,517 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! HTTP semantics of the asset responses: conditional and range requests and compression.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use http::{
+    header::{
+        ACCEPT_ENCODING, ACCEPT_RANGES, ALLOW, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+        CONTENT_SECURITY_POLICY, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RANGE, VARY,
+    },
+    HeaderMap, HeaderValue, Method, Request, Response, StatusCode,
+};
+use sha2::{Digest, Sha256};
+
+/// Assets smaller than this are not worth compressing.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+/// Favors speed since assets are compressed on their first request.
+const BROTLI_QUALITY: u32 = 5;
+
+pub(crate) struct Asset {
+    pub bytes: Vec<u8>,
+    pub mime_type: String,
+    pub csp_header: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    fn encode(self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Brotli => {
+                let mut encoder =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, 22);
+                encoder.write_all(bytes)?;
+                Ok(encoder.into_inner())
+            }
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// The ETag and encoded bodies of the assets, computed once per path.
+///
+/// Disabled in development, where the assets can be read from disk and change.
+pub(crate) struct AssetCache {
+    enabled: bool,
+    assets: Mutex<HashMap<String, Arc<CachedAsset>>>,
+}
+
+struct CachedAsset {
+    etag: String,
+    encoded: Mutex<HashMap<Encoding, Vec<u8>>>,
+}
+
+impl AssetCache {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            assets: Default::default(),
+        }
+    }
+
+    fn get(&self, path: &str, bytes: &[u8]) -> Arc<CachedAsset> {
+        if self.enabled {
+            if let Some(asset) = self.assets.lock().unwrap().get(path) {
+                return asset.clone();
+            }
+        }
+        let asset = Arc::new(CachedAsset {
+            etag: etag(bytes),
+            encoded: Default::default(),
+        });
+        if self.enabled {
+            self.assets
+                .lock()
+                .unwrap()
+                .insert(path.into(), asset.clone());
+        }
+        asset
+    }
+}
+
+impl CachedAsset {
+    fn encode(&self, encoding: Encoding, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        if let Some(encoded) = self.encoded.lock().unwrap().get(&encoding) {
+            return Ok(encoded.clone());
+        }
+        // other requests aren't blocked while compressing
+        let encoded = encoding.encode(bytes)?;
+        self.encoded
+            .lock()
+            .unwrap()
+            .insert(encoding, encoded.clone());
+        Ok(encoded)
+    }
+}
+
+/// Builds the response to `request`, `asset` being `None` if nothing matches its path.
+pub(crate) fn respond<B>(
+    request: &Request<B>,
+    asset: Option<Asset>,
+    cache: &AssetCache,
+) -> Response<Vec<u8>> {
+    let method = request.method();
+    if method != Method::GET && method != Method::HEAD {
+        let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED);
+        response
+            .headers_mut()
+            .insert(ALLOW, HeaderValue::from_static("GET, HEAD"));
+        return response;
+    }
+
+    let Some(asset) = asset else {
+        return without_body(method, status_response(StatusCode::NOT_FOUND));
+    };
+
+    let headers = request.headers();
+    let len = asset.bytes.len();
+    let cached = cache.get(request.uri().path(), &asset.bytes);
+    let etag = cached.etag.as_str();
+    let compressible = is_compressible(&asset.mime_type) && len >= MIN_COMPRESSED_SIZE;
+    let encoding = compressible
+        .then(|| header_str(headers, &ACCEPT_ENCODING).and_then(negotiate_encoding))
+        .flatten();
+
+    let mut response = Response::new(Vec::new());
+    let response_headers = response.headers_mut();
+    if let Ok(mime_type) = HeaderValue::from_str(&asset.mime_type) {
+        response_headers.insert(CONTENT_TYPE, mime_type);
+    }
+    if let Some(csp) = asset
+        .csp_header
+        .and_then(|csp| HeaderValue::from_str(&csp).ok())
+    {
+        response_headers.insert(CONTENT_SECURITY_POLICY, csp);
+    }
+    if compressible {
+        response_headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+    response_headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    // the representation depends on the encoding so each one has its own tag
+    let tag = match encoding {
+        Some(encoding) => format!("\"{etag}-{}\"", encoding.name()),
+        None => format!("\"{etag}\""),
+    };
+    response_headers.insert(ETAG, HeaderValue::from_str(&tag).unwrap());
+
+    if header_str(headers, &IF_NONE_MATCH).is_some_and(|tags| matches_etag(tags, etag)) {
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        return response;
+    }
+
+    // ranges apply to the identity encoding
+    match header_str(headers, &RANGE).and_then(|range| parse_range(range, len)) {
+        Some(Ok(range)) => {
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let content_range = format!("bytes {}-{}/{len}", range.start, range.end - 1);
+            response.headers_mut().insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&content_range).unwrap(),
+            );
+            response.headers_mut().insert(ETAG, etag_header(etag));
+            *response.body_mut() = asset.bytes[range].to_vec();
+        }
+        Some(Err(())) => {
+            let mut response = status_response(StatusCode::RANGE_NOT_SATISFIABLE);
+            response.headers_mut().insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{len}")).unwrap(),
+            );
+            return without_body(method, response);
+        }
+        None => match encoding.map(|encoding| (encoding, cached.encode(encoding, &asset.bytes))) {
+            Some((encoding, Ok(encoded))) => {
+                response
+                    .headers_mut()
+                    .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+                *response.body_mut() = encoded;
+            }
+            Some((_, Err(e))) => {
+                log::error!("failed to compress asset: {e}");
+                response.headers_mut().insert(ETAG, etag_header(etag));
+                *response.body_mut() = asset.bytes;
+            }
+            None => *response.body_mut() = asset.bytes,
+        },
+    }
+
+    without_body(method, response)
+}
+
+fn status_response(status: StatusCode) -> Response<Vec<u8>> {
+    let mut response = Response::new(status.canonical_reason().unwrap_or_default().into());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    response
+}
+
+/// Drops the body of responses to `HEAD` requests, keeping its length.
+fn without_body(method: &Method, mut response: Response<Vec<u8>>) -> Response<Vec<u8>> {
+    let len = response.body().len();
+    response
+        .headers_mut()
+        .insert(CONTENT_LENGTH, HeaderValue::from(len));
+    if method == Method::HEAD {
+        response.body_mut().clear();
+    }
+    response
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &http::HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn etag(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)[..16]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn etag_header(etag: &str) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{etag}\"")).unwrap()
+}
+
+/// Weak comparison of the `If-None-Match` tags with any encoding of the asset.
+fn matches_etag(tags: &str, etag: &str) -> bool {
+    tags.split(',').map(str::trim).any(|tag| {
+        if tag == "*" {
+            return true;
+        }
+        let tag = tag.trim_start_matches("W/").trim_matches('"');
+        tag == etag
+            || tag
+                .strip_prefix(etag)
+                .and_then(|suffix| suffix.strip_prefix('-'))
+                .is_some_and(|name| name == "br" || name == "gzip")
+    })
+}
+
+fn is_compressible(mime_type: &str) -> bool {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    essence.starts_with("text/")
+        || ["javascript", "json", "xml", "wasm", "svg"]
+            .iter()
+            .any(|kind| essence.contains(kind))
+}
+
+/// Picks the preferred encoding with the highest quality value, favoring brotli on ties.
+fn negotiate_encoding(accept_encoding: &str) -> Option<Encoding> {
+    let mut brotli = None;
+    let mut gzip = None;
+    let mut wildcard = None;
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';').map(str::trim);
+        let name = params.next().unwrap_or_default().to_ascii_lowercase();
+        let quality = params
+            .find_map(|param| param.strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.parse::<f32>().ok());
+        match name.as_str() {
+            "br" => brotli = quality,
+            "gzip" => gzip = quality,
+            "*" => wildcard = quality,
+            _ => {}
+        }
+    }
+
+    let brotli = brotli.or(wildcard).unwrap_or(0.0);
+    let gzip = gzip.or(wildcard).unwrap_or(0.0);
+    if brotli > 0.0 && brotli >= gzip {
+        Some(Encoding::Brotli)
+    } else if gzip > 0.0 {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
+
+/// Parses a single byte range, ignoring headers that are invalid or request several ranges.
+fn parse_range(header: &str, len: usize) -> Option<Result<Range<usize>, ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        let suffix: usize = end.parse().ok()?;
+        len.saturating_sub(suffix)..len
+    } else {
+        let start: usize = start.parse().ok()?;
+        let end = match end {
+            "" => len,
+            end => {
+                let end: usize = end.parse().ok()?;
+                if end < start {
+                    return None;
+                }
+                end.saturating_add(1).min(len)
+            }
+        };
+        start..end
+    };
+
+    if range.start >= range.end {
+        Some(Err(()))
+    } else {
+        Some(Ok(range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn asset(bytes: Vec<u8>, mime_type: &str) -> Option<Asset> {
+        Some(Asset {
+            bytes,
+            mime_type: mime_type.into(),
+            csp_header: None,
+        })
+    }
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> Request<()> {
+        let mut request = Request::builder().method(method).uri("/index.js");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(()).unwrap()
+    }
+
+    fn script() -> Vec<u8> {
+        "console.log('hello world');\n".repeat(100).into_bytes()
+    }
+
+    /// Responds without caching, so every request sees the asset it passes.
+    fn respond<B>(request: &Request<B>, asset: Option<Asset>) -> Response<Vec<u8>> {
+        super::respond(request, asset, &AssetCache::new(false))
+    }
+
+    #[test]
+    fn responds_to_misses_and_unsupported_methods() {
+        let response = respond(&request(Method::GET, &[]), None);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = respond(
+            &request(Method::POST, &[]),
+            asset(script(), "text/javascript"),
+        );
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET, HEAD");
+    }
+
+    #[test]
+    fn head_keeps_the_headers() {
+        let get = respond(
+            &request(Method::GET, &[]),
+            asset(script(), "text/javascript"),
+        );
+        let head = respond(
+            &request(Method::HEAD, &[]),
+            asset(script(), "text/javascript"),
+        );
+        assert_eq!(head.status(), StatusCode::OK);
+        assert_eq!(head.headers(), get.headers());
+        assert_eq!(head.headers()[CONTENT_LENGTH], script().len().to_string());
+        assert!(head.body().is_empty());
+    }
+
+    #[test]
+    fn serves_byte_ranges() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let get = |range: &str| {
+            respond(
+                &request(Method::GET, &[("range", range)]),
+                asset(bytes.clone(), "video/mp4"),
+            )
+        };
+
+        let response = get("bytes=10-19");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 10-19/256");
+        assert_eq!(response.body(), &bytes[10..20]);
+
+        assert_eq!(get("bytes=250-").body(), &bytes[250..]);
+        assert_eq!(get("bytes=-6").body(), &bytes[250..]);
+        assert_eq!(get("bytes=200-999").body(), &bytes[200..]);
+
+        let response = get("bytes=256-");
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes */256");
+
+        // multiple ranges are not supported, so the whole asset is sent
+        let response = get("bytes=0-1,5-6");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &bytes);
+    }
+
+    #[test]
+    fn revalidates_with_etag() {
+        let response = respond(
+            &request(Method::GET, &[]),
+            asset(script(), "text/javascript"),
+        );
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+
+        let response = respond(
+            &request(
+                Method::GET,
+                &[("if-none-match", &format!("\"other\", {etag}"))],
+            ),
+            asset(script(), "text/javascript"),
+        );
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.body().is_empty());
+
+        let response = respond(
+            &request(Method::GET, &[("if-none-match", &etag)]),
+            asset(b"changed".to_vec(), "text/javascript"),
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn negotiates_compression() {
+        let response = respond(
+            &request(Method::GET, &[("accept-encoding", "gzip, br;q=0.5")]),
+            asset(script(), "text/javascript"),
+        );
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[VARY], "Accept-Encoding");
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(response.body().as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, script());
+
+        let response = respond(
+            &request(Method::GET, &[("accept-encoding", "gzip, deflate, br")]),
+            asset(script(), "text/javascript"),
+        );
+        assert_eq!(response.headers()[CONTENT_ENCODING], "br");
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(response.body().as_slice(), 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, script());
+
+        let response = respond(
+            &request(Method::GET, &[("accept-encoding", "br;q=0, gzip;q=0")]),
+            asset(script(), "text/javascript"),
+        );
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+
+        let response = respond(
+            &request(Method::GET, &[("accept-encoding", "br")]),
+            asset(vec![0; 4096], "image/png"),
+        );
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    }
+
+    #[test]
+    fn caches_etags_and_encoded_bodies() {
+        let cache = AssetCache::new(true);
+        let get = |bytes: Vec<u8>| {
+            super::respond(
+                &request(Method::GET, &[("accept-encoding", "gzip")]),
+                asset(bytes, "text/javascript"),
+                &cache,
+            )
+        };
+
+        let first = get(script());
+        // the cached tag and body of the path are reused
+        let second = get(script().into_iter().rev().collect());
+        assert_eq!(second.headers()[ETAG], first.headers()[ETAG]);
+        assert_eq!(second.body(), first.body());
+
+        let mut request = request(Method::GET, &[("accept-encoding", "gzip")]);
+        *request.uri_mut() = "/other.js".parse().unwrap();
+        let other = super::respond(
+            &request,
+            asset(script().into_iter().rev().collect(), "text/javascript"),
+            &cache,
+        );
+        assert_ne!(other.headers()[ETAG], first.headers()[ETAG]);
+    }
+}
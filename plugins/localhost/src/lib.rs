@@ -11,14 +11,105 @@
     html_favicon_url = "https://github.com/tauri-apps/tauri/raw/dev/app-icon.png"
 )]
 
-use std::collections::HashMap;
+use std::{
+    io::Read,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
 
-use http::Uri;
+use http::{header::HeaderName, HeaderMap, HeaderValue, Method, Uri};
 use tauri::{
     plugin::{Builder as PluginBuilder, TauriPlugin},
-    Runtime,
+    AssetResolver, Manager, RunEvent, Runtime,
 };
-use tiny_http::{Header, Response as HttpResponse, Server};
+use tiny_http::{Header, Response as HttpResponse, Server, StatusCode};
+
+mod access;
+mod asset;
+mod route;
+use access::{Access, AccessControl};
+use asset::{Asset, AssetCache};
+pub use route::Params;
+use route::Route;
+
+const DEFAULT_WORKERS: usize = 4;
+const DEFAULT_MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error("failed to start the localhost server on {address}: {source}")]
+    Bind {
+        address: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("the localhost server is not listening on a TCP address")]
+    NotTcp,
+}
+
+/// The running server, available in the app state once the plugin is initialized.
+///
+/// # Examples
+///
+/// ```
+/// use tauri::Manager;
+/// use tauri_plugin_localhost::LocalhostServer;
+///
+/// tauri::Builder::default()
+///   .plugin(tauri_plugin_localhost::Builder::new(0).build())
+///   .setup(|app| {
+///     let port = app.state::<LocalhostServer>().port();
+///     println!("serving on http://localhost:{port}");
+///     Ok(())
+///   });
+/// ```
+pub struct LocalhostServer {
+    address: SocketAddr,
+    token: Option<String>,
+    server: Arc<Server>,
+    shutting_down: Arc<AtomicBool>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl LocalhostServer {
+    /// The address the server is bound to.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The port the server is bound to, useful when the plugin was built with port `0`.
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// The secret generated for this launch when the plugin was built with [`Builder::require_token`].
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// The URL of the app root on `host`, including the token if one is required.
+    pub fn url(&self, host: &str) -> String {
+        match &self.token {
+            Some(token) => format!("http://{host}:{}/?token={token}", self.port()),
+            None => format!("http://{host}:{}/", self.port()),
+        }
+    }
+
+    /// Stops accepting requests and waits for the ones being handled.
+    fn shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        let workers = std::mem::take(&mut *self.workers.lock().unwrap());
+        for _ in &workers {
+            self.server.unblock();
+        }
+        for worker in workers {
+            let _ = worker.join();
+        }
+    }
+}
 
 pub struct Request {
     url: String,
@@ -31,12 +122,23 @@ impl Request {
 }
 
 pub struct Response {
-    headers: HashMap<String, String>,
+    headers: HeaderMap,
 }
 
 impl Response {
+    /// Sets a header, replacing its current values. Invalid header names and values are ignored.
     pub fn add_header<H: Into<String>, V: Into<String>>(&mut self, header: H, value: V) {
-        self.headers.insert(header.into(), value.into());
+        if let (Ok(header), Ok(value)) = (
+            HeaderName::try_from(header.into()),
+            HeaderValue::try_from(value.into()),
+        ) {
+            self.headers.insert(header, value);
+        }
+    }
+
+    /// The response headers, e.g. to append a value to a header sent several times like `Set-Cookie`.
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
     }
 }
 
@@ -46,14 +148,28 @@ pub struct Builder {
     port: u16,
     host: Option<String>,
     on_request: OnRequest,
+    routes: Vec<Route>,
+    workers: usize,
+    max_body_size: u64,
+    require_token: bool,
+    check_host: bool,
+    allowed_origins: Option<Vec<String>>,
 }
 
 impl Builder {
+    /// Serves the app on the given port. Use `0` to bind to a free port,
+    /// which can be read from the [`LocalhostServer`] state.
     pub fn new(port: u16) -> Self {
         Self {
             port,
             host: None,
             on_request: None,
+            routes: Vec::new(),
+            workers: DEFAULT_WORKERS,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            require_token: false,
+            check_host: false,
+            allowed_origins: None,
         }
     }
 
@@ -63,6 +179,47 @@ impl Builder {
         self
     }
 
+    /// Number of threads handling requests concurrently. Defaults to 4.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// The largest request body accepted, in bytes. Larger requests get a `413 Payload Too Large` response.
+    /// Defaults to 10 MiB.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Rejects requests without the secret generated on every launch, available with [`LocalhostServer::token`].
+    ///
+    /// The token is accepted in the `token` query parameter or a bearer `Authorization` header,
+    /// which then sets a cookie so the other requests of the page are accepted too.
+    /// Use [`LocalhostServer::url`] to open the app with it.
+    pub fn require_token(mut self) -> Self {
+        self.require_token = true;
+        self
+    }
+
+    /// Rejects requests whose `Host` header is not a loopback name or the configured [`Self::host`],
+    /// preventing DNS rebinding attacks from reading the app through another domain.
+    pub fn check_host(mut self) -> Self {
+        self.check_host = true;
+        self
+    }
+
+    /// Rejects requests sent with an `Origin` header that is not the server itself or one of `origins`,
+    /// e.g. `fetch` calls from other websites.
+    pub fn allowed_origins<I, O>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator<Item = O>,
+        O: Into<String>,
+    {
+        self.allowed_origins = Some(origins.into_iter().map(Into::into).collect());
+        self
+    }
+
     pub fn on_request<F: Fn(&Request, &mut Response) + Send + Sync + 'static>(
         mut self,
         f: F,
@@ -71,56 +228,311 @@ impl Builder {
         self
     }
 
+    /// Handles requests with the given method and path with `handler` instead of serving the app assets.
+    /// Routes registered for `GET` also handle `HEAD` requests.
+    ///
+    /// The path pattern can contain `:name` segments matching any path segment
+    /// and end with a `*name` segment matching the rest of the path.
+    /// Their values are available with the [`Params`] request extension.
+    /// Routes are checked in the order they were registered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tauri::http::{Method, Response};
+    /// use tauri_plugin_localhost::{Builder, Params};
+    ///
+    /// Builder::new(9527).route(Method::GET, "/oauth/:provider/callback", |request| {
+    ///     let provider = request.extensions().get::<Params>().and_then(|p| p.get("provider"));
+    ///     let query = request.uri().query().unwrap_or_default();
+    ///     // exchange the code in the query for a token...
+    ///     Response::new(b"You can close this window".to_vec())
+    /// });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a `*name` segment is not the last segment of `path`.
+    pub fn route<F>(mut self, method: Method, path: &str, handler: F) -> Self
+    where
+        F: Fn(http::Request<Vec<u8>>) -> http::Response<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.routes
+            .push(Route::new(method, path, Box::new(handler)));
+        self
+    }
+
     pub fn build<R: Runtime>(mut self) -> TauriPlugin<R> {
         let port = self.port;
         let host = self.host.unwrap_or("localhost".to_string());
-        let on_request = self.on_request.take();
+        let on_request = Arc::new(self.on_request.take());
+        let routes = Arc::new(self.routes);
+        let workers = self.workers;
+        let max_body_size = self.max_body_size;
+        let access = Arc::new(AccessControl {
+            token: self.require_token.then(access::generate_token),
+            hosts: self.check_host.then(|| vec![host.to_ascii_lowercase()]),
+            allowed_origins: self.allowed_origins,
+        });
 
         PluginBuilder::new("localhost")
             .setup(move |app, _api| {
-                let asset_resolver = app.asset_resolver();
-                std::thread::spawn(move || {
-                    let server =
-                        Server::http(format!("{host}:{port}")).expect("Unable to spawn server");
-                    for req in server.incoming_requests() {
-                        let path = req
-                            .url()
-                            .parse::<Uri>()
-                            .map(|uri| uri.path().into())
-                            .unwrap_or_else(|_| req.url().into());
-
-                        #[allow(unused_mut)]
-                        if let Some(mut asset) = asset_resolver.get(path) {
-                            let request = Request {
-                                url: req.url().into(),
-                            };
-                            let mut response = Response {
-                                headers: Default::default(),
-                            };
-
-                            response.add_header("Content-Type", asset.mime_type);
-                            if let Some(csp) = asset.csp_header {
-                                response
-                                    .headers
-                                    .insert("Content-Security-Policy".into(), csp);
-                            }
-
-                            if let Some(on_request) = &on_request {
-                                on_request(&request, &mut response);
-                            }
+                let address = format!("{host}:{port}");
+                let server =
+                    Server::http(&address).map_err(|source| Error::Bind { address, source })?;
+                let address = server.server_addr().to_ip().ok_or(Error::NotTcp)?;
+                let server = Arc::new(server);
+                let shutting_down = Arc::new(AtomicBool::new(false));
 
-                            let mut resp = HttpResponse::from_data(asset.bytes);
-                            for (header, value) in response.headers {
-                                if let Ok(h) = Header::from_bytes(header.as_bytes(), value) {
-                                    resp.add_header(h);
+                let cache = Arc::new(AssetCache::new(!tauri::is_dev()));
+                let workers = (0..workers)
+                    .map(|i| {
+                        let server = server.clone();
+                        let shutting_down = shutting_down.clone();
+                        let asset_resolver = app.asset_resolver();
+                        let cache = cache.clone();
+                        let routes = routes.clone();
+                        let on_request = on_request.clone();
+                        let access = access.clone();
+                        let port = address.port();
+                        std::thread::Builder::new()
+                            .name(format!("localhost-worker-{i}"))
+                            .spawn(move || loop {
+                                match server.recv() {
+                                    Ok(req) => handle_request(
+                                        req,
+                                        port,
+                                        &access,
+                                        max_body_size,
+                                        &asset_resolver,
+                                        &cache,
+                                        &routes,
+                                        &on_request,
+                                    ),
+                                    // `recv` fails once unblocked by the shutdown
+                                    Err(_) if shutting_down.load(Ordering::SeqCst) => break,
+                                    Err(e) => log::error!("localhost server error: {e}"),
                                 }
-                            }
-                            req.respond(resp).expect("unable to setup response");
-                        }
-                    }
+                            })
+                    })
+                    .collect::<std::io::Result<Vec<_>>>()?;
+
+                app.manage(LocalhostServer {
+                    address,
+                    token: access.token.clone(),
+                    server,
+                    shutting_down,
+                    workers: Mutex::new(workers),
                 });
                 Ok(())
             })
+            .on_event(|app, event| {
+                if let RunEvent::Exit = event {
+                    if let Some(server) = app.try_state::<LocalhostServer>() {
+                        server.shutdown();
+                    }
+                }
+            })
             .build()
     }
 }
+
+#[allow(clippy::too_many_arguments)]
+fn handle_request<R: Runtime>(
+    mut req: tiny_http::Request,
+    port: u16,
+    access: &AccessControl,
+    max_body_size: u64,
+    asset_resolver: &AssetResolver<R>,
+    cache: &AssetCache,
+    routes: &[Route],
+    on_request: &OnRequest,
+) {
+    let path = req
+        .url()
+        .parse::<Uri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|_| req.url().into());
+
+    let mut http_request = http::Request::builder()
+        .method(req.method().as_str())
+        .uri(req.url());
+    for header in req.headers() {
+        http_request = http_request.header(header.field.as_str().as_str(), header.value.as_str());
+    }
+    // the access is checked before reading the body, which could be large
+    let http_response = match http_request.body(()) {
+        Ok(http_request) => match access.check(&http_request, port) {
+            Some(access_kind) => {
+                let body_length = req.body_length();
+                match read_body(req.as_reader(), body_length, max_body_size) {
+                    Ok(body) => {
+                        let http_request = http_request.map(|()| body);
+                        let route = routes.iter().find_map(|route| {
+                            route
+                                .matches(http_request.method(), &path)
+                                .map(|params| (route, params))
+                        });
+                        let mut response = match route {
+                            Some((route, params)) => route.handle(http_request, params),
+                            None => {
+                                let asset = asset_resolver.get(path).map(|asset| Asset {
+                                    bytes: asset.bytes,
+                                    mime_type: asset.mime_type,
+                                    csp_header: asset.csp_header,
+                                });
+                                asset::respond(&http_request, asset, cache)
+                            }
+                        };
+                        if let (Access::AllowedWithToken, Some(token)) =
+                            (access_kind, &access.token)
+                        {
+                            let cookie = format!(
+                                "{}={token}; Path=/; HttpOnly; SameSite=Strict",
+                                access::TOKEN_COOKIE
+                            );
+                            if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+                                response
+                                    .headers_mut()
+                                    .append(http::header::SET_COOKIE, cookie);
+                            }
+                        }
+                        response
+                    }
+                    Err(status) => status_response(status),
+                }
+            }
+            None => status_response(http::StatusCode::FORBIDDEN),
+        },
+        Err(_) => status_response(http::StatusCode::BAD_REQUEST),
+    };
+
+    let (parts, body) = http_response.into_parts();
+    let request = Request {
+        url: req.url().into(),
+    };
+    let mut response = Response {
+        headers: parts.headers,
+    };
+    if let Some(on_request) = on_request {
+        on_request(&request, &mut response);
+    }
+
+    let mut resp = HttpResponse::from_data(body)
+        .with_status_code(StatusCode(parts.status.as_u16()))
+        // the length is always known, so `Content-Length` is sent even to `HEAD` requests
+        .with_chunked_threshold(usize::MAX);
+    for header in tiny_http_headers(&response.headers) {
+        resp.add_header(header);
+    }
+    if let Err(e) = req.respond(resp) {
+        log::error!("failed to respond to localhost request: {e}");
+    }
+}
+
+/// Reads a request body of at most `limit` bytes.
+fn read_body(
+    reader: impl Read,
+    length: Option<usize>,
+    limit: u64,
+) -> Result<Vec<u8>, http::StatusCode> {
+    if length.is_some_and(|length| length as u64 > limit) {
+        return Err(http::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let mut body = Vec::new();
+    // one more byte than the limit tells bodies without a known length that are too large
+    match reader.take(limit.saturating_add(1)).read_to_end(&mut body) {
+        Ok(_) if body.len() as u64 > limit => Err(http::StatusCode::PAYLOAD_TOO_LARGE),
+        Ok(_) => Ok(body),
+        Err(e) => {
+            log::error!("failed to read localhost request body: {e}");
+            Err(http::StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+/// Converts the headers of a response, keeping every value of the headers sent several times.
+///
+/// `tiny_http` only supports ASCII header values, others are skipped.
+fn tiny_http_headers(headers: &HeaderMap) -> Vec<Header> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let header = Header::from_bytes(name.as_str(), value.as_bytes()).ok();
+            if header.is_none() {
+                log::warn!(
+                    "skipping the `{name}` localhost response header with a non-ASCII value"
+                );
+            }
+            header
+        })
+        .collect()
+}
+
+fn status_response(status: http::StatusCode) -> http::Response<Vec<u8>> {
+    let mut response = http::Response::new(Vec::new());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_the_body_size() {
+        let body = b"hello world".as_slice();
+        assert_eq!(read_body(body, Some(11), 11), Ok(body.to_vec()));
+        assert_eq!(read_body(body, None, 11), Ok(body.to_vec()));
+        assert_eq!(
+            read_body(body, Some(11), 10),
+            Err(http::StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        // the declared length can't be trusted
+        assert_eq!(
+            read_body(body, Some(4), 10),
+            Err(http::StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        assert_eq!(
+            read_body(body, None, 10),
+            Err(http::StatusCode::PAYLOAD_TOO_LARGE)
+        );
+    }
+
+    #[test]
+    fn keeps_every_header_value() {
+        let mut response = Response {
+            headers: HeaderMap::new(),
+        };
+        response
+            .headers_mut()
+            .append(http::header::SET_COOKIE, HeaderValue::from_static("a=1"));
+        response
+            .headers_mut()
+            .append(http::header::SET_COOKIE, HeaderValue::from_static("b=2"));
+        response.add_header("content-type", "text/plain");
+        response.add_header("Content-Type", "text/html");
+        response.headers_mut().append(
+            "x-name",
+            HeaderValue::from_bytes("caf\u{e9}".as_bytes()).unwrap(),
+        );
+
+        let headers = tiny_http_headers(&response.headers)
+            .into_iter()
+            .map(|h| {
+                (
+                    h.field.to_string().to_ascii_lowercase(),
+                    h.value.to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headers,
+            [
+                ("set-cookie".to_string(), "a=1".to_string()),
+                ("set-cookie".to_string(), "b=2".to_string()),
+                ("content-type".to_string(), "text/html".to_string()),
+            ]
+        );
+    }
+}
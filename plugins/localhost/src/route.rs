@@ -0,0 +1,167 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Rust handlers for requests matching a method and a path pattern.
+
+use std::collections::HashMap;
+
+use http::{Method, Request, Response};
+
+pub(crate) type Handler = Box<dyn Fn(Request<Vec<u8>>) -> Response<Vec<u8>> + Send + Sync>;
+
+/// Values of the `:name` and `*name` segments of the route pattern,
+/// available in the extensions of the requests passed to the route handlers.
+#[derive(Debug, Clone, Default)]
+pub struct Params(HashMap<String, String>);
+
+impl Params {
+    /// The value of the `name` segment.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    /// Matches the rest of the path.
+    Wildcard(String),
+}
+
+pub(crate) struct Route {
+    method: Method,
+    segments: Vec<Segment>,
+    handler: Handler,
+}
+
+impl Route {
+    /// # Panics
+    ///
+    /// Panics if a wildcard is not the last segment of `pattern`.
+    pub fn new(method: Method, pattern: &str, handler: Handler) -> Self {
+        let segments: Vec<Segment> = pattern
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.into())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    Segment::Wildcard(name.into())
+                } else {
+                    Segment::Literal(segment.into())
+                }
+            })
+            .collect();
+        if let Some(position) = segments
+            .iter()
+            .position(|segment| matches!(segment, Segment::Wildcard(_)))
+        {
+            assert!(
+                position == segments.len() - 1,
+                "wildcards must be the last segment of the route pattern `{pattern}`"
+            );
+        }
+
+        Self {
+            method,
+            segments,
+            handler,
+        }
+    }
+
+    /// Returns the route parameters if the route handles requests to `path` with `method`.
+    pub fn matches(&self, method: &Method, path: &str) -> Option<Params> {
+        if method != self.method && !(self.method == Method::GET && method == Method::HEAD) {
+            return None;
+        }
+
+        let mut params = HashMap::new();
+        let mut path_segments = path.split('/').filter(|segment| !segment.is_empty());
+        for segment in &self.segments {
+            match segment {
+                Segment::Wildcard(name) => {
+                    let rest = path_segments.by_ref().collect::<Vec<_>>().join("/");
+                    if !name.is_empty() {
+                        params.insert(name.clone(), rest);
+                    }
+                    return Some(Params(params));
+                }
+                Segment::Literal(literal) => {
+                    if path_segments.next() != Some(literal.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), path_segments.next()?.to_string());
+                }
+            }
+        }
+
+        path_segments.next().is_none().then_some(Params(params))
+    }
+
+    pub fn handle(&self, mut request: Request<Vec<u8>>, params: Params) -> Response<Vec<u8>> {
+        request.extensions_mut().insert(params);
+        (self.handler)(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(method: Method, pattern: &str) -> Route {
+        Route::new(method, pattern, Box::new(|_| Response::new(Vec::new())))
+    }
+
+    #[test]
+    fn matches_literal_and_param_segments() {
+        let route = route(Method::GET, "/api/users/:id");
+        let params = route.matches(&Method::GET, "/api/users/42").unwrap();
+        assert_eq!(params.get("id"), Some("42"));
+        assert!(route.matches(&Method::HEAD, "/api/users/42").is_some());
+        assert!(route.matches(&Method::POST, "/api/users/42").is_none());
+        assert!(route.matches(&Method::GET, "/api/users").is_none());
+        assert!(route.matches(&Method::GET, "/api/users/42/posts").is_none());
+        assert!(route.matches(&Method::GET, "/api/groups/42").is_none());
+    }
+
+    #[test]
+    fn wildcard_matches_the_rest_of_the_path() {
+        let route = route(Method::POST, "/files/*path");
+        let params = route.matches(&Method::POST, "/files/a/b.txt").unwrap();
+        assert_eq!(params.get("path"), Some("a/b.txt"));
+        assert_eq!(
+            route.matches(&Method::POST, "/files").unwrap().get("path"),
+            Some("")
+        );
+        assert!(route.matches(&Method::POST, "/other/a").is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn wildcard_must_be_last() {
+        route(Method::GET, "/*path/edit");
+    }
+
+    #[test]
+    fn handler_receives_params() {
+        let route = Route::new(
+            Method::GET,
+            "/callback/:provider",
+            Box::new(|request| {
+                let provider = request
+                    .extensions()
+                    .get::<Params>()
+                    .unwrap()
+                    .get("provider");
+                Response::new(provider.unwrap_or_default().as_bytes().to_vec())
+            }),
+        );
+        let params = route.matches(&Method::GET, "/callback/github").unwrap();
+        let response = route.handle(Request::new(Vec::new()), params);
+        assert_eq!(response.body(), b"github");
+    }
+}
@@ -6,6 +6,7 @@ const COMMANDS: &[&str] = &[
     "load",
     "get_store",
     "set",
+    "set_with_ttl",
     "get",
     "has",
     "delete",
@@ -17,6 +18,11 @@ const COMMANDS: &[&str] = &[
     "length",
     "reload",
     "save",
+    "get_path",
+    "set_path",
+    "delete_path",
+    "merge_patch",
+    "batch",
 ];
 
 fn main() {
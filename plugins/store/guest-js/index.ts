@@ -12,6 +12,53 @@ interface ChangePayload<T> {
   key: string
   value: T
   exists: boolean
+  changed: string[]
+}
+
+interface BatchChangePayload<T> {
+  path: string
+  resourceId?: number
+  changes: Array<Omit<ChangePayload<T>, 'path' | 'resourceId'>>
+}
+
+/**
+ * A change applied by {@linkcode IStore.batch}.
+ */
+export type BatchOperation =
+  | { type: 'set'; key: string; value: unknown }
+  | { type: 'delete'; key: string }
+  | { type: 'clear' }
+  | { type: 'setPath'; path: string; value: unknown }
+  | { type: 'deletePath'; path: string }
+  | { type: 'mergePatch'; patch: Record<string, unknown> }
+
+/**
+ * Calls `cb` for every key changed in the store `rid`, by single changes and batches.
+ */
+async function listenToChanges<T>(
+  rid: number,
+  cb: (change: Omit<ChangePayload<T>, 'path' | 'resourceId'>) => void
+): Promise<UnlistenFn> {
+  const unlistenChange = await listen<ChangePayload<T>>(
+    'store://change',
+    (event) => {
+      if (event.payload.resourceId === rid) {
+        cb(event.payload)
+      }
+    }
+  )
+  const unlistenBatch = await listen<BatchChangePayload<T>>(
+    'store://batch-change',
+    (event) => {
+      if (event.payload.resourceId === rid) {
+        event.payload.changes.forEach(cb)
+      }
+    }
+  )
+  return () => {
+    unlistenChange()
+    unlistenBatch()
+  }
 }
 
 /**
@@ -34,6 +81,15 @@ export type StoreOptions = {
    * Force create a new store with default values even if it already exists.
    */
   createNew?: boolean
+  /**
+   * Number of previous versions of the store file to keep, loaded when the store file can't be. Defaults to `0`.
+   */
+  backups?: number
+  /**
+   * Format to save the store in, overriding the one of the path extension, e.g. `.toml`.
+   * Formats other than `json` must be enabled with the feature of the same name on the rust side.
+   */
+  format?: 'json' | 'toml' | 'yaml' | 'msgpack' | 'cbor'
 }
 
 /**
@@ -109,6 +165,10 @@ export class LazyStore implements IStore {
     return (await this.store).set(key, value)
   }
 
+  async setWithTtl(key: string, value: unknown, ttl: number): Promise<void> {
+    return (await this.store).setWithTtl(key, value, ttl)
+  }
+
   async get<T>(key: string): Promise<T | undefined> {
     return (await this.store).get<T>(key)
   }
@@ -117,6 +177,26 @@ export class LazyStore implements IStore {
     return (await this.store).has(key)
   }
 
+  async getPath<T>(path: string): Promise<T | undefined> {
+    return (await this.store).getPath<T>(path)
+  }
+
+  async setPath(path: string, value: unknown): Promise<void> {
+    return (await this.store).setPath(path, value)
+  }
+
+  async deletePath(path: string): Promise<boolean> {
+    return (await this.store).deletePath(path)
+  }
+
+  async mergePatch(patch: Record<string, unknown>): Promise<void> {
+    return (await this.store).mergePatch(patch)
+  }
+
+  async batch(operations: BatchOperation[]): Promise<void> {
+    return (await this.store).batch(operations)
+  }
+
   async delete(key: string): Promise<boolean> {
     return (await this.store).delete(key)
   }
@@ -161,7 +241,7 @@ export class LazyStore implements IStore {
   }
 
   async onChange<T>(
-    cb: (key: string, value: T | undefined) => void
+    cb: (key: string, value: T | undefined, changed: string[]) => void
   ): Promise<UnlistenFn> {
     return (await this.store).onChange<T>(cb)
   }
@@ -234,6 +314,15 @@ export class Store extends Resource implements IStore {
     })
   }
 
+  async setWithTtl(key: string, value: unknown, ttl: number): Promise<void> {
+    await invoke('plugin:store|set_with_ttl', {
+      rid: this.rid,
+      key,
+      value,
+      ttl
+    })
+  }
+
   async get<T>(key: string): Promise<T | undefined> {
     const [value, exists] = await invoke<[T, boolean]>('plugin:store|get', {
       rid: this.rid,
@@ -249,6 +338,46 @@ export class Store extends Resource implements IStore {
     })
   }
 
+  async getPath<T>(path: string): Promise<T | undefined> {
+    const [value, exists] = await invoke<[T, boolean]>(
+      'plugin:store|get_path',
+      {
+        rid: this.rid,
+        path
+      }
+    )
+    return exists ? value : undefined
+  }
+
+  async setPath(path: string, value: unknown): Promise<void> {
+    await invoke('plugin:store|set_path', {
+      rid: this.rid,
+      path,
+      value
+    })
+  }
+
+  async deletePath(path: string): Promise<boolean> {
+    return await invoke('plugin:store|delete_path', {
+      rid: this.rid,
+      path
+    })
+  }
+
+  async mergePatch(patch: Record<string, unknown>): Promise<void> {
+    await invoke('plugin:store|merge_patch', {
+      rid: this.rid,
+      patch
+    })
+  }
+
+  async batch(operations: BatchOperation[]): Promise<void> {
+    await invoke('plugin:store|batch', {
+      rid: this.rid,
+      operations
+    })
+  }
+
   async delete(key: string): Promise<boolean> {
     return await invoke('plugin:store|delete', {
       rid: this.rid,
@@ -292,23 +421,18 @@ export class Store extends Resource implements IStore {
     key: string,
     cb: (value: T | undefined) => void
   ): Promise<UnlistenFn> {
-    return await listen<ChangePayload<T>>('store://change', (event) => {
-      if (event.payload.resourceId === this.rid && event.payload.key === key) {
-        cb(event.payload.exists ? event.payload.value : undefined)
+    return await listenToChanges<T>(this.rid, (change) => {
+      if (change.key === key) {
+        cb(change.exists ? change.value : undefined)
       }
     })
   }
 
   async onChange<T>(
-    cb: (key: string, value: T | undefined) => void
+    cb: (key: string, value: T | undefined, changed: string[]) => void
   ): Promise<UnlistenFn> {
-    return await listen<ChangePayload<T>>('store://change', (event) => {
-      if (event.payload.resourceId === this.rid) {
-        cb(
-          event.payload.key,
-          event.payload.exists ? event.payload.value : undefined
-        )
-      }
+    return await listenToChanges<T>(this.rid, (change) => {
+      cb(change.key, change.exists ? change.value : undefined, change.changed)
     })
   }
 }
@@ -323,6 +447,23 @@ interface IStore {
    */
   set(key: string, value: unknown): Promise<void>
 
+  /**
+   * Inserts a key-value pair into the store, deleted once `ttl` milliseconds have elapsed.
+   *
+   * Expired keys are hidden right away and deleted in the background, notifying change listeners.
+   *
+   * @example
+   * ```typescript
+   * await store.setWithTtl('weather', response, 10 * 60 * 1000)
+   * ```
+   *
+   * @param key
+   * @param value
+   * @param ttl Time to live in milliseconds.
+   * @returns
+   */
+  setWithTtl(key: string, value: unknown, ttl: number): Promise<void>
+
   /**
    * Returns the value for the given `key` or `undefined` if the key does not exist.
    *
@@ -339,6 +480,65 @@ interface IStore {
    */
   has(key: string): Promise<boolean>
 
+  /**
+   * Returns the value at the JSON Pointer `path`, whose first token is the store key,
+   * or `undefined` if it does not exist.
+   *
+   * @example
+   * ```typescript
+   * const theme = await store.getPath<string>('/appearance/theme')
+   * ```
+   *
+   * @param path
+   * @returns
+   */
+  getPath<T>(path: string): Promise<T | undefined>
+
+  /**
+   * Sets the value at the JSON Pointer `path`, whose first token is the store key,
+   * creating the missing objects on the way. `-` appends to arrays.
+   *
+   * @param path
+   * @param value
+   * @returns
+   */
+  setPath(path: string, value: unknown): Promise<void>
+
+  /**
+   * Removes the value at the JSON Pointer `path`, whose first token is the store key.
+   *
+   * @param path
+   * @returns
+   */
+  deletePath(path: string): Promise<boolean>
+
+  /**
+   * Applies a JSON Merge Patch (RFC 7386) to the store: the patch fields are the store keys,
+   * nested objects are merged and `null` removes a value.
+   *
+   * @param patch
+   * @returns
+   */
+  mergePatch(patch: Record<string, unknown>): Promise<void>
+
+  /**
+   * Applies the operations as a unit: either all of them are applied, or none if one fails.
+   * Listeners are notified once all of them are applied.
+   *
+   * @example
+   * ```typescript
+   * await store.batch([
+   *   { type: 'set', key: 'theme', value: 'dark' },
+   *   { type: 'setPath', path: '/window/width', value: 800 },
+   *   { type: 'delete', key: 'legacyTheme' }
+   * ])
+   * ```
+   *
+   * @param operations
+   * @returns
+   */
+  batch(operations: BatchOperation[]): Promise<void>
+
   /**
    * Removes a key-value pair from the store.
    *
@@ -422,13 +622,16 @@ interface IStore {
 
   /**
    * Listen to changes on the store.
+   *
+   * The callback receives the changed key, its new value,
+   * and the JSON Pointers to the values that changed in it, e.g. `['/appearance/theme']`.
    * @param cb
    * @returns A promise resolving to a function to unlisten to the event.
    *
    * @since 2.0.0
    */
   onChange<T>(
-    cb: (key: string, value: T | undefined) => void
+    cb: (key: string, value: T | undefined, changed: string[]) => void
   ): Promise<UnlistenFn>
 
   /**
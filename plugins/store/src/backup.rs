@@ -0,0 +1,137 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Previous versions of the store file, kept next to it with a `.bak.<n>` extension,
+//! see [`crate::StoreBuilder::backups`].
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The path of the backup `index` of the store at `path`, `1` being the most recent.
+pub(crate) fn path(path: &Path, index: usize) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(format!(".bak.{index}"));
+    path.with_file_name(file_name)
+}
+
+/// Copies the store file to the first backup, shifting the previous ones and dropping the oldest.
+pub(crate) fn rotate(store_path: &Path, count: usize) -> io::Result<()> {
+    if count == 0 || !store_path.exists() {
+        return Ok(());
+    }
+    for i in (1..count).rev() {
+        let backup = path(store_path, i);
+        if backup.exists() {
+            fs::rename(&backup, path(store_path, i + 1))?;
+        }
+    }
+    fs::copy(store_path, path(store_path, 1))?;
+    Ok(())
+}
+
+/// Loads the store file with `load`, or the most recent of the `count` backups it succeeds with.
+///
+/// Returns the backup loaded and the error of the store file, or that error if no backup could be loaded.
+pub(crate) fn load<E>(
+    store_path: &Path,
+    count: usize,
+    mut load: impl FnMut(&Path) -> Result<(), E>,
+) -> Result<Option<(PathBuf, E)>, E> {
+    let error = match load(store_path) {
+        Ok(()) => return Ok(None),
+        Err(error) => error,
+    };
+    for i in 1..=count {
+        let backup = path(store_path, i);
+        if load(&backup).is_ok() {
+            return Ok(Some((backup, error)));
+        }
+    }
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "tauri-plugin-store-backup-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: &Path) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+
+    fn load_valid(path: &Path) -> Result<(), String> {
+        match read(path) {
+            Some(contents) if contents.starts_with("valid") => Ok(()),
+            Some(contents) => Err(contents),
+            None => Err("missing".into()),
+        }
+    }
+
+    #[test]
+    fn rotates_the_newest_backups() {
+        let store = test_dir("rotate").join("store.json");
+        // nothing to back up yet
+        rotate(&store, 3).unwrap();
+        assert!(!path(&store, 1).exists());
+
+        for version in 1..=5 {
+            fs::write(&store, format!("v{version}")).unwrap();
+            rotate(&store, 3).unwrap();
+        }
+        assert_eq!(read(&path(&store, 1)).as_deref(), Some("v5"));
+        assert_eq!(read(&path(&store, 2)).as_deref(), Some("v4"));
+        assert_eq!(read(&path(&store, 3)).as_deref(), Some("v3"));
+        assert!(!path(&store, 4).exists());
+        assert_eq!(path(&store, 2).file_name().unwrap(), "store.json.bak.2");
+
+        rotate(&store, 0).unwrap();
+        assert_eq!(read(&path(&store, 1)).as_deref(), Some("v5"));
+    }
+
+    #[test]
+    fn falls_back_to_the_newest_valid_backup() {
+        let store = test_dir("fallback").join("store.json");
+        fs::write(&store, "corrupt").unwrap();
+        fs::write(path(&store, 1), "corrupt too").unwrap();
+        fs::write(path(&store, 2), "valid 2").unwrap();
+        fs::write(path(&store, 3), "valid 3").unwrap();
+
+        let mut loaded = Vec::new();
+        let backup = load(&store, 3, |path| {
+            loaded.push(path.to_path_buf());
+            load_valid(path)
+        })
+        .unwrap();
+        assert_eq!(backup, Some((path(&store, 2), "corrupt".to_string())));
+        assert_eq!(
+            loaded,
+            vec![store.clone(), path(&store, 1), path(&store, 2)]
+        );
+
+        fs::write(&store, "valid").unwrap();
+        assert_eq!(load(&store, 3, load_valid), Ok(None));
+    }
+
+    #[test]
+    fn fails_without_a_valid_backup() {
+        let store = test_dir("corrupt").join("store.json");
+        fs::write(&store, "corrupt").unwrap();
+        assert_eq!(load(&store, 0, load_valid), Err("corrupt".to_string()));
+
+        fs::write(path(&store, 1), "corrupt too").unwrap();
+        assert_eq!(load(&store, 2, load_valid), Err("corrupt".to_string()));
+    }
+}
@@ -0,0 +1,315 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Encryption at rest with XChaCha20-Poly1305.
+//!
+//! An encrypted store file is made of a header, a random nonce and the ciphertext
+//! of the serialized store. The header is authenticated too, so any modification of the file
+//! makes it fail to decrypt.
+//!
+//! ```text
+//! magic (4 bytes) | key kind (1 byte) | salt (16 bytes, passphrases only) | nonce (24 bytes) | ciphertext
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use zeroize::Zeroizing;
+
+use crate::{Error, Result};
+
+/// Length of the keys passed to [`Encryption::key`].
+pub const KEY_LENGTH: usize = 32;
+
+const MAGIC: &[u8; 4] = b"TSE\x01";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+
+const KIND_KEY: u8 = 0;
+const KIND_PASSPHRASE: u8 = 1;
+
+type Key = Zeroizing<[u8; KEY_LENGTH]>;
+/// The last derived key and its salt, deriving is deliberately slow.
+type DerivedKey = Arc<Mutex<Option<([u8; SALT_LENGTH], Key)>>>;
+
+type KeyFn = dyn Fn() -> std::result::Result<[u8; KEY_LENGTH], Box<dyn std::error::Error + Send + Sync>>
+    + Send
+    + Sync;
+
+#[derive(Clone)]
+enum KeySource {
+    Key(Arc<KeyFn>),
+    Passphrase {
+        passphrase: Arc<Zeroizing<String>>,
+        derived: DerivedKey,
+    },
+}
+
+impl KeySource {
+    fn passphrase(passphrase: String) -> Self {
+        Self::Passphrase {
+            passphrase: Arc::new(Zeroizing::new(passphrase)),
+            derived: Default::default(),
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            Self::Key(_) => KIND_KEY,
+            Self::Passphrase { .. } => KIND_PASSPHRASE,
+        }
+    }
+
+    /// Returns the key, derived with `salt` for passphrases.
+    fn key(&self, salt: Option<&[u8; SALT_LENGTH]>) -> Result<Key> {
+        match self {
+            Self::Key(key) => key().map(Zeroizing::new).map_err(Error::EncryptionKey),
+            Self::Passphrase {
+                passphrase,
+                derived,
+            } => {
+                let salt = salt.expect("passphrases are derived with a salt");
+                let mut derived = derived.lock().unwrap();
+                if let Some((derived_salt, key)) = &*derived {
+                    if derived_salt == salt {
+                        return Ok(key.clone());
+                    }
+                }
+                let mut key = Zeroizing::new([0; KEY_LENGTH]);
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+                    .map_err(|e| Error::EncryptionKey(e.to_string().into()))?;
+                *derived = Some((*salt, key.clone()));
+                Ok(key)
+            }
+        }
+    }
+
+    /// The salt to encrypt with, reusing the last one so the key is not derived on every save.
+    fn salt(&self) -> [u8; SALT_LENGTH] {
+        match self {
+            Self::Passphrase { derived, .. } => match &*derived.lock().unwrap() {
+                Some((salt, _)) => *salt,
+                None => {
+                    let mut salt = [0; SALT_LENGTH];
+                    OsRng.fill_bytes(&mut salt);
+                    salt
+                }
+            },
+            Self::Key(_) => unreachable!("keys are not derived"),
+        }
+    }
+}
+
+/// Encrypts a store on disk with XChaCha20-Poly1305.
+///
+/// Stores that were modified or encrypted with an unknown key fail to load with [`Error::Decrypt`].
+///
+/// # Examples
+///
+/// ```
+/// use tauri_plugin_store::{Encryption, StoreExt};
+///
+/// tauri::Builder::default()
+///   .plugin(tauri_plugin_store::Builder::default().build())
+///   .setup(|app| {
+///     let store = app
+///       .store_builder("tokens.json")
+///       // e.g. read from the OS keychain
+///       .encryption(Encryption::key(|| Ok([7; 32])))
+///       .build()?;
+///     Ok(())
+///   });
+/// ```
+#[derive(Clone)]
+pub struct Encryption {
+    /// The key to encrypt with, followed by the previous keys the store may still be encrypted with.
+    keys: Vec<KeySource>,
+}
+
+impl std::fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryption").finish_non_exhaustive()
+    }
+}
+
+impl Encryption {
+    /// Encrypts with the key returned by `key`, called on every save and load.
+    pub fn key<F>(key: F) -> Self
+    where
+        F: Fn() -> std::result::Result<[u8; KEY_LENGTH], Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            keys: vec![KeySource::Key(Arc::new(key))],
+        }
+    }
+
+    /// Encrypts with a key derived from `passphrase` with Argon2id and a random salt stored in the file.
+    pub fn passphrase(passphrase: impl Into<String>) -> Self {
+        Self {
+            keys: vec![KeySource::passphrase(passphrase.into())],
+        }
+    }
+
+    /// Decrypts stores still encrypted with a previous key, which are then saved with the current one.
+    pub fn previous_key<F>(mut self, key: F) -> Self
+    where
+        F: Fn() -> std::result::Result<[u8; KEY_LENGTH], Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.keys.push(KeySource::Key(Arc::new(key)));
+        self
+    }
+
+    /// Decrypts stores still encrypted with a previous passphrase, which are then saved with the current key.
+    pub fn previous_passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.keys.push(KeySource::passphrase(passphrase.into()));
+        self
+    }
+
+    pub(crate) fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let source = &self.keys[0];
+        let mut header = MAGIC.to_vec();
+        header.push(source.kind());
+        let key = match source {
+            KeySource::Key(_) => source.key(None)?,
+            KeySource::Passphrase { .. } => {
+                let salt = source.salt();
+                header.extend_from_slice(&salt);
+                source.key(Some(&salt))?
+            }
+        };
+
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(key.as_ref().into())
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &header,
+                },
+            )
+            .map_err(|_| Error::Encrypt)?;
+
+        let mut bytes = header;
+        bytes.extend_from_slice(&nonce);
+        bytes.extend(ciphertext);
+        Ok(bytes)
+    }
+
+    /// Returns the plaintext and whether it was encrypted with a previous key.
+    pub(crate) fn decrypt(&self, bytes: &[u8]) -> Result<(Vec<u8>, bool)> {
+        if bytes.len() < MAGIC.len() + 1 || !bytes.starts_with(MAGIC) {
+            return Err(Error::Decrypt);
+        }
+        let kind = bytes[MAGIC.len()];
+        let (header_length, salt) = match kind {
+            KIND_KEY => (MAGIC.len() + 1, None),
+            KIND_PASSPHRASE => {
+                let end = MAGIC.len() + 1 + SALT_LENGTH;
+                let salt: &[u8; SALT_LENGTH] = bytes
+                    .get(MAGIC.len() + 1..end)
+                    .and_then(|salt| salt.try_into().ok())
+                    .ok_or(Error::Decrypt)?;
+                (end, Some(salt))
+            }
+            _ => return Err(Error::Decrypt),
+        };
+        if bytes.len() < header_length + NONCE_LENGTH {
+            return Err(Error::Decrypt);
+        }
+        let (header, rest) = bytes.split_at(header_length);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+        for (i, source) in self.keys.iter().enumerate() {
+            if source.kind() != kind {
+                continue;
+            }
+            let key = source.key(salt)?;
+            if let Ok(plaintext) = XChaCha20Poly1305::new(key.as_ref().into()).decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            ) {
+                return Ok((plaintext, i != 0));
+            }
+        }
+        Err(Error::Decrypt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_with_a_key() {
+        let encryption = Encryption::key(|| Ok([1; KEY_LENGTH]));
+        let bytes = encryption.encrypt(b"{\"token\":\"secret\"}").unwrap();
+        assert!(!bytes.windows(6).any(|w| w == b"secret"));
+        assert_eq!(
+            encryption.decrypt(&bytes).unwrap(),
+            (b"{\"token\":\"secret\"}".to_vec(), false)
+        );
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let encryption = Encryption::key(|| Ok([1; KEY_LENGTH]));
+        let bytes = encryption.encrypt(b"{}").unwrap();
+        for i in 0..bytes.len() {
+            let mut tampered = bytes.clone();
+            tampered[i] ^= 1;
+            assert!(matches!(encryption.decrypt(&tampered), Err(Error::Decrypt)));
+        }
+        assert!(matches!(
+            encryption.decrypt(&bytes[..bytes.len() - 1]),
+            Err(Error::Decrypt)
+        ));
+        assert!(matches!(encryption.decrypt(b"{}"), Err(Error::Decrypt)));
+        assert!(matches!(
+            Encryption::key(|| Ok([2; KEY_LENGTH])).decrypt(&bytes),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn derives_keys_from_passphrases() {
+        let bytes = Encryption::passphrase("correct horse battery staple")
+            .encrypt(b"{}")
+            .unwrap();
+        assert_eq!(
+            Encryption::passphrase("correct horse battery staple")
+                .decrypt(&bytes)
+                .unwrap(),
+            (b"{}".to_vec(), false)
+        );
+        assert!(matches!(
+            Encryption::passphrase("wrong").decrypt(&bytes),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn decrypts_with_previous_keys() {
+        let old = Encryption::passphrase("old").encrypt(b"{}").unwrap();
+        let encryption = Encryption::key(|| Ok([2; KEY_LENGTH])).previous_passphrase("old");
+        assert_eq!(encryption.decrypt(&old).unwrap(), (b"{}".to_vec(), true));
+
+        let new = encryption.encrypt(b"{}").unwrap();
+        assert_eq!(new[MAGIC.len()], KIND_KEY);
+        assert_eq!(encryption.decrypt(&new).unwrap(), (b"{}".to_vec(), false));
+    }
+}
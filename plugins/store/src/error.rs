@@ -29,6 +29,43 @@ pub enum Error {
     /// Deserialize function not found
     #[error("Deserialize Function \"{0}\" not found")]
     DeserializeFunctionNotFound(String),
+    /// The store format is unknown or its feature is not enabled.
+    #[error("Store format \"{0}\" is unknown or not enabled")]
+    UnknownFormat(String),
+    /// The encryption key could not be retrieved or derived.
+    #[cfg(feature = "encryption")]
+    #[error("Failed to get the store encryption key. {0}")]
+    EncryptionKey(Box<dyn std::error::Error + Send + Sync>),
+    /// The store could not be encrypted.
+    #[cfg(feature = "encryption")]
+    #[error("Failed to encrypt store")]
+    Encrypt,
+    /// The store was modified or encrypted with an unknown key.
+    #[cfg(feature = "encryption")]
+    #[error("Failed to decrypt store, it was modified or encrypted with another key")]
+    Decrypt,
+    /// The store was saved by a newer version of the app.
+    #[error("Store version {found} is newer than the supported version {supported}")]
+    UnsupportedVersion { found: u64, supported: u32 },
+    /// The saved store version is not a number.
+    #[error("Invalid store version {0}")]
+    InvalidVersion(serde_json::Value),
+    /// A migration registered with [`crate::StoreBuilder::migrate`] failed.
+    #[error("Failed to migrate store from version {from}. {source}")]
+    Migration {
+        from: u32,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The store file could not be watched.
+    #[cfg(feature = "watch")]
+    #[error(transparent)]
+    Watch(#[from] notify::Error),
+    /// The JSON Pointer is invalid or designates a value that can't be set.
+    #[error("Invalid store path \"{0}\"")]
+    InvalidPath(String),
+    /// The merge patch of a store is not an object.
+    #[error("Store merge patches must be objects")]
+    InvalidMergePatch,
     /// Some Tauri API failed
     #[error(transparent)]
     Tauri(#[from] tauri::Error),
@@ -0,0 +1,156 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Expiration of the keys set with a time to live.
+
+use serde_json::Value as JsonValue;
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The key the expiration times are saved under, alongside the store values.
+pub const EXPIRES_KEY: &str = "__expires__";
+
+/// Milliseconds since the Unix epoch, so expiration times survive restarts.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+/// The time left until `at`.
+pub(crate) fn until(at: u64) -> Duration {
+    Duration::from_millis(at.saturating_sub(now()))
+}
+
+/// The expiration times of the keys, in milliseconds since the Unix epoch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Expiries(HashMap<String, u64>);
+
+impl Expiries {
+    /// Takes the expiration times saved in a `store` loaded from disk.
+    ///
+    /// Invalid times are ignored, which keeps their keys.
+    pub fn take(store: &mut HashMap<String, JsonValue>) -> Self {
+        let Some(JsonValue::Object(expiries)) = store.remove(EXPIRES_KEY) else {
+            return Self::default();
+        };
+        Self(
+            expiries
+                .into_iter()
+                .filter_map(|(key, at)| Some((key, at.as_u64()?)))
+                .collect(),
+        )
+    }
+
+    /// The value to save under [`EXPIRES_KEY`], `None` if no key expires.
+    pub fn to_value(&self) -> Option<JsonValue> {
+        if self.0.is_empty() {
+            return None;
+        }
+        Some(JsonValue::Object(
+            self.0
+                .iter()
+                .map(|(key, at)| (key.clone(), (*at).into()))
+                .collect(),
+        ))
+    }
+
+    pub fn insert(&mut self, key: String, ttl: Duration) {
+        let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        self.0.insert(key, now().saturating_add(ttl));
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.0.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    /// Drops the expiration times of the keys not in `store`.
+    #[cfg(feature = "watch")]
+    pub fn retain(&mut self, store: &HashMap<String, JsonValue>) {
+        self.0.retain(|key, _| store.contains_key(key));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn is_expired(&self, key: &str, now: u64) -> bool {
+        self.0.get(key).is_some_and(|at| *at <= now)
+    }
+
+    /// The keys expired at `now`.
+    pub fn expired(&self, now: u64) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// The earliest expiration time.
+    pub fn next(&self) -> Option<u64> {
+        self.0.values().min().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn expires_keys() {
+        let mut expiries = Expiries::default();
+        expiries.insert("token".into(), Duration::from_secs(60));
+        expiries.insert("response".into(), Duration::ZERO);
+        let now = now();
+
+        assert!(expiries.is_expired("response", now));
+        assert!(!expiries.is_expired("token", now));
+        assert!(!expiries.is_expired("theme", now));
+        assert_eq!(expiries.expired(now), vec!["response".to_string()]);
+        assert!(expiries.is_expired("token", now + 60_000));
+        assert!(expiries.next().unwrap() <= now);
+
+        expiries.remove("response");
+        assert!(expiries.expired(now).is_empty());
+        assert!(until(expiries.next().unwrap()) > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn round_trips_through_the_store() {
+        let mut expiries = Expiries::default();
+        expiries.insert("token".into(), Duration::from_secs(60));
+
+        let mut store: HashMap<String, JsonValue> =
+            serde_json::from_value(json!({ "token": "secret" })).unwrap();
+        store.insert(EXPIRES_KEY.into(), expiries.to_value().unwrap());
+        assert_eq!(Expiries::take(&mut store), expiries);
+        assert_eq!(store, HashMap::from([("token".into(), json!("secret"))]));
+
+        assert_eq!(Expiries::default().to_value(), None);
+        assert!(Expiries::take(&mut store).is_empty());
+    }
+
+    #[test]
+    fn ignores_invalid_times() {
+        let mut store: HashMap<String, JsonValue> = serde_json::from_value(json!({
+            EXPIRES_KEY: { "token": "soon", "response": 1 }
+        }))
+        .unwrap();
+        let expiries = Expiries::take(&mut store);
+        assert_eq!(expiries.expired(now()), vec!["response".to_string()]);
+        assert!(!expiries.is_expired("token", u64::MAX));
+    }
+}
@@ -0,0 +1,299 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! The file formats stores can be saved in, besides custom serialize functions.
+
+use serde_json::Value as JsonValue;
+use std::{collections::HashMap, path::Path};
+
+use crate::{DeserializeFn, SerializeFn};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A file format for stores, selected with [`crate::StoreBuilder::format`]
+/// or from the extension of the store path.
+///
+/// Formats other than JSON are enabled with the feature of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    /// Pretty-printed JSON, the default format.
+    Json,
+    /// TOML, `.toml` files.
+    ///
+    /// TOML has no `null`: keys set to `null` are left out of the file, so they are missing
+    /// once the store is reloaded, and arrays containing `null` can't be saved.
+    #[cfg(feature = "toml")]
+    Toml,
+    /// YAML, `.yaml` or `.yml` files.
+    #[cfg(feature = "yaml")]
+    Yaml,
+    /// MessagePack, `.msgpack` or `.mpk` files.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// CBOR, `.cbor` files.
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Format {
+    /// Returns the format matching the extension of `path`, if it is enabled.
+    pub fn from_extension(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(Self::Json),
+            #[cfg(feature = "toml")]
+            "toml" => Some(Self::Toml),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(Self::Yaml),
+            #[cfg(feature = "msgpack")]
+            "msgpack" | "mpk" => Some(Self::MessagePack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Returns the format named `name`, as used from JavaScript, if it is enabled.
+    ///
+    /// The names are `json`, `toml`, `yaml`, `msgpack` and `cbor`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            #[cfg(feature = "toml")]
+            "toml" => Some(Self::Toml),
+            #[cfg(feature = "yaml")]
+            "yaml" => Some(Self::Yaml),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Some(Self::MessagePack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn serialize_fn(self) -> SerializeFn {
+        match self {
+            Self::Json => json::serialize,
+            #[cfg(feature = "toml")]
+            Self::Toml => toml::serialize,
+            #[cfg(feature = "yaml")]
+            Self::Yaml => yaml::serialize,
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => msgpack::serialize,
+            #[cfg(feature = "cbor")]
+            Self::Cbor => cbor::serialize,
+        }
+    }
+
+    pub fn deserialize_fn(self) -> DeserializeFn {
+        match self {
+            Self::Json => json::deserialize,
+            #[cfg(feature = "toml")]
+            Self::Toml => toml::deserialize,
+            #[cfg(feature = "yaml")]
+            Self::Yaml => yaml::deserialize,
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => msgpack::deserialize,
+            #[cfg(feature = "cbor")]
+            Self::Cbor => cbor::deserialize,
+        }
+    }
+
+    /// Converts the contents of a store file from this format to `to`,
+    /// e.g. to keep the data of a store whose format changed.
+    pub fn convert(self, bytes: &[u8], to: Format) -> crate::Result<Vec<u8>> {
+        let store = (self.deserialize_fn())(bytes).map_err(crate::Error::Deserialize)?;
+        (to.serialize_fn())(&store).map_err(crate::Error::Serialize)
+    }
+}
+
+mod json {
+    use super::*;
+
+    pub fn serialize(store: &HashMap<String, JsonValue>) -> Result<Vec<u8>, BoxError> {
+        Ok(serde_json::to_vec_pretty(store)?)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<HashMap<String, JsonValue>, BoxError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+#[cfg(feature = "toml")]
+mod toml {
+    use super::*;
+
+    pub fn serialize(store: &HashMap<String, JsonValue>) -> Result<Vec<u8>, BoxError> {
+        let store: HashMap<&String, JsonValue> = store
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, without_nulls(value)))
+            .collect();
+        Ok(::toml::to_string_pretty(&store)?.into_bytes())
+    }
+
+    /// Removes the `null` values of the objects in `value`, which TOML can't represent.
+    fn without_nulls(value: &JsonValue) -> JsonValue {
+        match value {
+            JsonValue::Object(object) => object
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), without_nulls(value)))
+                .collect(),
+            JsonValue::Array(array) => array.iter().map(without_nulls).collect(),
+            value => value.clone(),
+        }
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<HashMap<String, JsonValue>, BoxError> {
+        Ok(::toml::from_str(std::str::from_utf8(bytes)?)?)
+    }
+}
+
+#[cfg(feature = "yaml")]
+mod yaml {
+    use super::*;
+
+    pub fn serialize(store: &HashMap<String, JsonValue>) -> Result<Vec<u8>, BoxError> {
+        Ok(serde_norway::to_string(store)?.into_bytes())
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<HashMap<String, JsonValue>, BoxError> {
+        Ok(serde_norway::from_slice(bytes)?)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+mod msgpack {
+    use super::*;
+
+    pub fn serialize(store: &HashMap<String, JsonValue>) -> Result<Vec<u8>, BoxError> {
+        Ok(rmp_serde::to_vec_named(store)?)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<HashMap<String, JsonValue>, BoxError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use super::*;
+
+    pub fn serialize(store: &HashMap<String, JsonValue>) -> Result<Vec<u8>, BoxError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(store, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<HashMap<String, JsonValue>, BoxError> {
+        Ok(ciborium::from_reader(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_store;
+    use serde_json::json;
+
+    fn sample() -> HashMap<String, JsonValue> {
+        test_store(json!({
+            "theme": "dark",
+            "volume": 0.5,
+            "launches": 42,
+            "offset": -3,
+            "muted": false,
+            "recent": ["a.txt", "b.txt"],
+            "window": { "width": 800, "size": { "height": 600 } },
+            "emoji": "🦀"
+        }))
+    }
+
+    fn formats() -> Vec<Format> {
+        vec![
+            Format::Json,
+            #[cfg(feature = "toml")]
+            Format::Toml,
+            #[cfg(feature = "yaml")]
+            Format::Yaml,
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+        ]
+    }
+
+    #[test]
+    fn round_trips() {
+        for format in formats() {
+            let bytes = (format.serialize_fn())(&sample()).unwrap();
+            assert_eq!(
+                (format.deserialize_fn())(&bytes).unwrap(),
+                sample(),
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn converts_between_formats() {
+        for from in formats() {
+            let bytes = (from.serialize_fn())(&sample()).unwrap();
+            for to in formats() {
+                let converted = from.convert(&bytes, to).unwrap();
+                assert_eq!(
+                    (to.deserialize_fn())(&converted).unwrap(),
+                    sample(),
+                    "{from:?} to {to:?}"
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn leaves_nulls_out_of_toml() {
+        let mut with_nulls = sample();
+        with_nulls.insert("removed".into(), JsonValue::Null);
+        with_nulls.insert(
+            "nested".into(),
+            json!({ "kept": 1, "removed": null, "list": [{ "removed": null }] }),
+        );
+        let bytes = (Format::Toml.serialize_fn())(&with_nulls).unwrap();
+
+        let mut expected = sample();
+        expected.insert("nested".into(), json!({ "kept": 1, "list": [{}] }));
+        assert_eq!((Format::Toml.deserialize_fn())(&bytes).unwrap(), expected);
+
+        with_nulls.insert("list".into(), json!([1, null]));
+        assert!((Format::Toml.serialize_fn())(&with_nulls).is_err());
+    }
+
+    #[test]
+    fn selects_formats() {
+        for format in formats() {
+            let name = match format {
+                Format::Json => "json",
+                #[cfg(feature = "toml")]
+                Format::Toml => "toml",
+                #[cfg(feature = "yaml")]
+                Format::Yaml => "yaml",
+                #[cfg(feature = "msgpack")]
+                Format::MessagePack => "msgpack",
+                #[cfg(feature = "cbor")]
+                Format::Cbor => "cbor",
+            };
+            assert_eq!(Format::from_name(name), Some(format));
+            assert_eq!(
+                Format::from_extension(format!("store.{}", name.to_uppercase())),
+                Some(format)
+            );
+        }
+        assert_eq!(Format::from_extension("store.bin"), None);
+        assert_eq!(Format::from_extension("store"), None);
+        assert_eq!(Format::from_name("xml"), None);
+    }
+}
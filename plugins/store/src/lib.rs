@@ -9,7 +9,10 @@
     html_favicon_url = "https://github.com/tauri-apps/tauri/raw/dev/app-icon.png"
 )]
 
+#[cfg(feature = "encryption")]
+pub use encryption::{Encryption, KEY_LENGTH};
 pub use error::{Error, Result};
+pub use format::Format;
 use serde::{Deserialize, Serialize};
 pub use serde_json::Value as JsonValue;
 use std::{
@@ -23,9 +26,28 @@ use tauri::{
     plugin::{self, TauriPlugin},
     AppHandle, Manager, ResourceId, RunEvent, Runtime, State,
 };
+pub use transaction::Transaction;
+#[cfg(feature = "watch")]
+pub use watch::ConflictPolicy;
 
+mod backup;
+#[cfg(feature = "encryption")]
+mod encryption;
 mod error;
+mod expiry;
+mod format;
+mod migration;
+mod pointer;
 mod store;
+mod transaction;
+#[cfg(feature = "watch")]
+mod watch;
+
+/// Builds store contents from a JSON object, for tests.
+#[cfg(test)]
+pub(crate) fn test_store(value: JsonValue) -> HashMap<String, JsonValue> {
+    serde_json::from_value(value).unwrap()
+}
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -35,6 +57,26 @@ struct ChangePayload<'a> {
     key: &'a str,
     value: Option<&'a JsonValue>,
     exists: bool,
+    /// JSON Pointers to the changed values, starting with the key.
+    changed: Vec<String>,
+}
+
+/// The changes committed by a transaction, emitted at once.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct BatchChangePayload<'a> {
+    path: &'a Path,
+    resource_id: Option<u32>,
+    changes: Vec<KeyChange<'a>>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct KeyChange<'a> {
+    key: &'a str,
+    value: Option<&'a JsonValue>,
+    exists: bool,
+    changed: &'a [String],
 }
 
 #[derive(Debug)]
@@ -53,6 +95,7 @@ enum AutoSave {
     Bool(bool),
 }
 
+#[allow(clippy::too_many_arguments)]
 fn builder<R: Runtime>(
     app: AppHandle<R>,
     store_state: State<'_, StoreState>,
@@ -61,6 +104,8 @@ fn builder<R: Runtime>(
     serialize_fn_name: Option<String>,
     deserialize_fn_name: Option<String>,
     create_new: bool,
+    backups: Option<usize>,
+    format: Option<String>,
 ) -> Result<StoreBuilder<R>> {
     let mut builder = app.store_builder(path);
     if let Some(auto_save) = auto_save {
@@ -75,6 +120,11 @@ fn builder<R: Runtime>(
         }
     }
 
+    if let Some(format) = format {
+        let format = Format::from_name(&format).ok_or(crate::Error::UnknownFormat(format))?;
+        builder = builder.format(format);
+    }
+
     if let Some(serialize_fn_name) = serialize_fn_name {
         let serialize_fn = store_state
             .serialize_fns
@@ -95,10 +145,15 @@ fn builder<R: Runtime>(
         builder = builder.create_new();
     }
 
+    if let Some(backups) = backups {
+        builder = builder.backups(backups);
+    }
+
     Ok(builder)
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn load<R: Runtime>(
     app: AppHandle<R>,
     store_state: State<'_, StoreState>,
@@ -107,6 +162,8 @@ async fn load<R: Runtime>(
     serialize_fn_name: Option<String>,
     deserialize_fn_name: Option<String>,
     create_new: Option<bool>,
+    backups: Option<usize>,
+    format: Option<String>,
 ) -> Result<ResourceId> {
     let builder = builder(
         app,
@@ -116,6 +173,8 @@ async fn load<R: Runtime>(
         serialize_fn_name,
         deserialize_fn_name,
         create_new.unwrap_or_default(),
+        backups,
+        format,
     )?;
     let (_, rid) = builder.build_inner()?;
     Ok(rid)
@@ -143,6 +202,19 @@ async fn set<R: Runtime>(
     Ok(())
 }
 
+#[tauri::command]
+async fn set_with_ttl<R: Runtime>(
+    app: AppHandle<R>,
+    rid: ResourceId,
+    key: String,
+    value: JsonValue,
+    ttl: u64,
+) -> Result<()> {
+    let store = app.resources_table().get::<Store<R>>(rid)?;
+    store.set_with_ttl(key, value, Duration::from_millis(ttl));
+    Ok(())
+}
+
 #[tauri::command]
 async fn get<R: Runtime>(
     app: AppHandle<R>,
@@ -155,6 +227,59 @@ async fn get<R: Runtime>(
     Ok((value, exists))
 }
 
+#[tauri::command]
+async fn get_path<R: Runtime>(
+    app: AppHandle<R>,
+    rid: ResourceId,
+    path: String,
+) -> Result<(Option<JsonValue>, bool)> {
+    let store = app.resources_table().get::<Store<R>>(rid)?;
+    let value = store.get_path(&path)?;
+    let exists = value.is_some();
+    Ok((value, exists))
+}
+
+#[tauri::command]
+async fn set_path<R: Runtime>(
+    app: AppHandle<R>,
+    rid: ResourceId,
+    path: String,
+    value: JsonValue,
+) -> Result<()> {
+    let store = app.resources_table().get::<Store<R>>(rid)?;
+    store.set_path(&path, value)
+}
+
+#[tauri::command]
+async fn delete_path<R: Runtime>(app: AppHandle<R>, rid: ResourceId, path: String) -> Result<bool> {
+    let store = app.resources_table().get::<Store<R>>(rid)?;
+    store.delete_path(&path)
+}
+
+#[tauri::command]
+async fn merge_patch<R: Runtime>(
+    app: AppHandle<R>,
+    rid: ResourceId,
+    patch: JsonValue,
+) -> Result<()> {
+    let store = app.resources_table().get::<Store<R>>(rid)?;
+    store.merge_patch(&patch)
+}
+
+#[tauri::command]
+async fn batch<R: Runtime>(
+    app: AppHandle<R>,
+    rid: ResourceId,
+    operations: Vec<transaction::Operation>,
+) -> Result<()> {
+    let store = app.resources_table().get::<Store<R>>(rid)?;
+    store.transaction(|transaction| {
+        operations
+            .into_iter()
+            .try_for_each(|operation| operation.apply(transaction))
+    })
+}
+
 #[tauri::command]
 async fn has<R: Runtime>(app: AppHandle<R>, rid: ResourceId, key: String) -> Result<bool> {
     let store = app.resources_table().get::<Store<R>>(rid)?;
@@ -304,18 +429,6 @@ impl<R: Runtime, T: Manager<R>> StoreExt<R> for T {
     }
 }
 
-fn default_serialize(
-    cache: &HashMap<String, JsonValue>,
-) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
-    Ok(serde_json::to_vec_pretty(&cache)?)
-}
-
-fn default_deserialize(
-    bytes: &[u8],
-) -> std::result::Result<HashMap<String, JsonValue>, Box<dyn std::error::Error + Send + Sync>> {
-    serde_json::from_slice(bytes).map_err(Into::into)
-}
-
 pub struct Builder {
     serialize_fns: HashMap<String, SerializeFn>,
     deserialize_fns: HashMap<String, DeserializeFn>,
@@ -328,8 +441,8 @@ impl Default for Builder {
         Self {
             serialize_fns: Default::default(),
             deserialize_fns: Default::default(),
-            default_serialize,
-            default_deserialize,
+            default_serialize: Format::Json.serialize_fn(),
+            default_deserialize: Format::Json.deserialize_fn(),
         }
     }
 }
@@ -412,8 +525,26 @@ impl Builder {
     pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
         plugin::Builder::new("store")
             .invoke_handler(tauri::generate_handler![
-                load, get_store, set, get, has, delete, clear, reset, keys, values, length,
-                entries, reload, save,
+                load,
+                get_store,
+                set,
+                set_with_ttl,
+                get,
+                has,
+                delete,
+                clear,
+                reset,
+                keys,
+                values,
+                length,
+                entries,
+                reload,
+                save,
+                get_path,
+                set_path,
+                delete_path,
+                merge_patch,
+                batch,
             ])
             .setup(move |app_handle, _api| {
                 app_handle.manage(StoreState {
@@ -0,0 +1,158 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Versioning of the store contents, migrated step by step when loaded.
+
+use serde_json::Value as JsonValue;
+use std::{collections::BTreeMap, collections::HashMap, sync::Arc};
+
+use crate::{Error, Result};
+
+/// The key the store version is saved under, alongside the store values.
+pub const VERSION_KEY: &str = "__version__";
+
+pub(crate) type MigrationFn = dyn Fn(
+        &mut HashMap<String, JsonValue>,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>
+    + Send
+    + Sync;
+
+#[derive(Clone, Default)]
+pub(crate) struct Migrations {
+    /// `None` if the store is not versioned.
+    pub version: Option<u32>,
+    /// Migrations from the version of the key to the next one.
+    pub steps: BTreeMap<u32, Arc<MigrationFn>>,
+}
+
+impl Migrations {
+    /// Migrates the `store` loaded from disk to the current version, returning whether it changed.
+    ///
+    /// Stores saved without a version are at version `0`.
+    pub fn migrate(&self, store: &mut HashMap<String, JsonValue>) -> Result<bool> {
+        let Some(version) = self.version else {
+            return Ok(false);
+        };
+        let found = match store.remove(VERSION_KEY) {
+            Some(found) => found.as_u64().ok_or(Error::InvalidVersion(found))?,
+            None => 0,
+        };
+        if found > u64::from(version) {
+            return Err(Error::UnsupportedVersion {
+                found,
+                supported: version,
+            });
+        }
+
+        // `found` fits in `u32` since it's not greater than `version`
+        let from = found as u32;
+        for (step, migration) in self.steps.range(from..version) {
+            migration(store).map_err(|source| Error::Migration {
+                from: *step,
+                source,
+            })?;
+        }
+        Ok(from < version)
+    }
+}
+
+impl std::fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Migrations")
+            .field("version", &self.version)
+            .field("steps", &self.steps.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_store;
+    use serde_json::json;
+
+    fn migrations() -> Migrations {
+        let mut steps: BTreeMap<u32, Arc<MigrationFn>> = BTreeMap::new();
+        steps.insert(
+            0,
+            Arc::new(|store| {
+                let theme = store
+                    .remove("dark")
+                    .map(|dark| if dark == true { "dark" } else { "light" });
+                store.insert("theme".into(), theme.unwrap_or("light").into());
+                Ok(())
+            }),
+        );
+        steps.insert(
+            2,
+            Arc::new(|store| {
+                let theme = store.remove("theme").ok_or("missing theme")?;
+                store.insert("appearance".into(), json!({ "theme": theme }));
+                Ok(())
+            }),
+        );
+        Migrations {
+            version: Some(3),
+            steps,
+        }
+    }
+
+    #[test]
+    fn migrates_unversioned_stores_from_the_first_version() {
+        let mut map = test_store(json!({ "dark": true, "lang": "en" }));
+        assert!(migrations().migrate(&mut map).unwrap());
+        assert_eq!(
+            map,
+            test_store(json!({ "appearance": { "theme": "dark" }, "lang": "en" }))
+        );
+    }
+
+    #[test]
+    fn runs_the_migrations_after_the_saved_version() {
+        let mut map = test_store(json!({ VERSION_KEY: 1, "theme": "light" }));
+        assert!(migrations().migrate(&mut map).unwrap());
+        assert_eq!(
+            map,
+            test_store(json!({ "appearance": { "theme": "light" } }))
+        );
+
+        let mut map = test_store(json!({ VERSION_KEY: 3, "theme": "light" }));
+        assert!(!migrations().migrate(&mut map).unwrap());
+        assert_eq!(map, test_store(json!({ "theme": "light" })));
+    }
+
+    #[test]
+    fn rejects_newer_and_invalid_versions() {
+        let mut map = test_store(json!({ VERSION_KEY: 4 }));
+        assert!(matches!(
+            migrations().migrate(&mut map),
+            Err(Error::UnsupportedVersion {
+                found: 4,
+                supported: 3
+            })
+        ));
+
+        let mut map = test_store(json!({ VERSION_KEY: "1" }));
+        assert!(matches!(
+            migrations().migrate(&mut map),
+            Err(Error::InvalidVersion(_))
+        ));
+    }
+
+    #[test]
+    fn reports_the_failed_migration() {
+        let mut map = test_store(json!({ VERSION_KEY: 2 }));
+        assert!(matches!(
+            migrations().migrate(&mut map),
+            Err(Error::Migration { from: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn ignores_unversioned_builders() {
+        let mut map = test_store(json!({ VERSION_KEY: 7 }));
+        assert!(!Migrations::default().migrate(&mut map).unwrap());
+        assert_eq!(map, test_store(json!({ VERSION_KEY: 7 })));
+    }
+}
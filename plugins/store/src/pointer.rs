@@ -0,0 +1,243 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Access to nested values with JSON Pointers (RFC 6901) and JSON Merge Patch (RFC 7386).
+//!
+//! The first token of a pointer is the store key, e.g. `/appearance/theme` is the `theme` field
+//! of the value stored under the `appearance` key.
+
+use serde_json::{Map, Value as JsonValue};
+
+use crate::{Error, Result};
+
+/// Splits `pointer` into the store key and the tokens of the path in its value.
+pub(crate) fn parse(pointer: &str) -> Result<(String, Vec<String>)> {
+    let Some(pointer) = pointer.strip_prefix('/') else {
+        return Err(Error::InvalidPath(pointer.into()));
+    };
+    let mut tokens = pointer
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"));
+    let key = tokens.next().unwrap_or_default();
+    Ok((key, tokens.collect()))
+}
+
+/// The pointer to the value stored under `key`.
+pub(crate) fn from_key(key: &str) -> String {
+    let mut pointer = String::new();
+    push(&mut pointer, key);
+    pointer
+}
+
+fn push(pointer: &mut String, token: &str) {
+    pointer.push('/');
+    pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+fn index(array: &[JsonValue], token: &str) -> Option<usize> {
+    // leading zeros are not allowed
+    if token.len() > 1 && token.starts_with('0') {
+        return None;
+    }
+    token.parse().ok().filter(|index| *index < array.len())
+}
+
+pub(crate) fn get<'a>(value: &'a JsonValue, tokens: &[String]) -> Option<&'a JsonValue> {
+    tokens.iter().try_fold(value, |value, token| match value {
+        JsonValue::Object(map) => map.get(token),
+        JsonValue::Array(array) => index(array, token).map(|index| &array[index]),
+        _ => None,
+    })
+}
+
+/// Sets the value at `tokens`, creating the missing objects on the way.
+///
+/// `-` appends to arrays. Returns `None` if a parent is neither an object nor an array,
+/// or an array index is out of bounds.
+pub(crate) fn set(value: &mut JsonValue, tokens: &[String], new_value: JsonValue) -> Option<()> {
+    let Some((token, rest)) = tokens.split_first() else {
+        *value = new_value;
+        return Some(());
+    };
+    if value.is_null() {
+        *value = JsonValue::Object(Map::new());
+    }
+    match value {
+        JsonValue::Object(map) => set(
+            map.entry(token.as_str()).or_insert(JsonValue::Null),
+            rest,
+            new_value,
+        ),
+        JsonValue::Array(array) if token == "-" => {
+            array.push(JsonValue::Null);
+            set(array.last_mut()?, rest, new_value)
+        }
+        JsonValue::Array(array) => {
+            let index = index(array, token)?;
+            set(&mut array[index], rest, new_value)
+        }
+        _ => None,
+    }
+}
+
+/// Removes the value at `tokens`, returning whether it existed.
+pub(crate) fn delete(value: &mut JsonValue, tokens: &[String]) -> bool {
+    let Some((last, parents)) = tokens.split_last() else {
+        return false;
+    };
+    let parent = parents.iter().try_fold(value, |value, token| match value {
+        JsonValue::Object(map) => map.get_mut(token),
+        JsonValue::Array(array) => index(array, token).map(|index| &mut array[index]),
+        _ => None,
+    });
+    match parent {
+        Some(JsonValue::Object(map)) => map.remove(last).is_some(),
+        Some(JsonValue::Array(array)) => match index(array, last) {
+            Some(index) => {
+                array.remove(index);
+                true
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Applies a JSON Merge Patch to `target`, at `pointer`,
+/// adding the pointers to the values that changed to `changed`.
+pub(crate) fn merge_patch(
+    target: &mut JsonValue,
+    patch: &JsonValue,
+    pointer: &mut String,
+    changed: &mut Vec<String>,
+) {
+    let JsonValue::Object(patch) = patch else {
+        if target != patch {
+            *target = patch.clone();
+            changed.push(pointer.clone());
+        }
+        return;
+    };
+    if !target.is_object() {
+        *target = JsonValue::Object(Map::new());
+        changed.push(pointer.clone());
+    }
+    let JsonValue::Object(target) = target else {
+        unreachable!()
+    };
+    let length = pointer.len();
+    for (key, value) in patch {
+        push(pointer, key);
+        if value.is_null() {
+            if target.remove(key).is_some() {
+                changed.push(pointer.clone());
+            }
+        } else {
+            merge_patch(
+                target.entry(key.as_str()).or_insert(JsonValue::Null),
+                value,
+                pointer,
+                changed,
+            );
+        }
+        pointer.truncate(length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tokens(pointer: &str) -> Vec<String> {
+        parse(pointer).unwrap().1
+    }
+
+    #[test]
+    fn parses_pointers() {
+        assert_eq!(
+            parse("/appearance/font~1size/~0a").unwrap(),
+            ("appearance".into(), vec!["font/size".into(), "~a".into()])
+        );
+        assert_eq!(parse("/theme").unwrap(), ("theme".into(), vec![]));
+        assert_eq!(parse("/").unwrap(), ("".into(), vec![]));
+        assert!(matches!(parse("theme"), Err(Error::InvalidPath(_))));
+        assert_eq!(from_key("a/b~c"), "/a~1b~0c");
+    }
+
+    #[test]
+    fn gets_nested_values() {
+        let value = json!({ "fonts": [{ "size": 12 }], "01": true });
+        assert_eq!(get(&value, &tokens("/_/fonts/0/size")), Some(&json!(12)));
+        assert_eq!(get(&value, &tokens("/_/01")), Some(&json!(true)));
+        assert_eq!(get(&value, &tokens("/_/fonts/1")), None);
+        assert_eq!(get(&value, &tokens("/_/fonts/00")), None);
+        assert_eq!(get(&value, &tokens("/_/fonts/0/size/x")), None);
+    }
+
+    #[test]
+    fn sets_nested_values() {
+        let mut value = JsonValue::Null;
+        set(&mut value, &tokens("/_/window/size/width"), json!(800)).unwrap();
+        set(&mut value, &tokens("/_/recent"), json!([])).unwrap();
+        set(&mut value, &tokens("/_/recent/-"), json!("a.txt")).unwrap();
+        set(&mut value, &tokens("/_/recent/0"), json!("b.txt")).unwrap();
+        assert_eq!(
+            value,
+            json!({ "window": { "size": { "width": 800 } }, "recent": ["b.txt"] })
+        );
+        assert_eq!(set(&mut value, &tokens("/_/recent/1"), json!(1)), None);
+        assert_eq!(
+            set(&mut value, &tokens("/_/window/size/width/x"), json!(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn deletes_nested_values() {
+        let mut value = json!({ "window": { "width": 800, "height": 600 }, "recent": ["a", "b"] });
+        assert!(delete(&mut value, &tokens("/_/window/width")));
+        assert!(delete(&mut value, &tokens("/_/recent/0")));
+        assert!(!delete(&mut value, &tokens("/_/window/width")));
+        assert!(!delete(&mut value, &tokens("/_/missing/width")));
+        assert_eq!(
+            value,
+            json!({ "window": { "height": 600 }, "recent": ["b"] })
+        );
+    }
+
+    #[test]
+    fn applies_merge_patches() {
+        // the example of RFC 7386
+        let mut value = json!({
+            "title": "Goodbye!",
+            "author": { "givenName": "John", "familyName": "Doe" },
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        let patch = json!({
+            "title": "Hello!",
+            "phoneNumber": "+01-123-456-7890",
+            "author": { "familyName": null },
+            "tags": ["example"]
+        });
+        let mut changed = Vec::new();
+        merge_patch(&mut value, &patch, &mut String::new(), &mut changed);
+        assert_eq!(
+            value,
+            json!({
+                "title": "Hello!",
+                "author": { "givenName": "John" },
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+        changed.sort();
+        assert_eq!(
+            changed,
+            ["/author/familyName", "/phoneNumber", "/tags", "/title"]
+        );
+    }
+}
@@ -2,11 +2,25 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use crate::{ChangePayload, StoreState};
+#[cfg(feature = "watch")]
+use crate::watch::{self, ConflictPolicy, StoreWatcher, WatchState};
+#[cfg(feature = "encryption")]
+use crate::Encryption;
+use crate::{
+    backup,
+    expiry::{self, Expiries, EXPIRES_KEY},
+    format::Format,
+    migration::{Migrations, VERSION_KEY},
+    pointer,
+    transaction::{self, Transaction},
+    BatchChangePayload, ChangePayload, KeyChange, StoreState,
+};
 use serde_json::Value as JsonValue;
 use std::{
     collections::HashMap,
-    fs,
+    ffi::OsString,
+    fs::{self, File},
+    io::{ErrorKind, Write},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
     time::Duration,
@@ -37,8 +51,14 @@ pub struct StoreBuilder<R: Runtime> {
     defaults: Option<HashMap<String, JsonValue>>,
     serialize_fn: SerializeFn,
     deserialize_fn: DeserializeFn,
+    #[cfg(feature = "encryption")]
+    encryption: Option<Encryption>,
     auto_save: Option<Duration>,
     create_new: bool,
+    backups: usize,
+    migrations: Migrations,
+    #[cfg(feature = "watch")]
+    watch: Option<ConflictPolicy>,
 }
 
 impl<R: Runtime> StoreBuilder<R> {
@@ -53,19 +73,31 @@ impl<R: Runtime> StoreBuilder<R> {
     ///     Ok(())
     ///   });
     /// ```
+    ///
+    /// Paths with the extension of an enabled [`Format`] other than JSON are saved in that format.
     pub fn new<M: Manager<R>, P: AsRef<Path>>(manager: &M, path: P) -> Self {
         let app = manager.app_handle().clone();
         let state = app.state::<StoreState>();
-        let serialize_fn = state.default_serialize;
-        let deserialize_fn = state.default_deserialize;
+        let (serialize_fn, deserialize_fn) = match Format::from_extension(&path) {
+            Some(format) if format != Format::Json => {
+                (format.serialize_fn(), format.deserialize_fn())
+            }
+            _ => (state.default_serialize, state.default_deserialize),
+        };
         Self {
             app,
             path: path.as_ref().to_path_buf(),
             defaults: None,
             serialize_fn,
             deserialize_fn,
+            #[cfg(feature = "encryption")]
+            encryption: None,
             auto_save: Some(Duration::from_millis(100)),
             create_new: false,
+            backups: 0,
+            migrations: Migrations::default(),
+            #[cfg(feature = "watch")]
+            watch: None,
         }
     }
 
@@ -112,6 +144,27 @@ impl<R: Runtime> StoreBuilder<R> {
         self
     }
 
+    /// Saves the store in `format`, instead of the format of the path extension.
+    ///
+    /// # Examples
+    /// ```
+    /// use tauri_plugin_store::Format;
+    ///
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = tauri_plugin_store::StoreBuilder::new(app, "settings.conf")
+    ///       .format(Format::Json)
+    ///       .build()?;
+    ///     Ok(())
+    ///   });
+    /// ```
+    pub fn format(mut self, format: Format) -> Self {
+        self.serialize_fn = format.serialize_fn();
+        self.deserialize_fn = format.deserialize_fn();
+        self
+    }
+
     /// Defines a custom serialization function.
     ///
     /// # Examples
@@ -148,6 +201,30 @@ impl<R: Runtime> StoreBuilder<R> {
         self
     }
 
+    /// Encrypts the store on disk, after serializing it.
+    ///
+    /// Loading a store that was modified or encrypted with an unknown key fails with [`crate::Error::Decrypt`]
+    /// instead of falling back to the defaults, and stores still encrypted with a previous key are saved with the current one.
+    ///
+    /// # Examples
+    /// ```
+    /// use tauri_plugin_store::Encryption;
+    ///
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = tauri_plugin_store::StoreBuilder::new(app, "tokens.bin")
+    ///       .encryption(Encryption::passphrase("new passphrase").previous_passphrase("old passphrase"))
+    ///       .build()?;
+    ///     Ok(())
+    ///   });
+    /// ```
+    #[cfg(feature = "encryption")]
+    pub fn encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
     /// Auto save on modified with a debounce duration
     ///
     /// # Examples
@@ -178,6 +255,94 @@ impl<R: Runtime> StoreBuilder<R> {
         self
     }
 
+    /// Keeps the given number of previous versions of the store file, next to it with a `.bak.<n>` extension,
+    /// where `1` is the most recent one.
+    ///
+    /// When the store file can't be loaded, e.g. after being corrupted, the most recent valid backup is loaded instead.
+    ///
+    /// # Examples
+    /// ```
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = tauri_plugin_store::StoreBuilder::new(app, "store.json")
+    ///       .backups(3)
+    ///       .build()?;
+    ///     Ok(())
+    ///   });
+    /// ```
+    pub fn backups(mut self, count: usize) -> Self {
+        self.backups = count;
+        self
+    }
+
+    /// Sets the version of the store contents, saved with them under the `__version__` key.
+    ///
+    /// When a store saved with an older version is loaded, the migrations registered with [`Self::migrate`]
+    /// from that version are run in order and the migrated store is saved.
+    /// Stores saved without a version are at version `0`,
+    /// and loading a store saved with a newer version fails with [`crate::Error::UnsupportedVersion`].
+    pub fn version(mut self, version: u32) -> Self {
+        self.migrations.version = Some(version);
+        self
+    }
+
+    /// Registers a migration of the store contents from version `from` to version `from + 1`.
+    ///
+    /// # Examples
+    /// ```
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = tauri_plugin_store::StoreBuilder::new(app, "settings.json")
+    ///       .version(1)
+    ///       .migrate(0, |store| {
+    ///         // `dark: bool` was replaced by `theme: "dark" | "light"`
+    ///         let dark = store.remove("dark").and_then(|dark| dark.as_bool()).unwrap_or(false);
+    ///         store.insert("theme".into(), if dark { "dark" } else { "light" }.into());
+    ///         Ok(())
+    ///       })
+    ///       .build()?;
+    ///     Ok(())
+    ///   });
+    /// ```
+    pub fn migrate<F>(mut self, from: u32, migration: F) -> Self
+    where
+        F: Fn(
+                &mut HashMap<String, JsonValue>,
+            ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.migrations.steps.insert(from, Arc::new(migration));
+        self
+    }
+
+    /// Watches the store file and applies the changes made by other processes, e.g. another instance of the app,
+    /// emitting a change event for each changed key.
+    ///
+    /// `policy` decides which value is kept for the keys that were also changed in the store and not saved yet.
+    ///
+    /// # Examples
+    /// ```
+    /// use tauri_plugin_store::ConflictPolicy;
+    ///
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = tauri_plugin_store::StoreBuilder::new(app, "settings.json")
+    ///       .watch(ConflictPolicy::PreferExternal)
+    ///       .build()?;
+    ///     Ok(())
+    ///   });
+    /// ```
+    #[cfg(feature = "watch")]
+    pub fn watch(mut self, policy: ConflictPolicy) -> Self {
+        self.watch = Some(policy);
+        self
+    }
+
     pub(crate) fn build_inner(mut self) -> crate::Result<(Arc<Store<R>>, ResourceId)> {
         let stores = self.app.state::<StoreState>().stores.clone();
         let mut stores = stores.lock().unwrap();
@@ -202,17 +367,59 @@ impl<R: Runtime> StoreBuilder<R> {
             self.defaults.take(),
             self.serialize_fn,
             self.deserialize_fn,
+            self.backups,
+            std::mem::take(&mut self.migrations),
+            #[cfg(feature = "encryption")]
+            self.encryption.take(),
         );
+        #[cfg(feature = "watch")]
+        {
+            store_inner.watch = self.watch.map(WatchState::new);
+        }
 
         if !self.create_new {
-            let _ = store_inner.load();
+            match store_inner.load() {
+                // the store was never saved
+                Err(crate::Error::Io(e)) if e.kind() == ErrorKind::NotFound => {}
+                result => result?,
+            }
         }
 
+        let expires = !store_inner.expiries.is_empty();
+        let store_inner = Arc::new(Mutex::new(store_inner));
+
+        #[cfg(feature = "watch")]
+        let watcher = match self.watch {
+            Some(_) => {
+                fs::create_dir_all(self.path.parent().expect("invalid store path"))?;
+                let store_inner = Arc::downgrade(&store_inner);
+                let watcher = watch::watch(&self.path, move || {
+                    if let Some(store) = store_inner.upgrade() {
+                        let mut store = store.lock().unwrap();
+                        if let Err(e) = store.apply_external_change() {
+                            log::error!(
+                                "failed to apply external change to store {:?}: {e}",
+                                store.path
+                            );
+                        }
+                    }
+                })?;
+                Some(Mutex::new(watcher))
+            }
+            None => None,
+        };
+
         let store = Store {
             auto_save: self.auto_save,
             auto_save_debounce_sender: Arc::new(Mutex::new(None)),
-            store: Arc::new(Mutex::new(store_inner)),
+            store: store_inner,
+            sweeper: Mutex::new(None),
+            #[cfg(feature = "watch")]
+            _watcher: watcher,
         };
+        if expires {
+            store.wake_sweeper();
+        }
 
         let store = Arc::new(store);
         let rid = self.app.resources_table().add_arc(store.clone());
@@ -253,15 +460,28 @@ struct StoreInner<R: Runtime> {
     defaults: Option<HashMap<String, JsonValue>>,
     serialize_fn: SerializeFn,
     deserialize_fn: DeserializeFn,
+    backups: usize,
+    /// Set when the store file couldn't be loaded, so it doesn't replace a valid backup on the next save.
+    skip_rotation: bool,
+    migrations: Migrations,
+    expiries: Expiries,
+    #[cfg(feature = "encryption")]
+    encryption: Option<Encryption>,
+    #[cfg(feature = "watch")]
+    watch: Option<WatchState>,
 }
 
 impl<R: Runtime> StoreInner<R> {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         app: AppHandle<R>,
         path: PathBuf,
         defaults: Option<HashMap<String, JsonValue>>,
         serialize_fn: SerializeFn,
         deserialize_fn: DeserializeFn,
+        backups: usize,
+        migrations: Migrations,
+        #[cfg(feature = "encryption")] encryption: Option<Encryption>,
     ) -> Self {
         Self {
             app,
@@ -270,26 +490,164 @@ impl<R: Runtime> StoreInner<R> {
             defaults,
             serialize_fn,
             deserialize_fn,
+            backups,
+            skip_rotation: false,
+            migrations,
+            expiries: Expiries::default(),
+            #[cfg(feature = "encryption")]
+            encryption,
+            #[cfg(feature = "watch")]
+            watch: None,
         }
     }
 
     /// Saves the store to disk at the store's `path`.
-    pub fn save(&self) -> crate::Result<()> {
+    pub fn save(&mut self) -> crate::Result<()> {
         fs::create_dir_all(self.path.parent().expect("invalid store path"))?;
 
-        let bytes = (self.serialize_fn)(&self.cache).map_err(crate::Error::Serialize)?;
-        fs::write(&self.path, bytes)?;
+        if let Some(version) = self.migrations.version {
+            self.cache.insert(VERSION_KEY.into(), version.into());
+        }
+        if let Some(expiries) = self.expiries.to_value() {
+            self.cache.insert(EXPIRES_KEY.into(), expiries);
+        }
+        let bytes = (self.serialize_fn)(&self.cache);
+        if self.migrations.version.is_some() {
+            self.cache.remove(VERSION_KEY);
+        }
+        self.cache.remove(EXPIRES_KEY);
+        let bytes = bytes.map_err(crate::Error::Serialize)?;
+        #[cfg(feature = "encryption")]
+        let bytes = match &self.encryption {
+            Some(encryption) => encryption.encrypt(&bytes)?,
+            None => bytes,
+        };
+
+        // write a temporary file replacing the store at once, so a crash can't leave it half written
+        let temp_path = with_extension_suffix(&self.path, "tmp");
+        if let Err(e) = self.replace_with(&temp_path, &bytes) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        #[cfg(feature = "watch")]
+        if let Some(watch) = &mut self.watch {
+            watch.synced(&self.cache, &bytes);
+        }
 
         Ok(())
     }
 
-    /// Update the store from the on-disk state
+    /// Writes `bytes` to `temp_path` and renames it to the store file, rotating the backups in between.
+    fn replace_with(&mut self, temp_path: &Path, bytes: &[u8]) -> crate::Result<()> {
+        let mut file = File::create(temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        drop(file);
+
+        if !std::mem::take(&mut self.skip_rotation) {
+            backup::rotate(&self.path, self.backups)?;
+        }
+        fs::rename(temp_path, &self.path)?;
+
+        // persist the rename itself, directories can't be opened as files on Windows
+        #[cfg(unix)]
+        File::open(self.path.parent().expect("invalid store path"))?.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Update the store from the on-disk state, or from the most recent valid backup if it can't be loaded.
     pub fn load(&mut self) -> crate::Result<()> {
-        let bytes = fs::read(&self.path)?;
+        let path = self.path.clone();
+        let loaded = backup::load(&path, self.backups, |file| {
+            // set before loading, which saves outdated stores
+            self.skip_rotation = file != path;
+            self.load_from(file)
+        });
+        if let Some((backup, error)) = loaded? {
+            log::warn!("failed to load store {path:?}, loaded backup {backup:?} instead: {error}");
+        }
+        Ok(())
+    }
 
-        self.cache
-            .extend((self.deserialize_fn)(&bytes).map_err(crate::Error::Deserialize)?);
+    fn load_from(&mut self, path: &Path) -> crate::Result<()> {
+        let bytes = fs::read(path)?;
+        let (store, expiries, outdated) = self.parse(&bytes)?;
+        self.cache.extend(store);
+        self.expiries.extend(expiries);
+
+        #[cfg(feature = "watch")]
+        if let Some(watch) = &mut self.watch {
+            if path == self.path {
+                watch.synced(&self.cache, &bytes);
+            }
+        }
+
+        if outdated {
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Decrypts, deserializes and migrates the contents of a store file,
+    /// returning the expiration times of its keys and whether they must be saved again to be up to date.
+    fn parse(&self, bytes: &[u8]) -> crate::Result<(HashMap<String, JsonValue>, Expiries, bool)> {
+        #[cfg(feature = "encryption")]
+        let decrypted;
+        #[cfg(feature = "encryption")]
+        let (bytes, rotated) = match &self.encryption {
+            Some(encryption) => {
+                let rotated;
+                (decrypted, rotated) = encryption.decrypt(bytes)?;
+                (decrypted.as_slice(), rotated)
+            }
+            None => (bytes, false),
+        };
+
+        let mut store = (self.deserialize_fn)(bytes).map_err(crate::Error::Deserialize)?;
+        let expiries = Expiries::take(&mut store);
+        let migrated = self.migrations.migrate(&mut store)?;
+
+        #[cfg(feature = "encryption")]
+        let migrated = migrated || rotated;
+        Ok((store, expiries, migrated))
+    }
+
+    /// Merges the changes made to the store file by other processes, emitting a change event for each changed key.
+    #[cfg(feature = "watch")]
+    fn apply_external_change(&mut self) -> crate::Result<()> {
+        let Some(watch) = &self.watch else {
+            return Ok(());
+        };
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            // a later event reports the new file
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if !watch.is_external_change(&bytes) {
+            return Ok(());
+        }
 
+        let (store, expiries, outdated) = self.parse(&bytes)?;
+        let mut external = self.defaults.clone().unwrap_or_default();
+        external.extend(store);
+        let resolved = watch.resolve(&self.cache, &external);
+        for (key, value) in watch::changes(&self.cache, &resolved) {
+            let _ = self.emit_change_event(key, value);
+        }
+        self.cache = resolved;
+        self.expiries.extend(expiries);
+        self.expiries.retain(&self.cache);
+        if let Some(watch) = &mut self.watch {
+            watch.synced(&external, &bytes);
+        }
+
+        if outdated {
+            self.save()?;
+        }
         Ok(())
     }
 
@@ -297,22 +655,133 @@ impl<R: Runtime> StoreInner<R> {
     pub fn set(&mut self, key: impl Into<String>, value: impl Into<JsonValue>) {
         let key = key.into();
         let value = value.into();
+        self.expiries.remove(&key);
         self.cache.insert(key.clone(), value.clone());
         let _ = self.emit_change_event(&key, Some(&value));
     }
 
+    /// Inserts a key-value pair into the store, deleted once `ttl` has elapsed.
+    pub fn set_with_ttl(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<JsonValue>,
+        ttl: Duration,
+    ) {
+        let key = key.into();
+        self.set(key.clone(), value);
+        self.expiries.insert(key, ttl);
+    }
+
+    /// Deletes the expired keys, returning whether there were any.
+    fn remove_expired(&mut self) -> bool {
+        let expired = self.expiries.expired(expiry::now());
+        for key in &expired {
+            self.delete(key);
+        }
+        !expired.is_empty()
+    }
+
+    /// The live entries of the store, without the expired ones not deleted yet.
+    fn live(&self) -> impl Iterator<Item = (&String, &JsonValue)> {
+        let now = expiry::now();
+        self.cache
+            .iter()
+            .filter(move |(key, _)| !self.expiries.is_expired(key, now))
+    }
+
     /// Returns a reference to the value corresponding to the key.
     pub fn get(&self, key: impl AsRef<str>) -> Option<&JsonValue> {
-        self.cache.get(key.as_ref())
+        let key = key.as_ref();
+        if self.expiries.is_expired(key, expiry::now()) {
+            return None;
+        }
+        self.cache.get(key)
+    }
+
+    /// The value of `key` to update, `null` if it does not exist or expired,
+    /// in which case the new value does not expire.
+    fn live_or_default(&mut self, key: &str) -> JsonValue {
+        match self.get(key) {
+            Some(value) => value.clone(),
+            None => {
+                self.expiries.remove(key);
+                JsonValue::Null
+            }
+        }
+    }
+
+    /// Returns a reference to the value at the JSON Pointer `path`.
+    pub fn get_path(&self, path: &str) -> crate::Result<Option<&JsonValue>> {
+        let (key, tokens) = pointer::parse(path)?;
+        Ok(self
+            .get(&key)
+            .and_then(|value| pointer::get(value, &tokens)))
+    }
+
+    /// Sets the value at the JSON Pointer `path`, creating the missing objects on the way.
+    pub fn set_path(&mut self, path: &str, value: JsonValue) -> crate::Result<()> {
+        let (key, tokens) = pointer::parse(path)?;
+        let mut key_value = self.live_or_default(&key);
+        pointer::set(&mut key_value, &tokens, value)
+            .ok_or_else(|| crate::Error::InvalidPath(path.into()))?;
+        self.cache.insert(key.clone(), key_value);
+        let _ = self.emit_nested_change_event(&key, self.cache.get(&key), vec![path.into()]);
+        Ok(())
+    }
+
+    /// Removes the value at the JSON Pointer `path`.
+    pub fn delete_path(&mut self, path: &str) -> crate::Result<bool> {
+        let (key, tokens) = pointer::parse(path)?;
+        if tokens.is_empty() {
+            return Ok(self.delete(key));
+        }
+        if self.get(&key).is_none() {
+            return Ok(false);
+        }
+        let Some(key_value) = self.cache.get_mut(&key) else {
+            return Ok(false);
+        };
+        let deleted = pointer::delete(key_value, &tokens);
+        if deleted {
+            let _ = self.emit_nested_change_event(&key, self.cache.get(&key), vec![path.into()]);
+        }
+        Ok(deleted)
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to the store, whose keys are the patch fields.
+    pub fn merge_patch(&mut self, patch: &JsonValue) -> crate::Result<()> {
+        let JsonValue::Object(patch) = patch else {
+            return Err(crate::Error::InvalidMergePatch);
+        };
+        for (key, key_patch) in patch {
+            if key_patch.is_null() {
+                self.delete(key);
+                continue;
+            }
+            let mut key_value = self.live_or_default(key);
+            let mut changed = Vec::new();
+            pointer::merge_patch(
+                &mut key_value,
+                key_patch,
+                &mut pointer::from_key(key),
+                &mut changed,
+            );
+            if !changed.is_empty() {
+                self.cache.insert(key.clone(), key_value);
+                let _ = self.emit_nested_change_event(key, self.cache.get(key), changed);
+            }
+        }
+        Ok(())
     }
 
     /// Returns `true` if the given `key` exists in the store.
     pub fn has(&self, key: impl AsRef<str>) -> bool {
-        self.cache.contains_key(key.as_ref())
+        self.get(key).is_some()
     }
 
     /// Removes a key-value pair from the store.
     pub fn delete(&mut self, key: impl AsRef<str>) -> bool {
+        self.expiries.remove(key.as_ref());
         let flag = self.cache.remove(key.as_ref()).is_some();
         if flag {
             let _ = self.emit_change_event(key.as_ref(), None);
@@ -326,6 +795,7 @@ impl<R: Runtime> StoreInner<R> {
     pub fn clear(&mut self) {
         let keys: Vec<String> = self.cache.keys().cloned().collect();
         self.cache.clear();
+        self.expiries.clear();
         for key in &keys {
             let _ = self.emit_change_event(key, None);
         }
@@ -347,6 +817,7 @@ impl<R: Runtime> StoreInner<R> {
                 }
             }
             self.cache.clone_from(defaults);
+            self.expiries.clear();
         } else {
             self.clear()
         }
@@ -354,30 +825,71 @@ impl<R: Runtime> StoreInner<R> {
 
     /// An iterator visiting all keys in arbitrary order.
     pub fn keys(&self) -> impl Iterator<Item = &String> {
-        self.cache.keys()
+        self.live().map(|(key, _)| key)
     }
 
     /// An iterator visiting all values in arbitrary order.
     pub fn values(&self) -> impl Iterator<Item = &JsonValue> {
-        self.cache.values()
+        self.live().map(|(_, value)| value)
     }
 
     /// An iterator visiting all key-value pairs in arbitrary order.
     pub fn entries(&self) -> impl Iterator<Item = (&String, &JsonValue)> {
-        self.cache.iter()
+        self.live()
     }
 
     /// Returns the number of elements in the store.
     pub fn len(&self) -> usize {
-        self.cache.len()
+        self.live().count()
     }
 
     /// Returns true if the store contains no elements.
     pub fn is_empty(&self) -> bool {
-        self.cache.is_empty()
+        self.live().next().is_none()
+    }
+
+    /// Applies the writes of a transaction, emitting a single event for all of them.
+    fn commit(&mut self, writes: &[transaction::Write]) {
+        if writes.is_empty() {
+            return;
+        }
+        transaction::commit(writes, &mut self.cache, &mut self.expiries);
+        let _ = self.emit_batch_change_event(writes);
+    }
+
+    fn emit_batch_change_event(&self, writes: &[transaction::Write]) -> crate::Result<()> {
+        let state = self.app.state::<StoreState>();
+        let stores = state.stores.lock().unwrap();
+        self.app.emit(
+            "store://batch-change",
+            BatchChangePayload {
+                path: &self.path,
+                resource_id: stores.get(&self.path).copied(),
+                changes: writes
+                    .iter()
+                    .map(|(key, value, changed)| KeyChange {
+                        key,
+                        value: value.as_ref(),
+                        exists: value.is_some(),
+                        changed,
+                    })
+                    .collect(),
+            },
+        )?;
+        Ok(())
     }
 
     fn emit_change_event(&self, key: &str, value: Option<&JsonValue>) -> crate::Result<()> {
+        self.emit_nested_change_event(key, value, vec![pointer::from_key(key)])
+    }
+
+    /// Emits the change of the value of `key` at the JSON Pointers `changed`.
+    fn emit_nested_change_event(
+        &self,
+        key: &str,
+        value: Option<&JsonValue>,
+        changed: Vec<String>,
+    ) -> crate::Result<()> {
         let state = self.app.state::<StoreState>();
         let stores = state.stores.lock().unwrap();
         let exists = value.is_some();
@@ -389,12 +901,21 @@ impl<R: Runtime> StoreInner<R> {
                 key,
                 value,
                 exists,
+                changed,
             },
         )?;
         Ok(())
     }
 }
 
+/// Appends `.{suffix}` to the file name of `path`.
+fn with_extension_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
 impl<R: Runtime> std::fmt::Debug for StoreInner<R> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Store")
@@ -408,6 +929,11 @@ pub struct Store<R: Runtime> {
     auto_save: Option<Duration>,
     auto_save_debounce_sender: Arc<Mutex<Option<UnboundedSender<AutoSaveMessage>>>>,
     store: Arc<Mutex<StoreInner<R>>>,
+    /// Wakes up the task deleting the expired keys, which stops when dropped.
+    sweeper: Mutex<Option<UnboundedSender<()>>>,
+    /// Stops watching the store file when dropped.
+    #[cfg(feature = "watch")]
+    _watcher: Option<Mutex<StoreWatcher>>,
 }
 
 impl<R: Runtime> Resource for Store<R> {
@@ -433,6 +959,33 @@ impl<R: Runtime> Store<R> {
         let _ = self.trigger_auto_save();
     }
 
+    /// Inserts a key-value pair into the store, deleted once `ttl` has elapsed.
+    ///
+    /// The expiration time is saved with the store. Expired keys are hidden right away
+    /// and deleted in the background, emitting change events.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use tauri_plugin_store::StoreExt;
+    ///
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = app.store("cache.json")?;
+    ///     store.set_with_ttl("access-token", "secret", Duration::from_secs(3600));
+    ///     Ok(())
+    ///   });
+    /// ```
+    pub fn set_with_ttl(&self, key: impl Into<String>, value: impl Into<JsonValue>, ttl: Duration) {
+        self.store
+            .lock()
+            .unwrap()
+            .set_with_ttl(key.into(), value.into(), ttl);
+        self.wake_sweeper();
+        let _ = self.trigger_auto_save();
+    }
+
     /// Returns the value for the given `key` or `None` if the key does not exist.
     pub fn get(&self, key: impl AsRef<str>) -> Option<JsonValue> {
         self.store.lock().unwrap().get(key).cloned()
@@ -452,6 +1005,116 @@ impl<R: Runtime> Store<R> {
         deleted
     }
 
+    /// Applies the changes made by `f` as a unit if it succeeds,
+    /// with a single change event and auto save for all of them.
+    ///
+    /// The store stays locked until `f` returns, so `f` must not access the store itself.
+    ///
+    /// # Examples
+    /// ```
+    /// use tauri_plugin_store::StoreExt;
+    /// use serde_json::json;
+    ///
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = app.store("contacts.json")?;
+    ///     store.transaction(|tx| {
+    ///       tx.set("alice", json!({ "email": "alice@example.com" }));
+    ///       tx.set("bob", json!({ "email": "bob@example.com" }));
+    ///       tx.set_path("/meta/importedAt", "2024-01-01")
+    ///     })?;
+    ///     Ok(())
+    ///   });
+    /// ```
+    pub fn transaction<T, E>(
+        &self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut store = self.store.lock().unwrap();
+        // expired keys must not be seen nor resurrected by the transaction
+        let expired = store.remove_expired();
+        let mut transaction = Transaction::new(&store.cache);
+        let output = f(&mut transaction);
+        let writes = match &output {
+            Ok(_) => transaction.into_writes(),
+            // rolls back the changes
+            Err(_) => {
+                drop(transaction);
+                Vec::new()
+            }
+        };
+        store.commit(&writes);
+        drop(store);
+
+        if expired || !writes.is_empty() {
+            let _ = self.trigger_auto_save();
+        }
+        output
+    }
+
+    /// Returns the value at the JSON Pointer `path`, whose first token is the store key,
+    /// or `None` if it does not exist.
+    ///
+    /// # Examples
+    /// ```
+    /// use tauri_plugin_store::StoreExt;
+    /// use serde_json::json;
+    ///
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = app.store("settings.json")?;
+    ///     store.set("appearance", json!({ "theme": "dark", "fontSize": 14 }));
+    ///     assert_eq!(store.get_path("/appearance/theme")?, Some(json!("dark")));
+    ///     Ok(())
+    ///   });
+    /// ```
+    pub fn get_path(&self, path: &str) -> crate::Result<Option<JsonValue>> {
+        Ok(self.store.lock().unwrap().get_path(path)?.cloned())
+    }
+
+    /// Sets the value at the JSON Pointer `path`, whose first token is the store key,
+    /// creating the missing objects on the way. `-` appends to arrays.
+    ///
+    /// Fails if a parent value is neither an object nor an array, or an array index is out of bounds.
+    pub fn set_path(&self, path: &str, value: impl Into<JsonValue>) -> crate::Result<()> {
+        self.store.lock().unwrap().set_path(path, value.into())?;
+        let _ = self.trigger_auto_save();
+        Ok(())
+    }
+
+    /// Removes the value at the JSON Pointer `path`, whose first token is the store key.
+    pub fn delete_path(&self, path: &str) -> crate::Result<bool> {
+        let deleted = self.store.lock().unwrap().delete_path(path)?;
+        if deleted {
+            let _ = self.trigger_auto_save();
+        }
+        Ok(deleted)
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to the store: the patch fields are the store keys,
+    /// nested objects are merged and `null` removes a value.
+    ///
+    /// # Examples
+    /// ```
+    /// use tauri_plugin_store::StoreExt;
+    /// use serde_json::json;
+    ///
+    /// tauri::Builder::default()
+    ///   .plugin(tauri_plugin_store::Builder::default().build())
+    ///   .setup(|app| {
+    ///     let store = app.store("settings.json")?;
+    ///     store.merge_patch(&json!({ "appearance": { "theme": "light", "fontSize": null } }))?;
+    ///     Ok(())
+    ///   });
+    /// ```
+    pub fn merge_patch(&self, patch: &JsonValue) -> crate::Result<()> {
+        self.store.lock().unwrap().merge_patch(patch)?;
+        let _ = self.trigger_auto_save();
+        Ok(())
+    }
+
     /// Clears the store, removing all key-value pairs.
     ///
     /// Note: To clear the storage and reset it to its `default` value, use [`reset`](Self::reset) instead.
@@ -500,7 +1163,14 @@ impl<R: Runtime> Store<R> {
 
     /// Update the store from the on-disk state
     pub fn reload(&self) -> crate::Result<()> {
-        self.store.lock().unwrap().load()
+        let mut store = self.store.lock().unwrap();
+        store.load()?;
+        let expires = !store.expiries.is_empty();
+        drop(store);
+        if expires {
+            self.wake_sweeper();
+        }
+        Ok(())
     }
 
     /// Saves the store to disk at the store's `path`.
@@ -551,7 +1221,10 @@ impl<R: Runtime> Store<R> {
                     }
                     _ = sleep(auto_save_delay) => {
                         auto_save_debounce_sender.lock().unwrap().take();
-                        let _ = store.lock().unwrap().save();
+                        let mut store = store.lock().unwrap();
+                        if let Err(e) = store.save() {
+                            log::error!("failed to save store {:?}: {e}", store.path);
+                        }
                         return;
                     }
                 };
@@ -560,6 +1233,46 @@ impl<R: Runtime> Store<R> {
         Ok(())
     }
 
+    /// Starts the task deleting the expired keys, or wakes it up to schedule a new expiration time.
+    fn wake_sweeper(&self) {
+        let mut sweeper = self.sweeper.lock().unwrap();
+        if let Some(sender) = &*sweeper {
+            let _ = sender.send(());
+            return;
+        }
+        let (sender, mut receiver) = unbounded_channel();
+        sweeper.replace(sender);
+        drop(sweeper);
+        let store = Arc::downgrade(&self.store);
+        let auto_save = self.auto_save.is_some();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let next = {
+                    let Some(store) = store.upgrade() else {
+                        return;
+                    };
+                    let mut store = store.lock().unwrap();
+                    if store.remove_expired() && auto_save {
+                        if let Err(e) = store.save() {
+                            log::error!("failed to save store {:?}: {e}", store.path);
+                        }
+                    }
+                    store.expiries.next()
+                };
+                let woken = match next {
+                    Some(at) => select! {
+                        message = receiver.recv() => message.is_some(),
+                        _ = sleep(expiry::until(at)) => true,
+                    },
+                    None => receiver.recv().await.is_some(),
+                };
+                if !woken {
+                    return;
+                }
+            }
+        });
+    }
+
     fn apply_pending_auto_save(&self) {
         // Cancel and save if auto save is pending
         if let Some(sender) = self.auto_save_debounce_sender.lock().unwrap().take() {
@@ -574,3 +1287,87 @@ impl<R: Runtime> Drop for Store<R> {
         self.apply_pending_auto_save();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_store;
+    use serde_json::json;
+
+    fn app() -> tauri::App<tauri::test::MockRuntime> {
+        tauri::test::mock_builder()
+            .plugin(crate::Builder::default().build())
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap()
+    }
+
+    /// An empty directory for the stores of a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tauri-plugin-store-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_store(path: &Path) -> HashMap<String, JsonValue> {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn migrates_old_stores_and_saves_their_version() {
+        let app = app();
+        let dir = test_dir("migration");
+        let path = dir.join("settings.json");
+        fs::write(&path, json!({ VERSION_KEY: 1, "dark": true }).to_string()).unwrap();
+
+        let store = StoreBuilder::new(app.handle(), &path)
+            .disable_auto_save()
+            .version(2)
+            .migrate(0, |_| Err("the store is already at version 1".into()))
+            .migrate(1, |store| {
+                let dark = store.remove("dark").ok_or("missing dark")?;
+                let theme = if dark == true { "dark" } else { "light" };
+                store.insert("theme".into(), theme.into());
+                Ok(())
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(store.get("theme"), Some(json!("dark")));
+        assert_eq!(store.get(VERSION_KEY), None);
+        // the migrated store is saved as soon as it is loaded
+        assert_eq!(
+            read_store(&path),
+            test_store(json!({ VERSION_KEY: 2, "theme": "dark" }))
+        );
+
+        store.set("lang", "en");
+        store.save().unwrap();
+        assert_eq!(
+            read_store(&path),
+            test_store(json!({ VERSION_KEY: 2, "theme": "dark", "lang": "en" }))
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn removes_the_temporary_file_when_saving_fails() {
+        let app = app();
+        let dir = test_dir("failed-save");
+        let path = dir.join("settings.json");
+        // the store can't be renamed over a non-empty directory
+        fs::create_dir_all(path.join("occupied")).unwrap();
+
+        let store = StoreBuilder::new(app.handle(), &path)
+            .disable_auto_save()
+            .create_new()
+            .build()
+            .unwrap();
+        store.set("theme", "dark");
+        assert!(store.save().is_err());
+        assert!(!with_extension_suffix(&path, "tmp").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,341 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Changes applied to a store as a unit, see [`crate::Store::transaction`].
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+
+use crate::{expiry::Expiries, pointer, Error, Result};
+
+/// A write committed by a transaction: the key, its new value and the JSON Pointers to the changed values.
+pub(crate) type Write = (String, Option<JsonValue>, Vec<String>);
+
+/// Changes to a store, applied when the transaction closure succeeds.
+///
+/// Reads see the changes made earlier in the transaction.
+pub struct Transaction<'a> {
+    cache: &'a HashMap<String, JsonValue>,
+    /// New values of the changed keys, `None` if deleted.
+    writes: HashMap<String, Option<JsonValue>>,
+    changed: HashMap<String, Vec<String>>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(cache: &'a HashMap<String, JsonValue>) -> Self {
+        Self {
+            cache,
+            writes: HashMap::new(),
+            changed: HashMap::new(),
+        }
+    }
+
+    fn write(&mut self, key: String, value: Option<JsonValue>, changed: Vec<String>) {
+        self.changed.entry(key.clone()).or_default().extend(changed);
+        self.writes.insert(key, value);
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get(&self, key: impl AsRef<str>) -> Option<&JsonValue> {
+        match self.writes.get(key.as_ref()) {
+            Some(value) => value.as_ref(),
+            None => self.cache.get(key.as_ref()),
+        }
+    }
+
+    /// Returns `true` if the given `key` exists in the store.
+    pub fn has(&self, key: impl AsRef<str>) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts a key-value pair into the store.
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<JsonValue>) {
+        let key = key.into();
+        let pointer = pointer::from_key(&key);
+        self.write(key, Some(value.into()), vec![pointer]);
+    }
+
+    /// Removes a key-value pair from the store.
+    pub fn delete(&mut self, key: impl AsRef<str>) -> bool {
+        let key = key.as_ref();
+        let deleted = self.has(key);
+        if deleted {
+            self.write(key.into(), None, vec![pointer::from_key(key)]);
+        }
+        deleted
+    }
+
+    /// Clears the store, removing all key-value pairs.
+    pub fn clear(&mut self) {
+        let keys: Vec<String> = self
+            .cache
+            .keys()
+            .chain(self.writes.keys())
+            .filter(|key| self.has(key))
+            .cloned()
+            .collect();
+        for key in keys {
+            self.delete(key);
+        }
+    }
+
+    /// Returns a reference to the value at the JSON Pointer `path`.
+    pub fn get_path(&self, path: &str) -> Result<Option<&JsonValue>> {
+        let (key, tokens) = pointer::parse(path)?;
+        Ok(self.get(key).and_then(|value| pointer::get(value, &tokens)))
+    }
+
+    /// Sets the value at the JSON Pointer `path`, creating the missing objects on the way.
+    pub fn set_path(&mut self, path: &str, value: impl Into<JsonValue>) -> Result<()> {
+        let (key, tokens) = pointer::parse(path)?;
+        let mut key_value = self.get(&key).cloned().unwrap_or_default();
+        pointer::set(&mut key_value, &tokens, value.into())
+            .ok_or_else(|| Error::InvalidPath(path.into()))?;
+        self.write(key, Some(key_value), vec![path.into()]);
+        Ok(())
+    }
+
+    /// Removes the value at the JSON Pointer `path`.
+    pub fn delete_path(&mut self, path: &str) -> Result<bool> {
+        let (key, tokens) = pointer::parse(path)?;
+        if tokens.is_empty() {
+            return Ok(self.delete(key));
+        }
+        let Some(mut key_value) = self.get(&key).cloned() else {
+            return Ok(false);
+        };
+        let deleted = pointer::delete(&mut key_value, &tokens);
+        if deleted {
+            self.write(key, Some(key_value), vec![path.into()]);
+        }
+        Ok(deleted)
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to the store, whose keys are the patch fields.
+    pub fn merge_patch(&mut self, patch: &JsonValue) -> Result<()> {
+        let JsonValue::Object(patch) = patch else {
+            return Err(Error::InvalidMergePatch);
+        };
+        for (key, key_patch) in patch {
+            if key_patch.is_null() {
+                self.delete(key);
+                continue;
+            }
+            let mut key_value = self.get(key).cloned().unwrap_or_default();
+            let mut changed = Vec::new();
+            pointer::merge_patch(
+                &mut key_value,
+                key_patch,
+                &mut pointer::from_key(key),
+                &mut changed,
+            );
+            if !changed.is_empty() {
+                self.write(key.clone(), Some(key_value), changed);
+            }
+        }
+        Ok(())
+    }
+
+    /// The writes that change the store.
+    pub(crate) fn into_writes(mut self) -> Vec<Write> {
+        self.writes
+            .into_iter()
+            .filter(|(key, value)| self.cache.get(key) != value.as_ref())
+            .map(|(key, value)| {
+                let changed = self.changed.remove(&key).unwrap_or_default();
+                (key, value, changed)
+            })
+            .collect()
+    }
+}
+
+/// Applies the `writes` of a transaction to the `cache` of a store.
+///
+/// The written keys don't expire anymore, like with [`crate::Store::set`].
+pub(crate) fn commit(
+    writes: &[Write],
+    cache: &mut HashMap<String, JsonValue>,
+    expiries: &mut Expiries,
+) {
+    for (key, value, _) in writes {
+        match value {
+            Some(value) => {
+                cache.insert(key.clone(), value.clone());
+            }
+            None => {
+                cache.remove(key);
+            }
+        }
+        expiries.remove(key);
+    }
+}
+
+/// A change of a batch sent from JavaScript.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub(crate) enum Operation {
+    Set { key: String, value: JsonValue },
+    Delete { key: String },
+    Clear,
+    SetPath { path: String, value: JsonValue },
+    DeletePath { path: String },
+    MergePatch { patch: JsonValue },
+}
+
+impl Operation {
+    pub fn apply(self, transaction: &mut Transaction<'_>) -> Result<()> {
+        match self {
+            Self::Set { key, value } => transaction.set(key, value),
+            Self::Delete { key } => {
+                transaction.delete(key);
+            }
+            Self::Clear => transaction.clear(),
+            Self::SetPath { path, value } => transaction.set_path(&path, value)?,
+            Self::DeletePath { path } => {
+                transaction.delete_path(&path)?;
+            }
+            Self::MergePatch { patch } => transaction.merge_patch(&patch)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_store;
+    use serde_json::json;
+
+    fn sample() -> HashMap<String, JsonValue> {
+        test_store(json!({
+            "theme": "dark",
+            "window": { "width": 800 },
+            "recent": ["a.txt"]
+        }))
+    }
+
+    fn sorted(mut writes: Vec<Write>) -> Vec<Write> {
+        writes.sort_by(|a, b| a.0.cmp(&b.0));
+        writes
+    }
+
+    #[test]
+    fn reads_its_own_writes() {
+        let store = sample();
+        let mut transaction = Transaction::new(&store);
+        transaction.set("lang", "en");
+        assert!(transaction.delete("theme"));
+        assert!(!transaction.delete("theme"));
+        transaction.set_path("/window/height", 600).unwrap();
+
+        assert_eq!(transaction.get("lang"), Some(&json!("en")));
+        assert!(!transaction.has("theme"));
+        assert_eq!(
+            transaction.get_path("/window").unwrap(),
+            Some(&json!({ "width": 800, "height": 600 }))
+        );
+        // the store is not modified
+        assert_eq!(store.get("theme"), Some(&json!("dark")));
+    }
+
+    #[test]
+    fn commits_the_changed_keys_once() {
+        let store = sample();
+        let mut transaction = Transaction::new(&store);
+        transaction.set("theme", "light");
+        transaction.set("theme", "dark");
+        transaction.set_path("/window/width", 1024).unwrap();
+        transaction.set_path("/window/height", 768).unwrap();
+        transaction.delete_path("/recent/0").unwrap();
+        transaction
+            .merge_patch(&json!({ "recent": null, "lang": "en" }))
+            .unwrap();
+
+        assert_eq!(
+            sorted(transaction.into_writes()),
+            vec![
+                ("lang".into(), Some(json!("en")), vec!["/lang".into()]),
+                (
+                    "recent".into(),
+                    None,
+                    vec!["/recent/0".into(), "/recent".into()]
+                ),
+                (
+                    "window".into(),
+                    Some(json!({ "width": 1024, "height": 768 })),
+                    vec!["/window/width".into(), "/window/height".into()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn clears_the_store() {
+        let store = sample();
+        let mut transaction = Transaction::new(&store);
+        transaction.set("lang", "en");
+        transaction.clear();
+        assert!(!transaction.has("lang"));
+        assert_eq!(
+            sorted(transaction.into_writes())
+                .into_iter()
+                .map(|(key, value, _)| (key, value))
+                .collect::<Vec<_>>(),
+            vec![
+                ("recent".into(), None),
+                ("theme".into(), None),
+                ("window".into(), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn committed_keys_do_not_expire() {
+        let mut cache = sample();
+        let mut expiries = Expiries::default();
+        for key in ["theme", "window", "recent"] {
+            expiries.insert(key.into(), std::time::Duration::ZERO);
+        }
+
+        let mut transaction = Transaction::new(&cache);
+        transaction.set("theme", "light");
+        transaction.delete("recent");
+        let writes = transaction.into_writes();
+        commit(&writes, &mut cache, &mut expiries);
+
+        assert_eq!(cache.get("theme"), Some(&json!("light")));
+        assert!(!cache.contains_key("recent"));
+        assert_eq!(expiries.expired(u64::MAX), vec!["window".to_string()]);
+    }
+
+    #[test]
+    fn applies_operations_from_javascript() {
+        let operations: Vec<Operation> = serde_json::from_value(json!([
+            { "type": "set", "key": "lang", "value": "fr" },
+            { "type": "setPath", "path": "/window/width", "value": 640 },
+            { "type": "deletePath", "path": "/recent/0" },
+            { "type": "mergePatch", "patch": { "theme": null } },
+        ]))
+        .unwrap();
+        let store = sample();
+        let mut transaction = Transaction::new(&store);
+        for operation in operations {
+            operation.apply(&mut transaction).unwrap();
+        }
+        assert_eq!(transaction.get("lang"), Some(&json!("fr")));
+        assert_eq!(transaction.get("window"), Some(&json!({ "width": 640 })));
+        assert_eq!(transaction.get("recent"), Some(&json!([])));
+        assert!(!transaction.has("theme"));
+
+        let invalid = Operation::SetPath {
+            path: "/theme/color".into(),
+            value: json!("red"),
+        };
+        assert!(matches!(
+            invalid.apply(&mut Transaction::new(&store)),
+            Err(Error::InvalidPath(_))
+        ));
+    }
+}
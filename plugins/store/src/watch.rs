@@ -0,0 +1,218 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Detection of changes made to the store file by other processes.
+
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, RecommendedCache};
+use serde_json::Value as JsonValue;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::Path,
+    time::Duration,
+};
+
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+pub(crate) type StoreWatcher = Debouncer<RecommendedWatcher, RecommendedCache>;
+
+/// How external changes are applied when the store has unsaved changes.
+///
+/// Changes to different keys are always merged; the policy decides which value is kept
+/// for the keys changed both in the store and in the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictPolicy {
+    /// Keeps the unsaved value, which overwrites the external change on the next save.
+    #[default]
+    PreferLocal,
+    /// Keeps the external value.
+    PreferExternal,
+    /// Discards all unsaved changes and reloads the file.
+    DiscardLocal,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct WatchState {
+    pub policy: ConflictPolicy,
+    /// The store contents as last saved or loaded.
+    pub synced: HashMap<String, JsonValue>,
+    /// Hash of the store file as last saved or loaded, to ignore our own writes.
+    pub file_hash: Option<u64>,
+}
+
+impl WatchState {
+    pub fn new(policy: ConflictPolicy) -> Self {
+        Self {
+            policy,
+            synced: HashMap::new(),
+            file_hash: None,
+        }
+    }
+
+    pub fn synced(&mut self, cache: &HashMap<String, JsonValue>, bytes: &[u8]) {
+        self.synced.clone_from(cache);
+        self.file_hash = Some(hash(bytes));
+    }
+
+    /// Whether `bytes` differ from the file as last saved or loaded.
+    pub fn is_external_change(&self, bytes: &[u8]) -> bool {
+        self.file_hash != Some(hash(bytes))
+    }
+
+    /// Merges the `external` contents of the file into the `local` store.
+    pub fn resolve(
+        &self,
+        local: &HashMap<String, JsonValue>,
+        external: &HashMap<String, JsonValue>,
+    ) -> HashMap<String, JsonValue> {
+        if self.policy == ConflictPolicy::DiscardLocal {
+            return external.clone();
+        }
+
+        let keys: HashSet<&String> = self
+            .synced
+            .keys()
+            .chain(local.keys())
+            .chain(external.keys())
+            .collect();
+        let mut resolved = HashMap::new();
+        for key in keys {
+            let base = self.synced.get(key);
+            let local_value = local.get(key);
+            let external_value = external.get(key);
+            let value = if local_value == base {
+                external_value
+            } else if external_value == base || self.policy == ConflictPolicy::PreferLocal {
+                local_value
+            } else {
+                external_value
+            };
+            if let Some(value) = value {
+                resolved.insert(key.clone(), value.clone());
+            }
+        }
+        resolved
+    }
+}
+
+/// The keys whose value differs between `old` and `new`, with their new value.
+pub(crate) fn changes<'a>(
+    old: &'a HashMap<String, JsonValue>,
+    new: &'a HashMap<String, JsonValue>,
+) -> Vec<(&'a str, Option<&'a JsonValue>)> {
+    let removed = old
+        .keys()
+        .filter(|key| !new.contains_key(*key))
+        .map(|key| (key.as_str(), None));
+    let changed = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(value))
+        .map(|(key, value)| (key.as_str(), Some(value)));
+    removed.chain(changed).collect()
+}
+
+/// Calls `on_change` when the file at `path` may have changed.
+///
+/// The parent directory is watched since saves replace the file.
+pub(crate) fn watch(
+    path: &Path,
+    on_change: impl Fn() + Send + 'static,
+) -> notify::Result<StoreWatcher> {
+    let file_name = path.file_name().map(ToOwned::to_owned);
+    let mut debouncer = new_debouncer(DEBOUNCE, None, move |result: DebounceEventResult| {
+        let Ok(events) = result else {
+            return;
+        };
+        let changed = events.iter().any(|event| {
+            event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == file_name.as_deref())
+        });
+        if changed {
+            on_change();
+        }
+    })?;
+    if let Some(dir) = path.parent() {
+        debouncer.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+    Ok(debouncer)
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_store;
+    use serde_json::json;
+
+    fn state(policy: ConflictPolicy) -> WatchState {
+        let mut state = WatchState::new(policy);
+        state.synced(
+            &test_store(json!({ "theme": "light", "lang": "en", "volume": 5 })),
+            b"saved",
+        );
+        state
+    }
+
+    #[test]
+    fn ignores_its_own_writes() {
+        let state = state(ConflictPolicy::default());
+        assert!(!state.is_external_change(b"saved"));
+        assert!(state.is_external_change(b"edited"));
+    }
+
+    #[test]
+    fn merges_changes_to_different_keys() {
+        let local = test_store(json!({ "theme": "dark", "lang": "en", "volume": 5 }));
+        let external = test_store(json!({ "theme": "light", "lang": "fr", "muted": true }));
+        for policy in [ConflictPolicy::PreferLocal, ConflictPolicy::PreferExternal] {
+            assert_eq!(
+                state(policy).resolve(&local, &external),
+                test_store(json!({ "theme": "dark", "lang": "fr", "muted": true }))
+            );
+        }
+    }
+
+    #[test]
+    fn applies_the_policy_to_conflicts() {
+        let local = test_store(json!({ "theme": "dark", "lang": "en" }));
+        let external = test_store(json!({ "theme": "system", "lang": "en", "volume": 5 }));
+        assert_eq!(
+            state(ConflictPolicy::PreferLocal).resolve(&local, &external),
+            test_store(json!({ "theme": "dark", "lang": "en" }))
+        );
+        assert_eq!(
+            state(ConflictPolicy::PreferExternal).resolve(&local, &external),
+            test_store(json!({ "theme": "system", "lang": "en" }))
+        );
+        assert_eq!(
+            state(ConflictPolicy::DiscardLocal).resolve(&local, &external),
+            external
+        );
+    }
+
+    #[test]
+    fn lists_changed_keys() {
+        let old = test_store(json!({ "theme": "light", "lang": "en", "volume": 5 }));
+        let new = test_store(json!({ "theme": "dark", "lang": "en", "muted": true }));
+        let mut changes = changes(&old, &new);
+        changes.sort_by_key(|(key, _)| *key);
+        assert_eq!(
+            changes,
+            vec![
+                ("muted", Some(&json!(true))),
+                ("theme", Some(&json!("dark"))),
+                ("volume", None),
+            ]
+        );
+    }
+}
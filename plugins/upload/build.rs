@@ -2,10 +2,82 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-const COMMANDS: &[&str] = &["download", "upload"];
+#[path = "src/scope.rs"]
+#[allow(dead_code)]
+mod scope;
+
+const COMMANDS: &[&str] = &[
+    "download",
+    "upload",
+    "tus_upload",
+    "queue_download",
+    "queue_upload",
+    "pause_transfer",
+    "resume_transfer",
+    "cancel_transfer",
+    "remove_transfer",
+    "set_transfer_priority",
+    "list_transfers",
+];
+
+/// Upload scope entry.
+#[derive(schemars::JsonSchema)]
+#[serde(untagged)]
+#[allow(unused)]
+enum UploadScopeEntry {
+    /// A URL that can be used as the remote endpoint of uploads and downloads.
+    /// Wildcards can be used following the URL pattern standard.
+    ///
+    /// See [the URL Pattern spec](https://urlpattern.spec.whatwg.org/) for more information.
+    ///
+    /// Examples:
+    ///
+    /// - "https://*.example.com/*" : allows transfers to and from any subdomain of "example.com"
+    ///
+    /// - "https://uploads.example.com/users/*": allows transfers to any URL that begins with "https://uploads.example.com/users/"
+    Value(String),
+    Object {
+        /// A URL that can be used as the remote endpoint of uploads and downloads.
+        /// Wildcards can be used following the URL pattern standard.
+        ///
+        /// See [the URL Pattern spec](https://urlpattern.spec.whatwg.org/) for more information.
+        ///
+        /// Examples:
+        ///
+        /// - "https://*.example.com/*" : allows transfers to and from any subdomain of "example.com"
+        ///
+        /// - "https://uploads.example.com/users/*": allows transfers to any URL that begins with "https://uploads.example.com/users/"
+        url: String,
+    },
+    Path {
+        /// A local path that files can be uploaded from or downloaded to.
+        /// Wildcards and base directory variables such as `$DOWNLOAD` can be used,
+        /// like in the fs plugin scope.
+        ///
+        /// Examples:
+        ///
+        /// - "$DOWNLOAD/**": allows downloads to any file in the user's download directory
+        path: std::path::PathBuf,
+    },
+}
+
+// Ensure `UploadScopeEntry` and `scope::EntryRaw` is kept in sync
+fn _f() {
+    match scope::EntryRaw::Value(String::new()) {
+        scope::EntryRaw::Value(url) => UploadScopeEntry::Value(url),
+        scope::EntryRaw::Object { url } => UploadScopeEntry::Object { url },
+        scope::EntryRaw::Path { path } => UploadScopeEntry::Path { path },
+    };
+    match UploadScopeEntry::Value(String::new()) {
+        UploadScopeEntry::Value(url) => scope::EntryRaw::Value(url),
+        UploadScopeEntry::Object { url } => scope::EntryRaw::Object { url },
+        UploadScopeEntry::Path { path } => scope::EntryRaw::Path { path },
+    };
+}
 
 fn main() {
     tauri_plugin::Builder::new(COMMANDS)
         .global_api_script_path("./api-iife.js")
+        .global_scope_schema(schemars::schema_for!(UploadScopeEntry))
         .build();
 }
@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: MIT
 
 import { invoke, Channel } from '@tauri-apps/api/core'
+import { listen, type UnlistenFn } from '@tauri-apps/api/event'
 
 interface ProgressPayload {
   progress: number
@@ -13,11 +14,53 @@ interface ProgressPayload {
 
 type ProgressHandler = (progress: ProgressPayload) => void
 
+interface DownloadOptions {
+  /**
+   * Write the file to `<filePath>.part` until it completes and, if a previous
+   * download of the same URL was interrupted, request the missing bytes only.
+   *
+   * The partial file is kept on failure so the download can be resumed later,
+   * even after an app restart.
+   */
+  resume?: boolean
+  /** Verify the downloaded file against the given hex encoded digest. */
+  checksum?: {
+    algorithm: 'sha256' | 'sha512'
+    value: string
+  }
+}
+
+interface MultipartFile {
+  path: string
+  /** Defaults to the field name of the main file. */
+  fieldName?: string
+  /** Defaults to the name of the file on disk. */
+  fileName?: string
+  mimeType?: string
+}
+
+interface MultipartOptions {
+  /** Name of the form field holding the uploaded file. Defaults to `file`. */
+  fieldName?: string
+  /** Text fields sent before the files. */
+  fields?: Record<string, string>
+  /** Additional files sent in the same request. */
+  files?: MultipartFile[]
+}
+
+interface UploadOptions {
+  /** Defaults to `POST`. */
+  method?: 'POST' | 'PUT' | 'PATCH'
+  /** Send a `multipart/form-data` body instead of the raw file bytes. */
+  multipart?: MultipartOptions
+}
+
 async function upload(
   url: string,
   filePath: string,
   progressHandler?: ProgressHandler,
-  headers?: Map<string, string>
+  headers?: Map<string, string>,
+  options?: UploadOptions
 ): Promise<string> {
   const ids = new Uint32Array(1)
   window.crypto.getRandomValues(ids)
@@ -33,7 +76,53 @@ async function upload(
     url,
     filePath,
     headers: headers ?? {},
-    onProgress
+    onProgress,
+    options
+  })
+}
+
+interface TusOptions {
+  /** Maximum size of the body of each `PATCH` request. Defaults to the whole file. */
+  chunkSize?: number
+  /**
+   * Sent in the `Upload-Metadata` header when creating the upload.
+   * The file name is added as `filename` unless already set.
+   */
+  metadata?: Record<string, string>
+  /**
+   * URL of an upload created by a previous attempt, to resume it.
+   * Relative URLs are resolved against the endpoint, and the result must be allowed by the scope.
+   *
+   * The URLs of unfinished uploads are also remembered in the app data directory,
+   * so uploading the same file to the same endpoint again resumes it automatically.
+   */
+  uploadUrl?: string
+  /** Delays in milliseconds before retrying a failed request. Defaults to `[0, 1000, 3000, 5000]`. */
+  retryDelays?: number[]
+}
+
+/// Upload a file with the tus resumable upload protocol.
+///
+/// Interrupted uploads are resumed from the last byte the server received.
+/// Resolves to the URL of the upload.
+async function tusUpload(
+  url: string,
+  filePath: string,
+  progressHandler?: ProgressHandler,
+  headers?: Map<string, string>,
+  options?: TusOptions
+): Promise<string> {
+  const onProgress = new Channel<ProgressPayload>()
+  if (progressHandler) {
+    onProgress.onmessage = progressHandler
+  }
+
+  return await invoke('plugin:upload|tus_upload', {
+    url,
+    filePath,
+    headers: headers ?? {},
+    onProgress,
+    options
   })
 }
 
@@ -45,7 +134,8 @@ async function download(
   url: string,
   filePath: string,
   progressHandler?: ProgressHandler,
-  headers?: Map<string, string>
+  headers?: Map<string, string>,
+  options?: DownloadOptions
 ): Promise<void> {
   const ids = new Uint32Array(1)
   window.crypto.getRandomValues(ids)
@@ -61,8 +151,182 @@ async function download(
     url,
     filePath,
     headers: headers ?? {},
-    onProgress
+    onProgress,
+    options
   })
 }
 
-export { download, upload }
+type TransferState =
+  | 'queued'
+  | 'running'
+  | 'paused'
+  | 'completed'
+  | 'failed'
+  | 'canceled'
+
+type TransferRequest =
+  | {
+      type: 'download'
+      url: string
+      filePath: string
+      checksum: DownloadOptions['checksum'] | null
+    }
+  | {
+      type: 'upload'
+      url: string
+      filePath: string
+      options: UploadOptions
+    }
+
+/** A transfer managed by the background queue. */
+interface Transfer {
+  id: number
+  request: TransferRequest
+  /** Transfers with a higher priority are started first. */
+  priority: number
+  state: TransferState
+  error: string | null
+  /** The response body of a completed upload. */
+  response: string | null
+  transferred: number
+  total: number
+  transferSpeed: number
+  /**
+   * Whether the transfer was queued with headers. Headers are not saved nor listed,
+   * so a transfer restored on launch stays paused until it is resumed with them again.
+   */
+  hasHeaders: boolean
+  /** The label of the webview that queued the transfer. */
+  webview: string
+}
+
+/** Aggregated progress of all unfinished transfers in the queue. */
+interface QueueProgress {
+  transferred: number
+  total: number
+  transferSpeed: number
+  running: number
+  queued: number
+  transfers: Array<{
+    id: number
+    transferred: number
+    total: number
+    transferSpeed: number
+  }>
+}
+
+interface QueueOptions {
+  headers?: Record<string, string>
+  priority?: number
+}
+
+/// Add a download to the background transfer queue.
+///
+/// Queued downloads are always resumable, so pausing one keeps the data downloaded so far.
+async function queueDownload(
+  url: string,
+  filePath: string,
+  options?: QueueOptions & Pick<DownloadOptions, 'checksum'>
+): Promise<number> {
+  return await invoke('plugin:upload|queue_download', {
+    url,
+    filePath,
+    headers: options?.headers,
+    checksum: options?.checksum,
+    priority: options?.priority
+  })
+}
+
+/// Add an upload to the background transfer queue.
+async function queueUpload(
+  url: string,
+  filePath: string,
+  options?: QueueOptions & UploadOptions
+): Promise<number> {
+  return await invoke('plugin:upload|queue_upload', {
+    url,
+    filePath,
+    headers: options?.headers,
+    options: {
+      method: options?.method,
+      multipart: options?.multipart
+    },
+    priority: options?.priority
+  })
+}
+
+async function pauseTransfer(id: number): Promise<void> {
+  await invoke('plugin:upload|pause_transfer', { id })
+}
+
+/// Queue a paused or failed transfer again, replacing its headers if any are given.
+async function resumeTransfer(
+  id: number,
+  headers?: Record<string, string>
+): Promise<void> {
+  await invoke('plugin:upload|resume_transfer', { id, headers })
+}
+
+async function cancelTransfer(id: number): Promise<void> {
+  await invoke('plugin:upload|cancel_transfer', { id })
+}
+
+/// Remove a transfer from the queue, canceling it first if it is not finished.
+async function removeTransfer(id: number): Promise<void> {
+  await invoke('plugin:upload|remove_transfer', { id })
+}
+
+async function setTransferPriority(id: number, priority: number): Promise<void> {
+  await invoke('plugin:upload|set_transfer_priority', { id, priority })
+}
+
+async function listTransfers(): Promise<Transfer[]> {
+  return await invoke('plugin:upload|list_transfers')
+}
+
+/// Listen to state changes of the queued transfers.
+async function onTransferChange(
+  cb: (transfer: Transfer) => void
+): Promise<UnlistenFn> {
+  return await listen<Transfer>('upload://transfer', (event) => {
+    cb(event.payload)
+  })
+}
+
+/// Listen to the aggregated progress of the transfer queue.
+async function onQueueProgress(
+  cb: (progress: QueueProgress) => void
+): Promise<UnlistenFn> {
+  return await listen<QueueProgress>('upload://progress', (event) => {
+    cb(event.payload)
+  })
+}
+
+export type {
+  DownloadOptions,
+  MultipartFile,
+  MultipartOptions,
+  ProgressPayload,
+  QueueOptions,
+  QueueProgress,
+  Transfer,
+  TransferRequest,
+  TransferState,
+  TusOptions,
+  UploadOptions
+}
+export {
+  download,
+  upload,
+  tusUpload,
+  queueDownload,
+  queueUpload,
+  pauseTransfer,
+  resumeTransfer,
+  cancelTransfer,
+  removeTransfer,
+  setTransferPriority,
+  listTransfers,
+  onTransferChange,
+  onQueueProgress
+}
@@ -0,0 +1,54 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use tokio::{fs::File, io::AsyncReadExt};
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ChecksumAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+/// Expected digest of a downloaded file, as a hex string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Checksum {
+    algorithm: ChecksumAlgorithm,
+    value: String,
+}
+
+impl Checksum {
+    /// Hashes the file at `path` and compares it against the expected digest.
+    pub async fn verify(&self, path: &Path) -> Result<()> {
+        let actual = match self.algorithm {
+            ChecksumAlgorithm::Sha256 => digest_file::<Sha256>(path).await?,
+            ChecksumAlgorithm::Sha512 => digest_file::<Sha512>(path).await?,
+        };
+        if actual.eq_ignore_ascii_case(self.value.trim()) {
+            Ok(())
+        } else {
+            Err(Error::ChecksumMismatch(self.value.clone(), actual))
+        }
+    }
+}
+
+async fn digest_file<D: Digest>(path: &Path) -> Result<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = D::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
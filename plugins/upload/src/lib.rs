@@ -11,26 +11,42 @@
     html_favicon_url = "https://github.com/tauri-apps/tauri/raw/dev/app-icon.png"
 )]
 
+mod checksum;
+mod manager;
+mod multipart;
+mod partial;
+mod scope;
 mod transfer_stats;
+mod tus;
+use checksum::Checksum;
+use multipart::MultipartOptions;
+use partial::PartialDownload;
+use scope::{Entry, Scope};
 use transfer_stats::TransferStats;
 
 use futures_util::TryStreamExt;
-use serde::{ser::Serializer, Serialize};
+use serde::{ser::Serializer, Deserialize, Serialize};
 use tauri::{
     command,
-    ipc::Channel,
+    ipc::{Channel, CommandScope, GlobalScope},
     plugin::{Builder as PluginBuilder, TauriPlugin},
-    Runtime,
+    utils::config::FsScope,
+    AppHandle, Manager, Runtime,
 };
+use tauri_plugin_fs::FsExt;
 use tokio::{
-    fs::File,
+    fs::{File, OpenOptions},
     io::{AsyncWriteExt, BufWriter},
 };
 use tokio_util::codec::{BytesCodec, FramedRead};
 
 use read_progress_stream::ReadProgressStream;
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use url::Url;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -44,6 +60,28 @@ pub enum Error {
     ContentLength(String),
     #[error("request failed with status code {0}: {1}")]
     HttpErrorCode(u16, String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("invalid Content-Range header: {0}")]
+    InvalidContentRange(String),
+    #[error("expected {0} bytes but the downloaded file has {1} bytes")]
+    SizeMismatch(u64, u64),
+    #[error("checksum mismatch, expected {0} but got {1}")]
+    ChecksumMismatch(String, String),
+    #[error("transfer not found for the given id: {0}")]
+    TransferNotFound(manager::TransferId),
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error(transparent)]
+    UrlParse(#[from] url::ParseError),
+    /// URL not allowed by the scope.
+    #[error("url not allowed on the configured scope: {0}")]
+    UrlNotAllowed(Url),
+    #[error("invalid tus response: {0}")]
+    InvalidTusResponse(String),
+    /// Path not allowed by the scope.
+    #[error("forbidden path: {0}")]
+    PathForbidden(PathBuf),
 }
 
 impl Serialize for Error {
@@ -64,13 +102,148 @@ struct ProgressPayload {
     transfer_speed: u64,
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadOptions {
+    /// Write to `<filePath>.part` and resume it with a `Range` request
+    /// if a previous attempt was interrupted.
+    #[serde(default)]
+    resume: bool,
+    /// Verify the downloaded file against this digest.
+    checksum: Option<Checksum>,
+}
+
+impl DownloadOptions {
+    /// All the local files written by a download to `file_path`.
+    fn paths(&self, file_path: &Path) -> Vec<PathBuf> {
+        let mut paths = vec![file_path.to_path_buf()];
+        if self.resume {
+            paths.extend(PartialDownload::paths(file_path));
+        }
+        paths
+    }
+}
+
+/// Ensures a transfer only targets URLs and local paths allowed by the plugin scope.
+///
+/// Paths allowed at runtime on the fs plugin scope, e.g. picked with the dialog plugin, are allowed too.
+fn check_scope<'a, R: Runtime>(
+    app: &AppHandle<R>,
+    command_scope: &CommandScope<Entry>,
+    global_scope: &GlobalScope<Entry>,
+    url: &str,
+    paths: impl IntoIterator<Item = &'a Path>,
+) -> Result<()> {
+    let url = Url::parse(url)?;
+    let scope = url_scope(command_scope, global_scope);
+    if !scope.is_allowed(&url) {
+        return Err(Error::UrlNotAllowed(url));
+    }
+
+    let mut allow = scope.allowed_paths();
+    let mut deny = scope.denied_paths();
+    if let Some(fs_scope) = app.try_fs_scope() {
+        allow.extend(fs_scope.allowed());
+        deny.extend(fs_scope.forbidden());
+    }
+    let path_scope = tauri::scope::fs::Scope::new(
+        app,
+        &FsScope::Scope {
+            allow,
+            deny,
+            require_literal_leading_dot: require_literal_leading_dot(app),
+        },
+    )?;
+    for path in paths {
+        if !path_scope.is_allowed(path) {
+            return Err(Error::PathForbidden(path.to_path_buf()));
+        }
+    }
+
+    Ok(())
+}
+
+/// The `requireLiteralLeadingDot` option of the fs plugin configuration.
+fn require_literal_leading_dot<R: Runtime>(app: &AppHandle<R>) -> Option<bool> {
+    app.config()
+        .plugins
+        .0
+        .get("fs")?
+        .get("requireLiteralLeadingDot")?
+        .as_bool()
+}
+
+/// The scope of a command merged with the global scope of the plugin.
+fn url_scope<'a>(
+    command_scope: &'a CommandScope<Entry>,
+    global_scope: &'a GlobalScope<Entry>,
+) -> Scope<'a> {
+    Scope::new(
+        command_scope
+            .allows()
+            .iter()
+            .chain(global_scope.allows())
+            .collect(),
+        command_scope
+            .denies()
+            .iter()
+            .chain(global_scope.denies())
+            .collect(),
+    )
+}
+
 #[command]
-async fn download(
+#[allow(clippy::too_many_arguments)]
+async fn download<R: Runtime>(
+    app: AppHandle<R>,
     url: &str,
     file_path: &str,
     headers: HashMap<String, String>,
     on_progress: Channel<ProgressPayload>,
+    options: Option<DownloadOptions>,
+    command_scope: CommandScope<Entry>,
+    global_scope: GlobalScope<Entry>,
+) -> Result<()> {
+    let options = options.unwrap_or_default();
+    check_scope(
+        &app,
+        &command_scope,
+        &global_scope,
+        url,
+        options
+            .paths(Path::new(file_path))
+            .iter()
+            .map(PathBuf::as_path),
+    )?;
+    run_download(
+        url,
+        Path::new(file_path),
+        headers,
+        options,
+        move |payload| {
+            let _ = on_progress.send(payload);
+        },
+    )
+    .await
+}
+
+async fn run_download(
+    url: &str,
+    file_path: &Path,
+    headers: HashMap<String, String>,
+    options: DownloadOptions,
+    on_progress: impl Fn(ProgressPayload),
 ) -> Result<()> {
+    let mut partial = if options.resume {
+        Some(PartialDownload::open(file_path, url).await?)
+    } else {
+        None
+    };
+
+    if partial.as_ref().is_some_and(PartialDownload::is_complete) {
+        return finish_download(file_path, partial, options.checksum.as_ref()).await;
+    }
+
     let client = reqwest::Client::new();
 
     let mut request = client.get(url);
@@ -80,6 +253,10 @@ async fn download(
         request = request.header(&key, value);
     }
 
+    if let Some(partial) = &partial {
+        request = partial.apply_range(request);
+    }
+
     let response = request.send().await?;
     if !response.status().is_success() {
         return Err(Error::HttpErrorCode(
@@ -87,44 +264,165 @@ async fn download(
             response.text().await.unwrap_or_default(),
         ));
     }
-    let total = response.content_length().unwrap_or(0);
 
-    let mut file = BufWriter::new(File::create(file_path).await?);
+    let (offset, total, file) = match &mut partial {
+        Some(partial) => {
+            let (offset, total) = partial.accept(url, &response).await?;
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(offset > 0)
+                .truncate(offset == 0)
+                .open(&partial.part_path)
+                .await?;
+            (offset, total, file)
+        }
+        None => (0, response.content_length(), File::create(file_path).await?),
+    };
+
+    let mut file = BufWriter::new(file);
     let mut stream = response.bytes_stream();
 
     let mut stats = TransferStats::default();
     while let Some(chunk) = stream.try_next().await? {
         file.write_all(&chunk).await?;
         stats.record_chunk_transfer(chunk.len());
-        let _ = on_progress.send(ProgressPayload {
+        on_progress(ProgressPayload {
             progress: chunk.len() as u64,
-            progress_total: stats.total_transferred,
-            total,
+            progress_total: offset + stats.total_transferred,
+            total: total.unwrap_or(0),
             transfer_speed: stats.transfer_speed,
         });
     }
     file.flush().await?;
 
+    let downloaded = offset + stats.total_transferred;
+    if let Some(total) = total.filter(|total| *total != downloaded) {
+        return Err(Error::SizeMismatch(total, downloaded));
+    }
+
+    finish_download(file_path, partial, options.checksum.as_ref()).await
+}
+
+async fn finish_download(
+    file_path: &Path,
+    partial: Option<PartialDownload>,
+    checksum: Option<&Checksum>,
+) -> Result<()> {
+    if let Some(checksum) = checksum {
+        let path = partial
+            .as_ref()
+            .map_or(file_path, |p| p.part_path.as_path());
+        if let Err(e) = checksum.verify(path).await {
+            // the data on disk is corrupted, there's nothing to resume from
+            if let Some(partial) = partial {
+                partial.discard().await;
+            }
+            return Err(e);
+        }
+    }
+
+    if let Some(partial) = partial {
+        partial.finish(file_path).await?;
+    }
+
     Ok(())
 }
 
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum UploadMethod {
+    #[default]
+    Post,
+    Put,
+    Patch,
+}
+
+impl From<UploadMethod> for reqwest::Method {
+    fn from(method: UploadMethod) -> Self {
+        match method {
+            UploadMethod::Post => Self::POST,
+            UploadMethod::Put => Self::PUT,
+            UploadMethod::Patch => Self::PATCH,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadOptions {
+    #[serde(default)]
+    method: UploadMethod,
+    /// Send a `multipart/form-data` body instead of the raw file bytes.
+    multipart: Option<MultipartOptions>,
+}
+
+impl UploadOptions {
+    /// All the local files read by an upload of `file_path`.
+    fn paths<'a>(&'a self, file_path: &'a Path) -> impl Iterator<Item = &'a Path> {
+        std::iter::once(file_path).chain(
+            self.multipart
+                .iter()
+                .flat_map(|multipart| multipart.additional_paths()),
+        )
+    }
+}
+
 #[command]
-async fn upload(
+#[allow(clippy::too_many_arguments)]
+async fn upload<R: Runtime>(
+    app: AppHandle<R>,
     url: &str,
     file_path: &str,
     headers: HashMap<String, String>,
     on_progress: Channel<ProgressPayload>,
+    options: Option<UploadOptions>,
+    command_scope: CommandScope<Entry>,
+    global_scope: GlobalScope<Entry>,
 ) -> Result<String> {
-    // Read the file
-    let file = File::open(file_path).await?;
-    let file_len = file.metadata().await.unwrap().len();
+    let options = options.unwrap_or_default();
+    check_scope(
+        &app,
+        &command_scope,
+        &global_scope,
+        url,
+        options.paths(Path::new(file_path)),
+    )?;
+    run_upload(
+        url,
+        Path::new(file_path),
+        headers,
+        options,
+        move |payload| {
+            let _ = on_progress.send(payload);
+        },
+    )
+    .await
+}
 
-    // Create the request and attach the file to the body
+async fn run_upload(
+    url: &str,
+    file_path: &Path,
+    headers: HashMap<String, String>,
+    options: UploadOptions,
+    on_progress: impl Fn(ProgressPayload) + Send + Sync + 'static,
+) -> Result<String> {
     let client = reqwest::Client::new();
-    let mut request = client
-        .post(url)
-        .header(reqwest::header::CONTENT_LENGTH, file_len)
-        .body(file_to_body(on_progress, file));
+    let mut request = client.request(options.method.into(), url);
+
+    request = match options.multipart {
+        Some(multipart) => request.multipart(multipart.into_form(file_path, on_progress).await?),
+        None => {
+            // Read the file
+            let file = File::open(file_path).await?;
+            let file_len = file.metadata().await?.len();
+
+            // Attach the file to the body
+            request
+                .header(reqwest::header::CONTENT_LENGTH, file_len)
+                .body(file_to_body(on_progress, file))
+        }
+    };
 
     // Loop through the headers keys and values
     // and add them to the request object.
@@ -143,7 +441,10 @@ async fn upload(
     }
 }
 
-fn file_to_body(channel: Channel<ProgressPayload>, file: File) -> reqwest::Body {
+fn file_to_body(
+    on_progress: impl Fn(ProgressPayload) + Send + Sync + 'static,
+    file: File,
+) -> reqwest::Body {
     let stream = FramedRead::new(file, BytesCodec::new()).map_ok(|r| r.freeze());
 
     let mut stats = TransferStats::default();
@@ -151,7 +452,7 @@ fn file_to_body(channel: Channel<ProgressPayload>, file: File) -> reqwest::Body
         stream,
         Box::new(move |progress, total| {
             stats.record_chunk_transfer(progress as usize);
-            let _ = channel.send(ProgressPayload {
+            on_progress(ProgressPayload {
                 progress,
                 progress_total: stats.total_transferred,
                 total,
@@ -161,17 +462,87 @@ fn file_to_body(channel: Channel<ProgressPayload>, file: File) -> reqwest::Body
     ))
 }
 
+const QUEUE_FILE: &str = "upload-queue.json";
+
 pub fn init<R: Runtime>() -> TauriPlugin<R> {
-    PluginBuilder::new("upload")
-        .invoke_handler(tauri::generate_handler![download, upload])
-        .build()
+    Builder::default().build()
+}
+
+pub struct Builder {
+    max_concurrent_transfers: usize,
+    persist_queue: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            max_concurrent_transfers: 3,
+            persist_queue: true,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of queued transfers running at the same time. Defaults to 3.
+    pub fn max_concurrent_transfers(mut self, max: usize) -> Self {
+        self.max_concurrent_transfers = max;
+        self
+    }
+
+    /// Whether unfinished queued transfers are saved in the app data directory
+    /// and restarted on the next launch. Enabled by default.
+    pub fn persist_queue(mut self, persist: bool) -> Self {
+        self.persist_queue = persist;
+        self
+    }
+
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        PluginBuilder::new("upload")
+            .invoke_handler(tauri::generate_handler![
+                download,
+                upload,
+                tus::tus_upload,
+                manager::queue_download,
+                manager::queue_upload,
+                manager::pause_transfer,
+                manager::resume_transfer,
+                manager::cancel_transfer,
+                manager::remove_transfer,
+                manager::set_transfer_priority,
+                manager::list_transfers,
+            ])
+            .setup(move |app, _api| {
+                let queue_path = if self.persist_queue {
+                    Some(app.path().app_data_dir()?.join(QUEUE_FILE))
+                } else {
+                    None
+                };
+                app.manage(manager::TransferManager::new(
+                    app.clone(),
+                    self.max_concurrent_transfers,
+                    queue_path,
+                ));
+                app.state::<manager::TransferManager<R>>().schedule();
+                Ok(())
+            })
+            .on_webview_ready(|webview| {
+                // start the restored transfers queued by this webview
+                if let Some(manager) = webview.try_state::<manager::TransferManager<R>>() {
+                    manager.schedule();
+                }
+            })
+            .build()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use mockito::{self, Mock, Server, ServerGuard};
-    use tauri::ipc::InvokeResponseBody;
     struct MockedServer {
         _server: ServerGuard,
         url: String,
@@ -198,15 +569,162 @@ mod tests {
         );
     }
 
+    #[test]
+    fn resumable_downloads_check_their_partial_files() {
+        let file_path = Path::new("/downloads/model.bin");
+        assert_eq!(
+            DownloadOptions::default().paths(file_path),
+            [PathBuf::from("/downloads/model.bin")]
+        );
+        let options = DownloadOptions {
+            resume: true,
+            checksum: None,
+        };
+        assert_eq!(
+            options.paths(file_path),
+            [
+                PathBuf::from("/downloads/model.bin"),
+                PathBuf::from("/downloads/model.bin.part"),
+                PathBuf::from("/downloads/model.bin.part.json"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_resume_partial_download() {
+        let mut server = Server::new_async().await;
+        let url = server.url() + "/resume_test";
+        let mock = server
+            .mock("GET", "/resume_test")
+            .match_header("range", "bytes=7-")
+            .match_header("if-range", "\"v1\"")
+            .with_status(206)
+            .with_header("content-range", "bytes 7-19/20")
+            .with_header("etag", "\"v1\"")
+            .with_body("response body")
+            .create_async()
+            .await;
+
+        let dir = test_dir("resume");
+        let file_path = dir.join("file.txt");
+        write_partial(&file_path, &url, b"mocked ");
+
+        let options = serde_json::from_value(serde_json::json!({
+            "resume": true,
+            "checksum": {
+                "algorithm": "sha256",
+                "value": "a39794a5c609f107c559cde40af4298290b0bc5223db47926eb6a05fdac537c9"
+            }
+        }))
+        .unwrap();
+        let result = run_download(&url, &file_path, HashMap::new(), options, |_| {}).await;
+        mock.assert_async().await;
+        assert!(result.is_ok(), "failed to resume download: {result:?}");
+        assert_eq!(
+            std::fs::read_to_string(&file_path).unwrap(),
+            "mocked response body"
+        );
+        assert!(!dir.join("file.txt.part").exists());
+        assert!(!dir.join("file.txt.part.json").exists());
+    }
+
+    #[tokio::test]
+    async fn should_restart_download_if_range_is_ignored() {
+        let mut server = Server::new_async().await;
+        let url = server.url() + "/restart_test";
+        let mock = server
+            .mock("GET", "/restart_test")
+            .with_status(200)
+            .with_header("etag", "\"v2\"")
+            .with_body("mocked response body")
+            .create_async()
+            .await;
+
+        let dir = test_dir("restart");
+        let file_path = dir.join("file.txt");
+        write_partial(&file_path, &url, b"stale data");
+
+        let options = DownloadOptions {
+            resume: true,
+            checksum: None,
+        };
+        let result = run_download(&url, &file_path, HashMap::new(), options, |_| {}).await;
+        mock.assert_async().await;
+        assert!(result.is_ok(), "failed to download file: {result:?}");
+        assert_eq!(
+            std::fs::read_to_string(&file_path).unwrap(),
+            "mocked response body"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_upload_multipart_form() {
+        let mut server = Server::new_async().await;
+        let url = server.url() + "/multipart_test";
+        let mock = server
+            .mock("PUT", "/multipart_test")
+            .match_header(
+                "content-type",
+                mockito::Matcher::Regex("^multipart/form-data; boundary=".into()),
+            )
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::Regex(r#"name="key"\r\n\r\nuploads/a.txt\r\n"#.into()),
+                mockito::Matcher::Regex(
+                    r#"name="document"; filename="a.txt"\r\n\r\nfirst file\r\n"#.into(),
+                ),
+                mockito::Matcher::Regex(
+                    r#"name="attachment"; filename="b.json"\r\nContent-Type: application/json\r\n\r\n\{\}\r\n"#
+                        .into(),
+                ),
+            ]))
+            .with_status(201)
+            .with_body("created")
+            .create_async()
+            .await;
+
+        let dir = test_dir("multipart");
+        std::fs::write(dir.join("a.txt"), "first file").unwrap();
+        std::fs::write(dir.join("b.txt"), "{}").unwrap();
+
+        let options = serde_json::from_value(serde_json::json!({
+            "method": "PUT",
+            "multipart": {
+                "fieldName": "document",
+                "fields": { "key": "uploads/a.txt" },
+                "files": [{
+                    "path": dir.join("b.txt"),
+                    "fieldName": "attachment",
+                    "fileName": "b.json",
+                    "mimeType": "application/json"
+                }]
+            }
+        }))
+        .unwrap();
+        let result = run_upload(&url, &dir.join("a.txt"), HashMap::new(), options, |_| {}).await;
+        mock.assert_async().await;
+        assert_eq!(result.unwrap(), "created");
+    }
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("tauri-plugin-upload-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_partial(file_path: &Path, url: &str, data: &[u8]) {
+        let mut part = file_path.as_os_str().to_owned();
+        part.push(".part");
+        std::fs::write(&part, data).unwrap();
+        part.push(".json");
+        let metadata = serde_json::json!({ "url": url, "etag": "\"v1\"", "total": 20 });
+        std::fs::write(&part, metadata.to_string()).unwrap();
+    }
+
     async fn download_file(url: &str) -> Result<()> {
-        let file_path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/test.txt");
+        let file_path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test/test.txt"));
         let headers = HashMap::new();
-        let sender: Channel<ProgressPayload> =
-            Channel::new(|msg: InvokeResponseBody| -> tauri::Result<()> {
-                let _ = msg;
-                Ok(())
-            });
-        download(url, file_path, headers, sender).await
+        run_download(url, file_path, headers, DownloadOptions::default(), |_| {}).await
     }
 
     async fn spawn_server_mocked(return_status: usize) -> MockedServer {
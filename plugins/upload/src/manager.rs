@@ -0,0 +1,1012 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! A queue of transfers running in the background with a concurrency limit.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{
+    async_runtime::JoinHandle,
+    command,
+    ipc::{CommandScope, GlobalScope},
+    AppHandle, Emitter, Manager, Runtime, Webview,
+};
+
+use crate::{
+    check_scope, run_download, run_upload, scope::Entry, Checksum, DownloadOptions, Error,
+    PartialDownload, ProgressPayload, Result, UploadOptions,
+};
+
+const TRANSFER_EVENT: &str = "upload://transfer";
+const PROGRESS_EVENT: &str = "upload://progress";
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+pub type TransferId = u32;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub(crate) enum TransferRequest {
+    #[serde(rename_all = "camelCase")]
+    Download {
+        url: String,
+        file_path: PathBuf,
+        /// Not saved nor sent to the webviews, as they may hold credentials.
+        #[serde(skip)]
+        headers: HashMap<String, String>,
+        checksum: Option<Checksum>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Upload {
+        url: String,
+        file_path: PathBuf,
+        #[serde(skip)]
+        headers: HashMap<String, String>,
+        #[serde(default)]
+        options: UploadOptions,
+    },
+}
+
+impl TransferRequest {
+    fn headers_mut(&mut self) -> &mut HashMap<String, String> {
+        match self {
+            Self::Download { headers, .. } | Self::Upload { headers, .. } => headers,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum TransferState {
+    Queued,
+    Running,
+    Paused,
+    Completed,
+    Failed,
+    Canceled,
+}
+
+impl TransferState {
+    fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Canceled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Transfer {
+    id: TransferId,
+    request: TransferRequest,
+    /// Transfers with a higher priority are started first.
+    priority: i32,
+    state: TransferState,
+    error: Option<String>,
+    /// The response body of a completed upload.
+    response: Option<String>,
+    transferred: u64,
+    total: u64,
+    #[serde(skip_deserializing)]
+    transfer_speed: u64,
+    /// Whether the transfer was queued with headers. They aren't saved, so a restored transfer
+    /// stays paused until it is resumed with them again.
+    #[serde(default)]
+    has_headers: bool,
+    /// The label of the webview that queued the transfer, whose scope it is checked against.
+    #[serde(default)]
+    webview: String,
+    /// Whether the transfer was checked against the scope since it was queued,
+    /// which restored transfers must be again before starting.
+    #[serde(skip)]
+    verified: bool,
+    /// Incremented every time the transfer starts, so a task stopped by a pause
+    /// can't update the transfer once it runs again.
+    #[serde(skip)]
+    generation: u32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TransferProgress {
+    id: TransferId,
+    transferred: u64,
+    total: u64,
+    transfer_speed: u64,
+}
+
+/// Progress of all unfinished transfers.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QueueProgress {
+    transferred: u64,
+    total: u64,
+    transfer_speed: u64,
+    running: usize,
+    queued: usize,
+    transfers: Vec<TransferProgress>,
+}
+
+/// The unfinished transfers at a revision of the queue, to be saved once it is unlocked.
+struct Snapshot {
+    revision: u64,
+    transfers: Vec<Transfer>,
+}
+
+#[derive(Default)]
+struct Queue {
+    transfers: Vec<Transfer>,
+    tasks: HashMap<TransferId, JoinHandle<()>>,
+    next_id: TransferId,
+    last_progress: Option<Instant>,
+    /// Incremented on every snapshot, so an older one is never saved over a newer one.
+    revision: u64,
+}
+
+impl Queue {
+    /// Restores the unfinished transfers saved on the previous launch.
+    fn restore(mut transfers: Vec<Transfer>) -> Self {
+        transfers.retain(|t| !t.state.is_finished());
+        for transfer in &mut transfers {
+            // interrupted by the app exit
+            if transfer.state == TransferState::Running {
+                transfer.state = TransferState::Queued;
+            }
+            if transfer.has_headers && transfer.state == TransferState::Queued {
+                transfer.state = TransferState::Paused;
+            }
+        }
+        Self {
+            next_id: transfers.iter().map(|t| t.id + 1).max().unwrap_or(0),
+            transfers,
+            ..Default::default()
+        }
+    }
+
+    fn get(&self, id: TransferId) -> Result<&Transfer> {
+        self.transfers
+            .iter()
+            .find(|t| t.id == id)
+            .ok_or(Error::TransferNotFound(id))
+    }
+
+    fn get_mut(&mut self, id: TransferId) -> Result<&mut Transfer> {
+        self.transfers
+            .iter_mut()
+            .find(|t| t.id == id)
+            .ok_or(Error::TransferNotFound(id))
+    }
+
+    fn push(&mut self, mut request: TransferRequest, priority: i32, webview: String) -> &Transfer {
+        let id = self.next_id;
+        self.next_id += 1;
+        let has_headers = !request.headers_mut().is_empty();
+        self.transfers.push(Transfer {
+            id,
+            request,
+            priority,
+            state: TransferState::Queued,
+            error: None,
+            response: None,
+            transferred: 0,
+            total: 0,
+            transfer_speed: 0,
+            has_headers,
+            webview,
+            verified: true,
+            generation: 0,
+        });
+        &self.transfers[self.transfers.len() - 1]
+    }
+
+    fn abort(&mut self, id: TransferId) {
+        if let Some(task) = self.tasks.remove(&id) {
+            task.abort();
+        }
+    }
+
+    /// Stops a queued or running transfer, returning whether it was changed.
+    fn pause(&mut self, id: TransferId) -> Result<bool> {
+        let transfer = self.get_mut(id)?;
+        if !matches!(
+            transfer.state,
+            TransferState::Queued | TransferState::Running
+        ) {
+            return Ok(false);
+        }
+        transfer.state = TransferState::Paused;
+        transfer.transfer_speed = 0;
+        self.abort(id);
+        Ok(true)
+    }
+
+    /// Queues a paused or failed transfer again, with new headers if any are given,
+    /// returning whether it was changed.
+    fn resume(&mut self, id: TransferId, headers: Option<HashMap<String, String>>) -> Result<bool> {
+        let transfer = self.get_mut(id)?;
+        if !matches!(
+            transfer.state,
+            TransferState::Paused | TransferState::Failed
+        ) {
+            return Ok(false);
+        }
+        if let Some(headers) = headers {
+            transfer.has_headers = !headers.is_empty();
+            *transfer.request.headers_mut() = headers;
+        }
+        transfer.state = TransferState::Queued;
+        transfer.error = None;
+        Ok(true)
+    }
+
+    /// Stops a transfer for good, returning its request if it was changed.
+    fn cancel(&mut self, id: TransferId) -> Result<Option<TransferRequest>> {
+        let transfer = self.get_mut(id)?;
+        if transfer.state.is_finished() {
+            return Ok(None);
+        }
+        transfer.state = TransferState::Canceled;
+        transfer.transfer_speed = 0;
+        let request = transfer.request.clone();
+        self.abort(id);
+        Ok(Some(request))
+    }
+
+    /// Removes a transfer from the queue, stopping it if it is running.
+    fn remove(&mut self, id: TransferId) -> Result<Transfer> {
+        let index = self
+            .transfers
+            .iter()
+            .position(|t| t.id == id)
+            .ok_or(Error::TransferNotFound(id))?;
+        self.abort(id);
+        Ok(self.transfers.remove(index))
+    }
+
+    /// Marks the queued transfer with the highest priority as running if the concurrency limit allows it,
+    /// returning it to be started.
+    fn start_next(&mut self, max_concurrent_transfers: usize) -> Option<&Transfer> {
+        let running = self
+            .transfers
+            .iter()
+            .filter(|t| t.state == TransferState::Running)
+            .count();
+        if running >= max_concurrent_transfers {
+            return None;
+        }
+        let transfer = self
+            .transfers
+            .iter_mut()
+            .filter(|t| t.state == TransferState::Queued && t.verified)
+            .max_by(|a, b| a.priority.cmp(&b.priority).then(b.id.cmp(&a.id)))?;
+        transfer.state = TransferState::Running;
+        transfer.generation = transfer.generation.wrapping_add(1);
+        Some(transfer)
+    }
+
+    /// Records the result of the task started for the `generation` of a transfer,
+    /// returning the transfer unless it was started again since.
+    fn finish(
+        &mut self,
+        id: TransferId,
+        generation: u32,
+        result: Result<Option<String>>,
+    ) -> Option<&Transfer> {
+        let transfer = self.get_mut(id).ok()?;
+        if transfer.generation != generation {
+            return None;
+        }
+        transfer.transfer_speed = 0;
+        match result {
+            // the transfer may have completed right before being paused
+            Ok(response) if transfer.state != TransferState::Canceled => {
+                transfer.state = TransferState::Completed;
+                transfer.response = response;
+            }
+            Err(e) if transfer.state == TransferState::Running => {
+                transfer.state = TransferState::Failed;
+                transfer.error.replace(e.to_string());
+            }
+            _ => {}
+        }
+        self.tasks.remove(&id);
+        self.get(id).ok()
+    }
+
+    /// Records the progress reported by the task started for the `generation` of a transfer,
+    /// returning whether it is current.
+    fn record_progress(
+        &mut self,
+        id: TransferId,
+        generation: u32,
+        payload: &ProgressPayload,
+    ) -> bool {
+        let Ok(transfer) = self.get_mut(id) else {
+            return false;
+        };
+        if transfer.generation != generation {
+            return false;
+        }
+        transfer.transferred = payload.progress_total;
+        transfer.total = payload.total;
+        transfer.transfer_speed = payload.transfer_speed;
+        true
+    }
+
+    fn snapshot(&mut self) -> Snapshot {
+        self.revision += 1;
+        Snapshot {
+            revision: self.revision,
+            transfers: self
+                .transfers
+                .iter()
+                .filter(|t| !t.state.is_finished())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn progress(&self) -> QueueProgress {
+        let unfinished = self.transfers.iter().filter(|t| !t.state.is_finished());
+        let mut progress = QueueProgress {
+            transferred: 0,
+            total: 0,
+            transfer_speed: 0,
+            running: 0,
+            queued: 0,
+            transfers: Vec::new(),
+        };
+        for transfer in unfinished {
+            progress.transferred += transfer.transferred;
+            progress.total += transfer.total;
+            match transfer.state {
+                TransferState::Running => {
+                    progress.running += 1;
+                    progress.transfer_speed += transfer.transfer_speed;
+                }
+                TransferState::Queued => progress.queued += 1,
+                _ => {}
+            }
+            progress.transfers.push(TransferProgress {
+                id: transfer.id,
+                transferred: transfer.transferred,
+                total: transfer.total,
+                transfer_speed: transfer.transfer_speed,
+            });
+        }
+        progress
+    }
+}
+
+pub(crate) struct TransferManager<R: Runtime> {
+    app: AppHandle<R>,
+    max_concurrent_transfers: usize,
+    queue_path: Option<PathBuf>,
+    queue: Mutex<Queue>,
+    /// The revision of the last saved snapshot, locked while saving.
+    saved: Mutex<u64>,
+}
+
+impl<R: Runtime> TransferManager<R> {
+    /// Creates the manager, restoring the unfinished transfers saved at `queue_path`.
+    pub fn new(
+        app: AppHandle<R>,
+        max_concurrent_transfers: usize,
+        queue_path: Option<PathBuf>,
+    ) -> Self {
+        let queue = match queue_path.as_deref().and_then(load_queue) {
+            Some(transfers) => Queue::restore(transfers),
+            None => Queue::default(),
+        };
+
+        Self {
+            app,
+            max_concurrent_transfers: max_concurrent_transfers.max(1),
+            queue_path,
+            queue: Mutex::new(queue),
+            saved: Mutex::new(0),
+        }
+    }
+
+    /// Queues a transfer already checked against the scope of `webview`.
+    pub fn enqueue(&self, request: TransferRequest, priority: i32, webview: String) -> TransferId {
+        let mut queue = self.queue.lock().unwrap();
+        let transfer = queue.push(request, priority, webview).clone();
+        drop(queue);
+
+        let _ = self.app.emit(TRANSFER_EVENT, &transfer);
+        self.schedule();
+        transfer.id
+    }
+
+    /// Emits the change of a transfer and saves the queue once it is unlocked.
+    fn changed(&self, mut queue: MutexGuard<'_, Queue>, id: TransferId) {
+        let transfer = queue.get(id).ok().cloned();
+        let snapshot = self.snapshot(&mut queue);
+        drop(queue);
+
+        if let Some(transfer) = transfer {
+            let _ = self.app.emit(TRANSFER_EVENT, transfer);
+        }
+        self.save(snapshot);
+    }
+
+    /// Stops a queued or running transfer, keeping the downloaded data to resume from.
+    pub fn pause(&self, id: TransferId) -> Result<()> {
+        let mut queue = self.queue.lock().unwrap();
+        if !queue.pause(id)? {
+            return Ok(());
+        }
+        self.changed(queue, id);
+        self.schedule();
+        Ok(())
+    }
+
+    /// Queues a paused or failed transfer again, replacing its headers if any are given.
+    pub fn resume(&self, id: TransferId, headers: Option<HashMap<String, String>>) -> Result<()> {
+        let mut queue = self.queue.lock().unwrap();
+        if !queue.resume(id, headers)? {
+            return Ok(());
+        }
+        self.changed(queue, id);
+        self.schedule();
+        Ok(())
+    }
+
+    /// Stops a transfer for good, removing the partially downloaded data.
+    pub fn cancel(&self, id: TransferId) -> Result<()> {
+        let mut queue = self.queue.lock().unwrap();
+        let Some(request) = queue.cancel(id)? else {
+            return Ok(());
+        };
+        discard_download(request);
+        self.changed(queue, id);
+        self.schedule();
+        Ok(())
+    }
+
+    /// Removes a transfer from the queue, canceling it first if it is not finished.
+    pub fn remove(&self, id: TransferId) -> Result<()> {
+        let mut queue = self.queue.lock().unwrap();
+        let transfer = queue.remove(id)?;
+        if !transfer.state.is_finished() {
+            discard_download(transfer.request);
+        }
+        let snapshot = self.snapshot(&mut queue);
+        drop(queue);
+
+        self.save(snapshot);
+        self.schedule();
+        Ok(())
+    }
+
+    pub fn set_priority(&self, id: TransferId, priority: i32) -> Result<()> {
+        let mut queue = self.queue.lock().unwrap();
+        queue.get_mut(id)?.priority = priority;
+        self.changed(queue, id);
+        Ok(())
+    }
+
+    pub fn transfers(&self) -> Vec<Transfer> {
+        self.queue.lock().unwrap().transfers.clone()
+    }
+
+    /// Starts the queued transfers with the highest priority until the concurrency limit is reached.
+    ///
+    /// Restored transfers are checked against the scope first, once the webview that queued them exists.
+    pub fn schedule(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        let mut changed = Vec::new();
+        for transfer in queue
+            .transfers
+            .iter_mut()
+            .filter(|t| t.state == TransferState::Queued && !t.verified)
+        {
+            match self.verify(transfer) {
+                Some(Ok(())) => transfer.verified = true,
+                Some(Err(e)) => {
+                    transfer.state = TransferState::Failed;
+                    transfer.error.replace(e.to_string());
+                    changed.push(transfer.clone());
+                }
+                None => {}
+            }
+        }
+        while let Some(transfer) = queue.start_next(self.max_concurrent_transfers) {
+            changed.push(transfer.clone());
+            let (id, generation) = (transfer.id, transfer.generation);
+            let task = self.spawn(id, generation, transfer.request.clone());
+            queue.tasks.insert(id, task);
+        }
+        let snapshot = self.snapshot(&mut queue);
+        drop(queue);
+
+        for transfer in changed {
+            let _ = self.app.emit(TRANSFER_EVENT, transfer);
+        }
+        self.save(snapshot);
+    }
+
+    /// Checks a restored transfer against the current scope of the webview that queued it,
+    /// `None` if that webview doesn't exist yet.
+    fn verify(&self, transfer: &Transfer) -> Option<Result<()>> {
+        let webview = self.app.get_webview_window(&transfer.webview)?;
+        let (command, url, paths) = match &transfer.request {
+            TransferRequest::Download { url, file_path, .. } => {
+                ("queue_download", url, download_paths(file_path))
+            }
+            TransferRequest::Upload {
+                url,
+                file_path,
+                options,
+                ..
+            } => (
+                "queue_upload",
+                url,
+                options.paths(file_path).map(Path::to_path_buf).collect(),
+            ),
+        };
+        let result = match webview.resolve_command_scope::<Entry>("upload", command) {
+            Ok(Some(scope)) => check_scope(
+                &self.app,
+                scope.command_scope(),
+                scope.global_scope(),
+                url,
+                paths.iter().map(PathBuf::as_path),
+            ),
+            // the command isn't allowed on the webview anymore
+            Ok(None) => url::Url::parse(url)
+                .map_err(Into::into)
+                .and_then(|url| Err(Error::UrlNotAllowed(url))),
+            Err(e) => Err(e.into()),
+        };
+        Some(result)
+    }
+
+    fn spawn(&self, id: TransferId, generation: u32, request: TransferRequest) -> JoinHandle<()> {
+        let app = self.app.clone();
+        tauri::async_runtime::spawn(async move {
+            let progress_app = app.clone();
+            let on_progress = move |payload: ProgressPayload| {
+                progress_app
+                    .state::<TransferManager<R>>()
+                    .record_progress(id, generation, payload);
+            };
+
+            let result = match request {
+                TransferRequest::Download {
+                    url,
+                    file_path,
+                    headers,
+                    checksum,
+                } => {
+                    // always resume so pausing doesn't throw away the downloaded data
+                    let options = DownloadOptions {
+                        resume: true,
+                        checksum,
+                    };
+                    run_download(&url, &file_path, headers, options, on_progress)
+                        .await
+                        .map(|_| None)
+                }
+                TransferRequest::Upload {
+                    url,
+                    file_path,
+                    headers,
+                    options,
+                } => run_upload(&url, &file_path, headers, options, on_progress)
+                    .await
+                    .map(Some),
+            };
+
+            app.state::<TransferManager<R>>()
+                .finish(id, generation, result);
+        })
+    }
+
+    fn finish(&self, id: TransferId, generation: u32, result: Result<Option<String>>) {
+        let mut queue = self.queue.lock().unwrap();
+        // a task stopped by a pause may finish after the transfer was started again
+        let Some(transfer) = queue.finish(id, generation, result).cloned() else {
+            return;
+        };
+        let progress = queue.progress();
+        queue.last_progress.replace(Instant::now());
+        drop(queue);
+
+        let _ = self.app.emit(TRANSFER_EVENT, transfer);
+        let _ = self.app.emit(PROGRESS_EVENT, progress);
+        self.schedule();
+    }
+
+    fn record_progress(&self, id: TransferId, generation: u32, payload: ProgressPayload) {
+        let mut queue = self.queue.lock().unwrap();
+        if !queue.record_progress(id, generation, &payload) {
+            return;
+        }
+
+        if queue
+            .last_progress
+            .map_or(true, |last| last.elapsed() >= PROGRESS_INTERVAL)
+        {
+            let progress = queue.progress();
+            queue.last_progress.replace(Instant::now());
+            drop(queue);
+
+            let _ = self.app.emit(PROGRESS_EVENT, progress);
+        }
+    }
+
+    /// Takes the transfers to save, if the queue is persisted.
+    fn snapshot(&self, queue: &mut Queue) -> Option<Snapshot> {
+        self.queue_path.as_ref().map(|_| queue.snapshot())
+    }
+
+    /// Saves the unfinished transfers so they can be restored on the next launch.
+    fn save(&self, snapshot: Option<Snapshot>) {
+        let (Some(path), Some(snapshot)) = (&self.queue_path, snapshot) else {
+            return;
+        };
+        let mut saved = self.saved.lock().unwrap();
+        // a newer snapshot was saved while this one was waiting
+        if snapshot.revision <= *saved {
+            return;
+        }
+        if let Err(e) = save_queue(path, &snapshot.transfers) {
+            log::error!("failed to save the transfer queue to {path:?}: {e}");
+        }
+        *saved = snapshot.revision;
+    }
+}
+
+/// The local files written by a queued download, which is always resumable.
+fn download_paths(file_path: &Path) -> Vec<PathBuf> {
+    DownloadOptions {
+        resume: true,
+        checksum: None,
+    }
+    .paths(file_path)
+}
+
+/// Removes the partially downloaded data of a stopped download.
+fn discard_download(request: TransferRequest) {
+    if let TransferRequest::Download { url, file_path, .. } = request {
+        tauri::async_runtime::spawn(async move {
+            if let Ok(partial) = PartialDownload::open(&file_path, &url).await {
+                partial.discard().await;
+            }
+        });
+    }
+}
+
+fn save_queue(path: &Path, transfers: &[Transfer]) -> Result<()> {
+    let bytes = serde_json::to_vec(transfers)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+fn load_queue(path: &Path) -> Option<Vec<Transfer>> {
+    let bytes = std::fs::read(path).ok()?;
+    match serde_json::from_slice(&bytes) {
+        Ok(transfers) => Some(transfers),
+        Err(e) => {
+            log::error!("failed to restore the transfer queue from {path:?}: {e}");
+            None
+        }
+    }
+}
+
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn queue_download<R: Runtime>(
+    app: AppHandle<R>,
+    webview: Webview<R>,
+    url: String,
+    file_path: PathBuf,
+    headers: Option<HashMap<String, String>>,
+    checksum: Option<Checksum>,
+    priority: Option<i32>,
+    command_scope: CommandScope<Entry>,
+    global_scope: GlobalScope<Entry>,
+) -> Result<TransferId> {
+    check_scope(
+        &app,
+        &command_scope,
+        &global_scope,
+        &url,
+        download_paths(&file_path).iter().map(PathBuf::as_path),
+    )?;
+    let id = app.state::<TransferManager<R>>().enqueue(
+        TransferRequest::Download {
+            url,
+            file_path,
+            headers: headers.unwrap_or_default(),
+            checksum,
+        },
+        priority.unwrap_or_default(),
+        webview.label().to_string(),
+    );
+    Ok(id)
+}
+
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn queue_upload<R: Runtime>(
+    app: AppHandle<R>,
+    webview: Webview<R>,
+    url: String,
+    file_path: PathBuf,
+    headers: Option<HashMap<String, String>>,
+    options: Option<UploadOptions>,
+    priority: Option<i32>,
+    command_scope: CommandScope<Entry>,
+    global_scope: GlobalScope<Entry>,
+) -> Result<TransferId> {
+    let options = options.unwrap_or_default();
+    check_scope(
+        &app,
+        &command_scope,
+        &global_scope,
+        &url,
+        options.paths(&file_path),
+    )?;
+    let id = app.state::<TransferManager<R>>().enqueue(
+        TransferRequest::Upload {
+            url,
+            file_path,
+            headers: headers.unwrap_or_default(),
+            options,
+        },
+        priority.unwrap_or_default(),
+        webview.label().to_string(),
+    );
+    Ok(id)
+}
+
+#[command]
+pub(crate) fn pause_transfer<R: Runtime>(app: AppHandle<R>, id: TransferId) -> Result<()> {
+    app.state::<TransferManager<R>>().pause(id)
+}
+
+#[command]
+pub(crate) fn resume_transfer<R: Runtime>(
+    app: AppHandle<R>,
+    id: TransferId,
+    headers: Option<HashMap<String, String>>,
+) -> Result<()> {
+    app.state::<TransferManager<R>>().resume(id, headers)
+}
+
+#[command]
+pub(crate) fn cancel_transfer<R: Runtime>(app: AppHandle<R>, id: TransferId) -> Result<()> {
+    app.state::<TransferManager<R>>().cancel(id)
+}
+
+#[command]
+pub(crate) fn remove_transfer<R: Runtime>(app: AppHandle<R>, id: TransferId) -> Result<()> {
+    app.state::<TransferManager<R>>().remove(id)
+}
+
+#[command]
+pub(crate) fn set_transfer_priority<R: Runtime>(
+    app: AppHandle<R>,
+    id: TransferId,
+    priority: i32,
+) -> Result<()> {
+    app.state::<TransferManager<R>>().set_priority(id, priority)
+}
+
+#[command]
+pub(crate) fn list_transfers<R: Runtime>(app: AppHandle<R>) -> Vec<Transfer> {
+    app.state::<TransferManager<R>>().transfers()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(priorities: &[i32]) -> Queue {
+        let mut queue = Queue::default();
+        for (i, priority) in priorities.iter().enumerate() {
+            let request = TransferRequest::Download {
+                url: format!("https://example.com/{i}"),
+                file_path: PathBuf::from(i.to_string()),
+                headers: HashMap::new(),
+                checksum: None,
+            };
+            queue.push(request, *priority, "main".into());
+        }
+        queue
+    }
+
+    fn start_all(queue: &mut Queue, max_concurrent_transfers: usize) -> Vec<TransferId> {
+        std::iter::from_fn(|| queue.start_next(max_concurrent_transfers).map(|t| t.id)).collect()
+    }
+
+    fn state(queue: &Queue, id: TransferId) -> TransferState {
+        queue.get(id).unwrap().state
+    }
+
+    fn progress(transferred: u64) -> ProgressPayload {
+        ProgressPayload {
+            progress: transferred,
+            progress_total: transferred,
+            total: 100,
+            transfer_speed: 10,
+        }
+    }
+
+    #[test]
+    fn starts_by_priority_then_queue_order() {
+        let mut queue = queue(&[0, 10, 0, 5]);
+        assert_eq!(start_all(&mut queue, 10), vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn limits_concurrent_transfers() {
+        let mut queue = queue(&[0, 0, 0]);
+        assert_eq!(start_all(&mut queue, 2), vec![0, 1]);
+        assert_eq!(state(&queue, 2), TransferState::Queued);
+
+        let generation = queue.get(0).unwrap().generation;
+        let finished = queue.finish(0, generation, Ok(None)).unwrap();
+        assert_eq!(finished.state, TransferState::Completed);
+        assert_eq!(start_all(&mut queue, 2), vec![2]);
+    }
+
+    #[test]
+    fn pauses_resumes_and_cancels() {
+        let mut queue = queue(&[0, 0]);
+        assert_eq!(start_all(&mut queue, 1), vec![0]);
+
+        assert!(queue.pause(0).unwrap());
+        assert!(!queue.pause(0).unwrap());
+        assert_eq!(state(&queue, 0), TransferState::Paused);
+        // the paused transfer frees its slot
+        assert_eq!(start_all(&mut queue, 1), vec![1]);
+
+        assert!(!queue.resume(1, None).unwrap());
+        assert!(queue.resume(0, None).unwrap());
+        assert_eq!(state(&queue, 0), TransferState::Queued);
+
+        assert!(queue.cancel(0).unwrap().is_some());
+        assert!(queue.cancel(0).unwrap().is_none());
+        assert!(!queue.resume(0, None).unwrap());
+        assert_eq!(state(&queue, 0), TransferState::Canceled);
+
+        assert!(matches!(queue.pause(42), Err(Error::TransferNotFound(42))));
+    }
+
+    #[test]
+    fn retries_failed_transfers() {
+        let mut queue = queue(&[0]);
+        let generation = queue.start_next(1).unwrap().generation;
+        let error = Error::HttpErrorCode(500, String::new());
+        let failed = queue.finish(0, generation, Err(error)).unwrap();
+        assert_eq!(failed.state, TransferState::Failed);
+        assert!(failed.error.is_some());
+
+        assert!(queue.resume(0, None).unwrap());
+        assert_eq!(queue.get(0).unwrap().error, None);
+        assert_eq!(start_all(&mut queue, 1), vec![0]);
+    }
+
+    #[test]
+    fn ignores_tasks_stopped_by_a_pause() {
+        let mut queue = queue(&[0]);
+        let stopped = queue.start_next(1).unwrap().generation;
+        queue.pause(0).unwrap();
+        queue.resume(0, None).unwrap();
+        let restarted = queue.start_next(1).unwrap().generation;
+        assert_ne!(stopped, restarted);
+
+        // the aborted task reports its last progress and result after the restart
+        assert!(!queue.record_progress(0, stopped, &progress(90)));
+        let error = Error::HttpErrorCode(500, String::new());
+        assert!(queue.finish(0, stopped, Err(error)).is_none());
+        assert_eq!(state(&queue, 0), TransferState::Running);
+        assert_eq!(queue.get(0).unwrap().transferred, 0);
+
+        assert!(queue.record_progress(0, restarted, &progress(50)));
+        assert_eq!(queue.get(0).unwrap().transferred, 50);
+        let finished = queue.finish(0, restarted, Ok(None)).unwrap();
+        assert_eq!(finished.state, TransferState::Completed);
+    }
+
+    #[test]
+    fn completes_transfers_finishing_while_being_paused() {
+        let mut queue = queue(&[0]);
+        let generation = queue.start_next(1).unwrap().generation;
+        queue.pause(0).unwrap();
+        let finished = queue.finish(0, generation, Ok(None)).unwrap();
+        assert_eq!(finished.state, TransferState::Completed);
+    }
+
+    #[test]
+    fn restores_unfinished_transfers() {
+        let mut queue = queue(&[0, 0, 0, 0]);
+        assert_eq!(start_all(&mut queue, 2), vec![0, 1]);
+        queue.pause(1).unwrap();
+        queue.cancel(2).unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "tauri-plugin-upload-queue-{}.json",
+            std::process::id()
+        ));
+        save_queue(&path, &queue.snapshot().transfers).unwrap();
+        let mut restored = Queue::restore(load_queue(&path).unwrap());
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            restored
+                .transfers
+                .iter()
+                .map(|t| (t.id, t.state))
+                .collect::<Vec<_>>(),
+            vec![
+                // interrupted by the app exit
+                (0, TransferState::Queued),
+                (1, TransferState::Paused),
+                (3, TransferState::Queued),
+            ]
+        );
+        assert_eq!(restored.next_id, 4);
+        assert!(restored
+            .transfers
+            .iter()
+            .all(|t| t.webview == "main" && !t.verified));
+        // restored transfers are checked against the scope before starting
+        assert!(restored.start_next(1).is_none());
+    }
+
+    #[test]
+    fn does_not_save_headers() {
+        let mut queue = queue(&[0, 0]);
+        let request = TransferRequest::Download {
+            url: "https://example.com/private".into(),
+            file_path: PathBuf::from("private"),
+            headers: HashMap::from([("Authorization".into(), "Bearer secret".into())]),
+            checksum: None,
+        };
+        let id = queue.push(request, 0, "main".into()).id;
+
+        let saved = serde_json::to_string(&queue.snapshot().transfers).unwrap();
+        assert!(!saved.contains("secret"));
+
+        // the transfer waits for its headers to be passed again
+        let mut restored = Queue::restore(serde_json::from_str(&saved).unwrap());
+        assert_eq!(state(&restored, 0), TransferState::Queued);
+        assert_eq!(state(&restored, id), TransferState::Paused);
+
+        let headers = HashMap::from([("Authorization".into(), "Bearer renewed".into())]);
+        assert!(restored.resume(id, Some(headers.clone())).unwrap());
+        assert_eq!(state(&restored, id), TransferState::Queued);
+        assert_eq!(
+            restored.get_mut(id).unwrap().request.headers_mut(),
+            &headers
+        );
+    }
+
+    #[test]
+    fn removes_transfers() {
+        let mut queue = queue(&[0, 0]);
+        assert_eq!(start_all(&mut queue, 2), vec![0, 1]);
+        let generation = queue.get(0).unwrap().generation;
+        queue.finish(0, generation, Ok(None)).unwrap();
+
+        assert_eq!(queue.remove(0).unwrap().state, TransferState::Completed);
+        // unfinished transfers are stopped
+        assert_eq!(queue.remove(1).unwrap().state, TransferState::Running);
+        assert!(queue.transfers.is_empty());
+        assert!(matches!(queue.remove(0), Err(Error::TransferNotFound(0))));
+        // the ids of removed transfers are not reused
+        assert_eq!(queue.next_id, 2);
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use futures_util::TryStreamExt;
+use read_progress_stream::ReadProgressStream;
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+use crate::{transfer_stats::TransferStats, ProgressPayload, Result};
+
+const DEFAULT_FIELD_NAME: &str = "file";
+
+/// Sends the upload as a `multipart/form-data` body instead of the raw file bytes.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MultipartOptions {
+    /// Name of the form field holding the uploaded file. Defaults to `file`.
+    field_name: Option<String>,
+    /// Text fields sent before the files, e.g. the policy fields of an S3 presigned POST.
+    #[serde(default)]
+    fields: HashMap<String, String>,
+    /// Additional files sent in the same request.
+    #[serde(default)]
+    files: Vec<MultipartFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MultipartFile {
+    path: PathBuf,
+    /// Defaults to the field name of the main file.
+    field_name: Option<String>,
+    /// Defaults to the name of the file on disk.
+    file_name: Option<String>,
+    mime_type: Option<String>,
+}
+
+impl MultipartOptions {
+    pub fn additional_paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|file| file.path.as_path())
+    }
+
+    /// Builds the form, reporting the progress of all files combined.
+    pub async fn into_form(
+        self,
+        file_path: &Path,
+        on_progress: impl Fn(ProgressPayload) + Send + Sync + 'static,
+    ) -> Result<Form> {
+        let field_name = self
+            .field_name
+            .unwrap_or_else(|| DEFAULT_FIELD_NAME.to_string());
+
+        let mut files = vec![(field_name.clone(), file_path.to_path_buf(), None, None)];
+        files.extend(self.files.into_iter().map(|file| {
+            (
+                file.field_name.unwrap_or_else(|| field_name.clone()),
+                file.path,
+                file.file_name,
+                file.mime_type,
+            )
+        }));
+
+        let mut opened = Vec::with_capacity(files.len());
+        let mut total = 0;
+        for (field_name, path, file_name, mime_type) in files {
+            let file = File::open(&path).await?;
+            let len = file.metadata().await?.len();
+            total += len;
+            let file_name = file_name.or_else(|| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            });
+            opened.push((field_name, file, len, file_name, mime_type));
+        }
+
+        let mut form = Form::new();
+        for (name, value) in self.fields {
+            form = form.text(name, value);
+        }
+
+        let on_progress = Arc::new(on_progress);
+        let stats = Arc::new(Mutex::new(TransferStats::default()));
+        for (field_name, file, len, file_name, mime_type) in opened {
+            let on_progress = on_progress.clone();
+            let stats = stats.clone();
+            let stream = FramedRead::new(file, BytesCodec::new()).map_ok(|r| r.freeze());
+            let body = reqwest::Body::wrap_stream(ReadProgressStream::new(
+                stream,
+                Box::new(move |progress, _| {
+                    let mut stats = stats.lock().unwrap();
+                    stats.record_chunk_transfer(progress as usize);
+                    on_progress(ProgressPayload {
+                        progress,
+                        progress_total: stats.total_transferred,
+                        total,
+                        transfer_speed: stats.transfer_speed,
+                    });
+                }),
+            ));
+
+            let mut part = Part::stream_with_length(body, len);
+            if let Some(file_name) = file_name {
+                part = part.file_name(file_name);
+            }
+            if let Some(mime_type) = mime_type {
+                part = part.mime_str(&mime_type)?;
+            }
+            form = form.part(field_name, part);
+        }
+
+        Ok(form)
+    }
+}
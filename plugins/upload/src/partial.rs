@@ -0,0 +1,181 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::path::{Path, PathBuf};
+
+use reqwest::{header, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+/// Validators of the remote file a partial download belongs to,
+/// persisted next to the partial file so the download can be resumed after a restart.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PartialMetadata {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub total: Option<u64>,
+}
+
+impl PartialMetadata {
+    fn from_response(url: &str, response: &Response, total: Option<u64>) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToString::to_string)
+        };
+        Self {
+            url: url.into(),
+            // weak validators can't be used with `If-Range`
+            etag: header(header::ETAG).filter(|etag| !etag.starts_with("W/")),
+            last_modified: header(header::LAST_MODIFIED),
+            total,
+        }
+    }
+
+    fn validator(&self) -> Option<&str> {
+        self.etag.as_deref().or(self.last_modified.as_deref())
+    }
+}
+
+/// A download written to `<file>.part` until it completes.
+pub(crate) struct PartialDownload {
+    pub part_path: PathBuf,
+    metadata_path: PathBuf,
+    metadata: Option<PartialMetadata>,
+    /// Number of bytes already on disk that can be resumed from.
+    pub offset: u64,
+}
+
+impl PartialDownload {
+    /// The partial file and its metadata, written next to `file_path`.
+    pub fn paths(file_path: &Path) -> [PathBuf; 2] {
+        [
+            with_suffix(file_path, ".part"),
+            with_suffix(file_path, ".part.json"),
+        ]
+    }
+
+    /// Looks for a previous partial download of `url` to `file_path`.
+    pub async fn open(file_path: &Path, url: &str) -> Result<Self> {
+        let [part_path, metadata_path] = Self::paths(file_path);
+
+        let metadata = match tokio::fs::read(&metadata_path).await {
+            Ok(bytes) => serde_json::from_slice::<PartialMetadata>(&bytes)
+                .ok()
+                .filter(|m| m.url == url && m.validator().is_some()),
+            Err(_) => None,
+        };
+
+        let offset = match (&metadata, tokio::fs::metadata(&part_path).await) {
+            (Some(_), Ok(part)) => part.len(),
+            _ => 0,
+        };
+
+        Ok(Self {
+            part_path,
+            metadata_path,
+            metadata,
+            offset,
+        })
+    }
+
+    /// Whether the partial file already contains the whole remote file.
+    pub fn is_complete(&self) -> bool {
+        self.offset > 0 && self.metadata.as_ref().and_then(|m| m.total) == Some(self.offset)
+    }
+
+    /// Requests the missing bytes only, if the remote file did not change since the last attempt.
+    pub fn apply_range(&self, request: RequestBuilder) -> RequestBuilder {
+        match self.metadata.as_ref().and_then(|m| m.validator()) {
+            Some(validator) if self.offset > 0 => request
+                .header(header::RANGE, format!("bytes={}-", self.offset))
+                .header(header::IF_RANGE, validator),
+            _ => request,
+        }
+    }
+
+    /// Reconciles the partial file with the server response,
+    /// returning the offset the response body starts at and the total size of the file.
+    pub async fn accept(&mut self, url: &str, response: &Response) -> Result<(u64, Option<u64>)> {
+        let (offset, total) = if response.status() == StatusCode::PARTIAL_CONTENT {
+            let content_range = response
+                .headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            let (start, total) = parse_content_range(content_range)
+                .ok_or_else(|| Error::InvalidContentRange(content_range.into()))?;
+            if start != self.offset {
+                return Err(Error::InvalidContentRange(content_range.into()));
+            }
+            (start, total)
+        } else {
+            // the server ignored the range or the file changed, start over
+            (0, response.content_length())
+        };
+
+        let metadata = PartialMetadata::from_response(url, response, total);
+        tokio::fs::write(&self.metadata_path, serde_json::to_vec(&metadata)?).await?;
+        self.metadata.replace(metadata);
+        self.offset = offset;
+
+        Ok((offset, total))
+    }
+
+    /// Moves the completed download to its final location.
+    pub async fn finish(self, file_path: &Path) -> Result<()> {
+        tokio::fs::rename(&self.part_path, file_path).await?;
+        let _ = tokio::fs::remove_file(&self.metadata_path).await;
+        Ok(())
+    }
+
+    /// Removes the partial file so the next attempt starts from scratch.
+    pub async fn discard(self) {
+        let _ = tokio::fs::remove_file(&self.part_path).await;
+        let _ = tokio::fs::remove_file(&self.metadata_path).await;
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Parses a `Content-Range: bytes <start>-<end>/<total>` header value.
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (
+        start.trim().parse::<u64>().ok()?,
+        end.trim().parse::<u64>().ok()?,
+    );
+    if end < start {
+        return None;
+    }
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_content_range() {
+        assert_eq!(parse_content_range("bytes 7-19/20"), Some((7, Some(20))));
+        assert_eq!(parse_content_range("bytes 0-0/*"), Some((0, None)));
+        assert_eq!(parse_content_range("bytes */20"), None);
+        assert_eq!(parse_content_range("bytes 9-3/20"), None);
+        assert_eq!(parse_content_range("items 0-3/20"), None);
+    }
+}
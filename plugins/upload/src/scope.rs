@@ -0,0 +1,152 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{path::PathBuf, sync::Arc};
+
+use serde::{Deserialize, Deserializer};
+use url::Url;
+use urlpattern::{UrlPattern, UrlPatternMatchInput};
+
+#[allow(rustdoc::bare_urls)]
+#[derive(Debug)]
+pub struct Entry {
+    pub url: Option<UrlPattern>,
+    pub path: Option<PathBuf>,
+}
+
+fn parse_url_pattern(s: &str) -> Result<UrlPattern, urlpattern::quirks::Error> {
+    let mut init = urlpattern::UrlPatternInit::parse_constructor_string::<regex::Regex>(s, None)?;
+    if init.search.as_ref().map(|p| p.is_empty()).unwrap_or(true) {
+        init.search.replace("*".to_string());
+    }
+    if init.hash.as_ref().map(|p| p.is_empty()).unwrap_or(true) {
+        init.hash.replace("*".to_string());
+    }
+    if init
+        .pathname
+        .as_ref()
+        .map(|p| p.is_empty() || p == "/")
+        .unwrap_or(true)
+    {
+        init.pathname.replace("*".to_string());
+    }
+    UrlPattern::parse(init, Default::default())
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum EntryRaw {
+    Value(String),
+    Object { url: String },
+    Path { path: PathBuf },
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        EntryRaw::deserialize(deserializer).and_then(|raw| {
+            let url = match raw {
+                EntryRaw::Value(url) => url,
+                EntryRaw::Object { url } => url,
+                EntryRaw::Path { path } => {
+                    return Ok(Entry {
+                        url: None,
+                        path: Some(path),
+                    })
+                }
+            };
+            Ok(Entry {
+                url: Some(parse_url_pattern(&url).map_err(|e| {
+                    serde::de::Error::custom(format!("`{}` is not a valid URL pattern: {e}", url))
+                })?),
+                path: None,
+            })
+        })
+    }
+}
+
+/// Scope for the remote endpoints transfers can target.
+#[derive(Debug)]
+pub struct Scope<'a> {
+    allowed: Vec<&'a Arc<Entry>>,
+    denied: Vec<&'a Arc<Entry>>,
+}
+
+impl<'a> Scope<'a> {
+    /// Creates a new scope from the scope configuration.
+    pub(crate) fn new(allowed: Vec<&'a Arc<Entry>>, denied: Vec<&'a Arc<Entry>>) -> Self {
+        Self { allowed, denied }
+    }
+
+    /// Determines if the given URL is allowed on this scope.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let matches = |entry: &&Arc<Entry>| {
+            entry.url.as_ref().is_some_and(|pattern| {
+                pattern
+                    .test(UrlPatternMatchInput::Url(url.clone()))
+                    .unwrap_or_default()
+            })
+        };
+        !self.denied.iter().any(matches) && self.allowed.iter().any(matches)
+    }
+
+    /// The local paths allowed on this scope.
+    pub fn allowed_paths(&self) -> Vec<PathBuf> {
+        self.allowed.iter().filter_map(|e| e.path.clone()).collect()
+    }
+
+    /// The local paths denied on this scope.
+    pub fn denied_paths(&self) -> Vec<PathBuf> {
+        self.denied.iter().filter_map(|e| e.path.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, str::FromStr, sync::Arc};
+
+    use super::Entry;
+
+    impl FromStr for Entry {
+        type Err = urlpattern::quirks::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let pattern = super::parse_url_pattern(s)?;
+            Ok(Self {
+                url: Some(pattern),
+                path: None,
+            })
+        }
+    }
+
+    #[test]
+    fn denied_takes_precedence() {
+        let allow = Arc::new("https://uploads.example.com/*".parse().unwrap());
+        let deny = Arc::new("https://uploads.example.com/private/*".parse().unwrap());
+        let scope = super::Scope::new(vec![&allow], vec![&deny]);
+        assert!(scope.is_allowed(&"https://uploads.example.com/public/a.png".parse().unwrap()));
+        assert!(!scope.is_allowed(&"https://uploads.example.com/private/a.png".parse().unwrap()));
+    }
+
+    #[test]
+    fn path_entries_do_not_allow_urls() {
+        let allow: Entry =
+            serde_json::from_value(serde_json::json!({ "path": "$DOWNLOAD/**" })).unwrap();
+        let deny: Entry =
+            serde_json::from_value(serde_json::json!("https://example.com/*")).unwrap();
+        let (allow, deny) = (Arc::new(allow), Arc::new(deny));
+        let scope = super::Scope::new(vec![&allow], vec![&deny]);
+        assert!(!scope.is_allowed(&"https://uploads.example.com".parse().unwrap()));
+        assert_eq!(scope.allowed_paths(), vec![PathBuf::from("$DOWNLOAD/**")]);
+        assert!(scope.denied_paths().is_empty());
+    }
+
+    #[test]
+    fn empty_scope_denies_everything() {
+        let scope = super::Scope::new(Vec::new(), Vec::new());
+        assert!(!scope.is_allowed(&"https://example.com".parse().unwrap()));
+    }
+}
@@ -0,0 +1,713 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Client for the [tus resumable upload protocol](https://tus.io/protocols/resumable-upload).
+
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::TryStreamExt;
+use read_progress_stream::ReadProgressStream;
+use reqwest::{
+    header::{HeaderName, CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use serde::Deserialize;
+use tauri::{
+    command,
+    ipc::{Channel, CommandScope, GlobalScope},
+    AppHandle, Manager, Runtime,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::codec::{BytesCodec, FramedRead};
+use url::Url;
+
+use crate::{
+    check_scope,
+    scope::{Entry, Scope},
+    transfer_stats::TransferStats,
+    url_scope, Error, ProgressPayload, Result,
+};
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+const DEFAULT_RETRY_DELAYS: &[u64] = &[0, 1000, 3000, 5000];
+const UPLOAD_URLS_FILE: &str = "tus-uploads.json";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TusOptions {
+    /// Maximum size of the body of each `PATCH` request. Defaults to the whole file.
+    chunk_size: Option<u64>,
+    /// Sent in the `Upload-Metadata` header when creating the upload.
+    /// The file name is added as `filename` unless already set.
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    /// URL of an upload created by a previous attempt, to resume it, relative to the endpoint.
+    upload_url: Option<String>,
+    /// Delays in milliseconds before retrying a failed request.
+    retry_delays: Option<Vec<u64>>,
+}
+
+/// Remembers the URLs of unfinished uploads so they can be resumed after an app restart.
+pub(crate) struct UploadUrlStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl UploadUrlStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn load(&self) -> HashMap<String, String> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn get(&self, fingerprint: &str) -> Option<String> {
+        let _lock = self.lock.lock().unwrap();
+        self.load().remove(fingerprint)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut HashMap<String, String>)) {
+        let _lock = self.lock.lock().unwrap();
+        let mut urls = self.load();
+        f(&mut urls);
+        let result = self
+            .path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&self.path, serde_json::to_vec(&urls)?));
+        if let Err(e) = result {
+            log::error!("failed to save the tus upload URLs: {e}");
+        }
+    }
+}
+
+/// Identifies an upload of a given file version to a given endpoint.
+fn fingerprint(endpoint: &str, file_path: &Path, metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default();
+    format!(
+        "{endpoint} {} {} {modified}",
+        file_path.display(),
+        metadata.len()
+    )
+}
+
+fn encode_metadata(metadata: &HashMap<String, String>) -> String {
+    let mut pairs = metadata
+        .iter()
+        .map(|(key, value)| format!("{key} {}", STANDARD.encode(value)))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs.join(",")
+}
+
+fn upload_offset(response: &Response) -> Result<u64> {
+    response
+        .headers()
+        .get(UPLOAD_OFFSET)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| Error::InvalidTusResponse("missing or invalid Upload-Offset header".into()))
+}
+
+fn check_url(scope: &Scope<'_>, url: &Url) -> Result<()> {
+    if scope.is_allowed(url) {
+        Ok(())
+    } else {
+        Err(Error::UrlNotAllowed(url.clone()))
+    }
+}
+
+/// Whether a failed request may succeed if it is sent again.
+fn is_retryable(error: &Error) -> bool {
+    match error {
+        Error::Request(_) => true,
+        Error::HttpErrorCode(status, _) => {
+            *status >= 500
+                || *status == StatusCode::CONFLICT.as_u16()
+                || *status == StatusCode::LOCKED.as_u16()
+        }
+        _ => false,
+    }
+}
+
+async fn error_for_status(response: Response) -> Error {
+    Error::HttpErrorCode(
+        response.status().as_u16(),
+        response.text().await.unwrap_or_default(),
+    )
+}
+
+struct TusUpload<'a> {
+    client: Client,
+    endpoint: Url,
+    /// Every upload URL is checked against it before the file or the headers are sent.
+    scope: &'a Scope<'a>,
+    headers: &'a HashMap<String, String>,
+    file_path: &'a Path,
+    size: u64,
+    chunk_size: u64,
+    metadata: String,
+    on_progress: Arc<dyn Fn(ProgressPayload) + Send + Sync>,
+    stats: Arc<Mutex<TransferStats>>,
+}
+
+impl TusUpload<'_> {
+    fn request(&self, method: reqwest::Method, url: &Url) -> RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, url.clone())
+            .header(TUS_RESUMABLE, TUS_VERSION);
+        for (key, value) in self.headers {
+            request = request.header(key, value);
+        }
+        request
+    }
+
+    /// Creates a new upload on the server and returns its URL.
+    async fn create(&self) -> Result<Url> {
+        let mut request = self
+            .request(reqwest::Method::POST, &self.endpoint)
+            .header(UPLOAD_LENGTH, self.size);
+        if !self.metadata.is_empty() {
+            request = request.header(UPLOAD_METADATA, &self.metadata);
+        }
+
+        let response = request.send().await?;
+        if response.status() != StatusCode::CREATED {
+            return Err(error_for_status(response).await);
+        }
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| Error::InvalidTusResponse("missing Location header".into()))?;
+        let url = self.endpoint.join(location)?;
+        // the server must not redirect the file to an endpoint outside of the scope
+        check_url(self.scope, &url)?;
+        Ok(url)
+    }
+
+    /// Asks the server how many bytes of the upload it already has,
+    /// or `None` if the upload no longer exists.
+    async fn offset(&self, url: &Url) -> Result<Option<u64>> {
+        let response = self.request(reqwest::Method::HEAD, url).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::GONE | StatusCode::FORBIDDEN => Ok(None),
+            status if status.is_success() => {
+                let offset = upload_offset(&response)?;
+                Ok(Some(offset).filter(|offset| *offset <= self.size))
+            }
+            _ => Err(error_for_status(response).await),
+        }
+    }
+
+    /// Sends the next chunk of the file starting at `offset`, returning the new offset.
+    async fn patch(&self, url: &Url, offset: u64) -> Result<u64> {
+        let len = (self.size - offset).min(self.chunk_size);
+        let mut file = File::open(self.file_path).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let stream = FramedRead::new(file.take(len), BytesCodec::new()).map_ok(|r| r.freeze());
+
+        let on_progress = self.on_progress.clone();
+        let stats = self.stats.clone();
+        let size = self.size;
+        let body = reqwest::Body::wrap_stream(ReadProgressStream::new(
+            stream,
+            Box::new(move |progress, sent| {
+                let mut stats = stats.lock().unwrap();
+                stats.record_chunk_transfer(progress as usize);
+                on_progress(ProgressPayload {
+                    progress,
+                    progress_total: offset + sent,
+                    total: size,
+                    transfer_speed: stats.transfer_speed,
+                });
+            }),
+        ));
+
+        let response = self
+            .request(reqwest::Method::PATCH, url)
+            .header(UPLOAD_OFFSET, offset)
+            .header(CONTENT_TYPE, OFFSET_CONTENT_TYPE)
+            .header(CONTENT_LENGTH, len)
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(error_for_status(response).await);
+        }
+        let new_offset = upload_offset(&response)?;
+        if new_offset <= offset || new_offset > self.size {
+            return Err(Error::InvalidTusResponse(format!(
+                "unexpected Upload-Offset {new_offset} after sending bytes {offset}-{}",
+                offset + len
+            )));
+        }
+        Ok(new_offset)
+    }
+
+    /// Sends the remaining bytes of the upload, creating it first if `upload_url` is unknown.
+    async fn send(&self, upload_url: &mut Option<Url>, store: impl Fn(&Url)) -> Result<()> {
+        let mut offset = match &*upload_url {
+            Some(url) => self.offset(url).await?,
+            None => None,
+        };
+        let url = match (&*upload_url, offset) {
+            (Some(url), Some(_)) => url.clone(),
+            _ => {
+                let url = self.create().await?;
+                store(&url);
+                upload_url.replace(url.clone());
+                offset.replace(0);
+                url
+            }
+        };
+
+        let mut offset = offset.unwrap_or_default();
+        while offset < self.size {
+            offset = self.patch(&url, offset).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Uploads the file with the tus protocol, resuming the upload after failures,
+/// and returns the URL of the upload.
+///
+/// Upload URLs, relative to `endpoint`, must be allowed on `scope`.
+/// A stored upload URL that isn't allowed anymore is replaced by a new upload.
+pub(crate) async fn run_tus_upload(
+    endpoint: &str,
+    file_path: &Path,
+    headers: HashMap<String, String>,
+    options: TusOptions,
+    scope: &Scope<'_>,
+    store: Option<&UploadUrlStore>,
+    on_progress: impl Fn(ProgressPayload) + Send + Sync + 'static,
+) -> Result<String> {
+    let file_metadata = tokio::fs::metadata(file_path).await?;
+    let fingerprint = fingerprint(endpoint, file_path, &file_metadata);
+
+    let mut metadata = options.metadata;
+    if let Some(file_name) = file_path.file_name() {
+        metadata
+            .entry("filename".into())
+            .or_insert_with(|| file_name.to_string_lossy().into_owned());
+    }
+
+    let upload = TusUpload {
+        client: Client::new(),
+        endpoint: Url::parse(endpoint)?,
+        scope,
+        headers: &headers,
+        file_path,
+        size: file_metadata.len(),
+        chunk_size: options
+            .chunk_size
+            .filter(|size| *size > 0)
+            .unwrap_or(u64::MAX),
+        metadata: encode_metadata(&metadata),
+        on_progress: Arc::new(on_progress),
+        stats: Default::default(),
+    };
+
+    let mut upload_url = match options.upload_url {
+        Some(url) => {
+            let url = upload.endpoint.join(&url)?;
+            check_url(scope, &url)?;
+            Some(url)
+        }
+        None => store
+            .and_then(|store| store.get(&fingerprint))
+            .and_then(|url| upload.endpoint.join(&url).ok())
+            .filter(|url| scope.is_allowed(url)),
+    };
+
+    let store_url = |url: &Url| {
+        if let Some(store) = store {
+            store.update(|urls| {
+                urls.insert(fingerprint.clone(), url.to_string());
+            });
+        }
+    };
+
+    let mut retry_delays = options
+        .retry_delays
+        .unwrap_or_else(|| DEFAULT_RETRY_DELAYS.to_vec())
+        .into_iter();
+    loop {
+        match upload.send(&mut upload_url, &store_url).await {
+            Ok(()) => break,
+            Err(e) if is_retryable(&e) => match retry_delays.next() {
+                Some(delay) => tokio::time::sleep(Duration::from_millis(delay)).await,
+                None => return Err(e),
+            },
+            Err(e) => return Err(e),
+        }
+    }
+
+    if let Some(store) = store {
+        store.update(|urls| {
+            urls.remove(&fingerprint);
+        });
+    }
+
+    // `send` only succeeds once the upload URL is known
+    Ok(upload_url.map(String::from).unwrap_or_default())
+}
+
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn tus_upload<R: Runtime>(
+    app: AppHandle<R>,
+    url: &str,
+    file_path: &str,
+    headers: HashMap<String, String>,
+    on_progress: Channel<ProgressPayload>,
+    options: Option<TusOptions>,
+    command_scope: CommandScope<Entry>,
+    global_scope: GlobalScope<Entry>,
+) -> Result<String> {
+    let file_path = Path::new(file_path);
+    check_scope(&app, &command_scope, &global_scope, url, [file_path])?;
+    let options = options.unwrap_or_default();
+
+    let store = app
+        .path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| UploadUrlStore::new(dir.join(UPLOAD_URLS_FILE)));
+
+    run_tus_upload(
+        url,
+        file_path,
+        headers,
+        options,
+        &url_scope(&command_scope, &global_scope),
+        store.as_ref(),
+        move |payload| {
+            let _ = on_progress.send(payload);
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    #[derive(Default)]
+    struct ServerState {
+        /// Declared length and received bytes of each upload.
+        uploads: HashMap<String, (u64, Vec<u8>)>,
+        metadata: Vec<String>,
+        /// Drop the connection of the next `PATCH` request after receiving this many bytes.
+        fail_after: Option<usize>,
+        /// `Location` of the created uploads instead of their id.
+        location: Option<String>,
+    }
+
+    /// A minimal in-process tus server storing uploads in memory.
+    struct TusServer {
+        endpoint: String,
+        state: Arc<Mutex<ServerState>>,
+    }
+
+    impl TusServer {
+        async fn spawn(fail_after: Option<usize>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = format!("http://{}/files/", listener.local_addr().unwrap());
+            let state = Arc::new(Mutex::new(ServerState {
+                fail_after,
+                ..Default::default()
+            }));
+            let state_ = state.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(stream, state_.clone()));
+                }
+            });
+            Self { endpoint, state }
+        }
+
+        /// A scope allowing the uploads of this server only.
+        fn scope_entry(&self) -> Arc<Entry> {
+            Arc::new(format!("{}*", self.endpoint).parse().unwrap())
+        }
+
+        fn uploads(&self) -> Vec<Vec<u8>> {
+            let state = self.state.lock().unwrap();
+            state
+                .uploads
+                .values()
+                .map(|(_, data)| data.clone())
+                .collect()
+        }
+    }
+
+    async fn handle_connection(stream: TcpStream, state: Arc<Mutex<ServerState>>) {
+        let mut stream = BufReader::new(stream);
+        loop {
+            let mut request_line = String::new();
+            if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_string();
+            let id = parts
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches("/files/")
+                .to_string();
+
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                match line.trim_end().split_once(':') {
+                    Some((name, value)) => {
+                        headers.insert(name.to_ascii_lowercase(), value.trim().to_string())
+                    }
+                    None => break,
+                };
+            }
+            let header = |name: &str| headers.get(name).and_then(|v| v.parse::<u64>().ok());
+
+            let status = match method.as_str() {
+                "POST" => {
+                    let mut state = state.lock().unwrap();
+                    let id = (state.uploads.len() + 1).to_string();
+                    let length = header("upload-length").unwrap();
+                    state.uploads.insert(id.clone(), (length, Vec::new()));
+                    if let Some(metadata) = headers.get("upload-metadata") {
+                        state.metadata.push(metadata.clone());
+                    }
+                    let location = state.location.clone().unwrap_or(id);
+                    format!("201 Created\r\nLocation: {location}")
+                }
+                "HEAD" => match state.lock().unwrap().uploads.get(&id) {
+                    Some((length, data)) => format!(
+                        "200 OK\r\nUpload-Offset: {}\r\nUpload-Length: {length}\r\nCache-Control: no-store",
+                        data.len()
+                    ),
+                    None => "404 Not Found".into(),
+                },
+                "PATCH" => {
+                    let len = header("content-length").unwrap() as usize;
+                    let fail_after = state.lock().unwrap().fail_after.take();
+                    let mut body = vec![0; fail_after.unwrap_or(len).min(len)];
+                    stream.read_exact(&mut body).await.unwrap();
+
+                    let mut state = state.lock().unwrap();
+                    let (_, data) = state.uploads.get_mut(&id).unwrap();
+                    if header("upload-offset") != Some(data.len() as u64) {
+                        "409 Conflict".into()
+                    } else {
+                        data.extend(body);
+                        if fail_after.is_some() {
+                            // simulate a lost connection
+                            return;
+                        }
+                        format!("204 No Content\r\nUpload-Offset: {}", data.len())
+                    }
+                }
+                _ => "405 Method Not Allowed".into(),
+            };
+
+            let response =
+                format!("HTTP/1.1 {status}\r\nTus-Resumable: 1.0.0\r\nContent-Length: 0\r\n\r\n");
+            if stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    fn test_file(name: &str, len: usize) -> (PathBuf, Vec<u8>) {
+        let dir = std::env::temp_dir().join(format!(
+            "tauri-plugin-upload-tus-{name}-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let data = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let path = dir.join("file.bin");
+        std::fs::write(&path, &data).unwrap();
+        (path, data)
+    }
+
+    fn options(value: serde_json::Value) -> TusOptions {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn uploads_in_chunks() {
+        let server = TusServer::spawn(None).await;
+        let (path, data) = test_file("chunks", 100);
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let progress_ = progress.clone();
+        let result = run_tus_upload(
+            &server.endpoint,
+            &path,
+            HashMap::new(),
+            options(serde_json::json!({ "chunkSize": 30 })),
+            &Scope::new(vec![&server.scope_entry()], Vec::new()),
+            None,
+            move |payload| progress_.lock().unwrap().push(payload.progress_total),
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), format!("{}1", server.endpoint));
+        assert_eq!(server.uploads(), vec![data]);
+        assert_eq!(progress.lock().unwrap().last(), Some(&100));
+        assert_eq!(
+            server.state.lock().unwrap().metadata,
+            vec![format!("filename {}", STANDARD.encode("file.bin"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn resumes_after_connection_failure() {
+        let server = TusServer::spawn(Some(40)).await;
+        let (path, data) = test_file("retry", 100);
+
+        let result = run_tus_upload(
+            &server.endpoint,
+            &path,
+            HashMap::new(),
+            options(serde_json::json!({ "retryDelays": [0] })),
+            &Scope::new(vec![&server.scope_entry()], Vec::new()),
+            None,
+            |_| {},
+        )
+        .await;
+
+        assert!(result.is_ok(), "failed to upload file: {result:?}");
+        // resumed with a `HEAD` request instead of creating a new upload
+        assert_eq!(server.uploads(), vec![data]);
+    }
+
+    #[tokio::test]
+    async fn resumes_stored_upload() {
+        let server = TusServer::spawn(Some(40)).await;
+        let (path, data) = test_file("store", 100);
+        let store = UploadUrlStore::new(path.with_file_name(UPLOAD_URLS_FILE));
+        let entry = server.scope_entry();
+        let scope = Scope::new(vec![&entry], Vec::new());
+
+        let result = run_tus_upload(
+            &server.endpoint,
+            &path,
+            HashMap::new(),
+            options(serde_json::json!({ "retryDelays": [] })),
+            &scope,
+            Some(&store),
+            |_| {},
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(store.load().len(), 1);
+
+        let result = run_tus_upload(
+            &server.endpoint,
+            &path,
+            HashMap::new(),
+            TusOptions::default(),
+            &scope,
+            Some(&store),
+            |_| {},
+        )
+        .await;
+        assert!(result.is_ok(), "failed to resume upload: {result:?}");
+        assert_eq!(server.uploads(), vec![data]);
+        assert!(store.load().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_upload_urls_outside_of_the_scope() {
+        let server = TusServer::spawn(None).await;
+        let (path, _) = test_file("scope", 100);
+        let entry = server.scope_entry();
+        let scope = Scope::new(vec![&entry], Vec::new());
+        // the same server under another host name, which the scope doesn't allow
+        let off_scope = server.endpoint.replace("127.0.0.1", "localhost");
+
+        server.state.lock().unwrap().location = Some(format!("{off_scope}1"));
+        let result = run_tus_upload(
+            &server.endpoint,
+            &path,
+            HashMap::new(),
+            TusOptions::default(),
+            &scope,
+            None,
+            |_| {},
+        )
+        .await;
+        assert!(matches!(result, Err(Error::UrlNotAllowed(_))), "{result:?}");
+        // the upload was created but nothing was sent to it
+        assert_eq!(server.uploads(), vec![Vec::<u8>::new()]);
+
+        let result = run_tus_upload(
+            &server.endpoint,
+            &path,
+            HashMap::new(),
+            options(serde_json::json!({ "uploadUrl": format!("{off_scope}1") })),
+            &scope,
+            None,
+            |_| {},
+        )
+        .await;
+        assert!(matches!(result, Err(Error::UrlNotAllowed(_))), "{result:?}");
+
+        // relative upload URLs are resolved against the endpoint
+        server.state.lock().unwrap().location = None;
+        let result = run_tus_upload(
+            &server.endpoint,
+            &path,
+            HashMap::new(),
+            options(serde_json::json!({ "uploadUrl": "1" })),
+            &scope,
+            None,
+            |_| {},
+        )
+        .await;
+        assert_eq!(result.unwrap(), format!("{}1", server.endpoint));
+        assert_eq!(server.uploads().len(), 1);
+    }
+}
@@ -2,10 +2,58 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-const COMMANDS: &[&str] = &["connect", "send"];
+#[path = "src/scope.rs"]
+#[allow(dead_code)]
+mod scope;
+
+const COMMANDS: &[&str] = &["connect", "send", "listen", "close_server"];
+
+/// WebSocket scope entry.
+#[derive(schemars::JsonSchema)]
+#[serde(untagged)]
+#[allow(unused)]
+enum WebSocketScopeEntry {
+    /// A URL that WebSocket connections can be opened to.
+    /// Wildcards can be used following the URL pattern standard.
+    ///
+    /// See [the URL Pattern spec](https://urlpattern.spec.whatwg.org/) for more information.
+    ///
+    /// Examples:
+    ///
+    /// - "wss://*.example.com" : allows connecting to any subdomain of "example.com"
+    ///
+    /// - "ws://localhost:*": allows connecting to any port on localhost
+    Value(String),
+    Object {
+        /// A URL that WebSocket connections can be opened to.
+        /// Wildcards can be used following the URL pattern standard.
+        ///
+        /// See [the URL Pattern spec](https://urlpattern.spec.whatwg.org/) for more information.
+        ///
+        /// Examples:
+        ///
+        /// - "wss://*.example.com" : allows connecting to any subdomain of "example.com"
+        ///
+        /// - "ws://localhost:*": allows connecting to any port on localhost
+        url: String,
+    },
+}
+
+// Ensure `WebSocketScopeEntry` and `scope::EntryRaw` is kept in sync
+fn _f() {
+    match scope::EntryRaw::Value(String::new()) {
+        scope::EntryRaw::Value(url) => WebSocketScopeEntry::Value(url),
+        scope::EntryRaw::Object { url } => WebSocketScopeEntry::Object { url },
+    };
+    match WebSocketScopeEntry::Value(String::new()) {
+        WebSocketScopeEntry::Value(url) => scope::EntryRaw::Value(url),
+        WebSocketScopeEntry::Object { url } => scope::EntryRaw::Object { url },
+    };
+}
 
 fn main() {
     tauri_plugin::Builder::new(COMMANDS)
         .global_api_script_path("./api-iife.js")
+        .global_scope_schema(schemars::schema_for!(WebSocketScopeEntry))
         .build();
 }
@@ -0,0 +1,852 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Open connections. Client connections are kept alive with a heartbeat and re-established when they drop.
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, Stream, StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, Mutex},
+    time::{sleep_until, Instant},
+};
+use tokio_tungstenite::{
+    client_async_with_config,
+    tungstenite::{handshake::client::Request, http::header::SEC_WEBSOCKET_PROTOCOL, Message},
+    Connector, MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{
+    deflate::{self, DeflateStream},
+    proxy::ProxyConfig,
+    tls, CloseFrame, ConnectionConfig, Error, Id, Result, WebSocketMessage,
+};
+
+type WebSocket = WebSocketStream<DeflateStream<MaybeTlsStream<TcpStream>>>;
+type WebSocketWriter = SplitSink<WebSocket, Message>;
+type WebSocketReader = SplitStream<WebSocket>;
+
+/// How a dropped connection is re-established.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconnectConfig {
+    /// Gives up after this many failed attempts in a row. Unlimited by default.
+    pub max_attempts: Option<u32>,
+    /// Delay in milliseconds before the first attempt. Defaults to 1000.
+    pub initial_delay: Option<u64>,
+    /// Upper bound in milliseconds of the delay between attempts. Defaults to 30000.
+    pub max_delay: Option<u64>,
+    /// Factor applied to the delay after each failed attempt. Defaults to 2.
+    pub multiplier: Option<f64>,
+}
+
+impl ReconnectConfig {
+    fn delay(&self, attempt: u32) -> Duration {
+        let initial = self.initial_delay.unwrap_or(1000) as f64;
+        let max = self.max_delay.unwrap_or(30_000) as f64;
+        let multiplier = self.multiplier.unwrap_or(2.0).max(1.0);
+        let delay = initial * multiplier.powi(attempt.saturating_sub(1) as i32);
+        Duration::from_millis(delay.min(max) as u64)
+    }
+}
+
+/// Pings the server and drops the connection if nothing is received in time,
+/// to detect half-open sockets.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatConfig {
+    /// Milliseconds without receiving anything before a ping is sent.
+    pub interval: u64,
+    /// Milliseconds to wait for an answer to the ping.
+    pub timeout: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectionState {
+    Connecting,
+    Open,
+    Reconnecting,
+    Closed,
+}
+
+/// Who opened a connection. The webviews can only use the connections they own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Owner {
+    /// Opened with [`crate::WebSocket::connect`].
+    Rust,
+    /// Opened by the webview with this label, or accepted by a server it started.
+    Webview(String),
+}
+
+struct Connection {
+    owner: Owner,
+    /// `None` while the connection is being re-established.
+    ///
+    /// Locked on its own so a slow peer doesn't block the other connections.
+    writer: Option<Arc<Mutex<WebSocketWriter>>>,
+    /// Set once a close frame was sent, so the connection is not re-established.
+    closing: bool,
+    /// Whether `permessage-deflate` was negotiated, so the messages sent are compressed.
+    compresses: bool,
+    /// Observers added after the connection was opened.
+    subscribers: Vec<mpsc::UnboundedSender<WebSocketMessage>>,
+}
+
+/// Stream of the messages and state changes of a connection.
+///
+/// It ends once the connection is closed for good.
+pub struct MessageStream(mpsc::UnboundedReceiver<WebSocketMessage>);
+
+impl Stream for MessageStream {
+    type Item = WebSocketMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+impl MessageStream {
+    pub(crate) fn channel() -> (mpsc::UnboundedSender<WebSocketMessage>, Self) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, Self(rx))
+    }
+}
+
+/// Connections opened by the client and accepted by the servers, shared by their tasks.
+#[derive(Default, Clone)]
+pub(crate) struct ConnectionManager(Arc<Mutex<HashMap<Id, Connection>>>);
+
+impl ConnectionManager {
+    pub async fn send(&self, id: Id, message: Message) -> Result<()> {
+        self.write(id, message, None).await
+    }
+
+    /// Sends a message on behalf of `owner`, as if the connections it doesn't own didn't exist.
+    pub async fn send_as(&self, owner: &Owner, id: Id, message: Message) -> Result<()> {
+        self.write(id, message, Some(owner)).await
+    }
+
+    async fn write(&self, id: Id, message: Message, owner: Option<&Owner>) -> Result<()> {
+        let (writer, compresses) = {
+            let mut connections = self.0.lock().await;
+            let connection = connections
+                .get_mut(&id)
+                .filter(|connection| !owner.is_some_and(|owner| connection.owner != *owner))
+                .ok_or(Error::ConnectionNotFound(id))?;
+            let close = matches!(message, Message::Close(_));
+            connection.closing |= close;
+            match &connection.writer {
+                Some(writer) => (writer.clone(), connection.compresses),
+                // closing stops the pending reconnection
+                None if close => return Ok(()),
+                None => return Err(Error::ConnectionNotOpen(id)),
+            }
+        };
+        let message = if compresses {
+            deflate::compress(message)?
+        } else {
+            message
+        };
+        writer.lock().await.send(message).await?;
+        Ok(())
+    }
+
+    /// Registers the connection, returning the half it is read from.
+    pub async fn insert(&self, id: Id, owner: Owner, ws_stream: WebSocket) -> WebSocketReader {
+        let compresses = ws_stream.get_ref().compresses();
+        let (write, read) = ws_stream.split();
+        self.0.lock().await.insert(
+            id,
+            Connection {
+                owner,
+                writer: Some(Arc::new(Mutex::new(write))),
+                closing: false,
+                compresses,
+                subscribers: Vec::new(),
+            },
+        );
+        read
+    }
+
+    /// Replaces the stream of a connection that was re-established.
+    async fn reattach(&self, id: Id, ws_stream: WebSocket) -> WebSocketReader {
+        let compresses = ws_stream.get_ref().compresses();
+        let (write, read) = ws_stream.split();
+        if let Some(connection) = self.0.lock().await.get_mut(&id) {
+            connection.writer.replace(Arc::new(Mutex::new(write)));
+            connection.compresses = compresses;
+        }
+        read
+    }
+
+    /// Observes a connection that is already open.
+    pub async fn subscribe(&self, id: Id) -> Result<MessageStream> {
+        let (tx, stream) = MessageStream::channel();
+        self.0
+            .lock()
+            .await
+            .get_mut(&id)
+            .ok_or(Error::ConnectionNotFound(id))?
+            .subscribers
+            .push(tx);
+        Ok(stream)
+    }
+
+    async fn notify(
+        &self,
+        id: Id,
+        message: WebSocketMessage,
+        on_message: &impl Fn(WebSocketMessage),
+    ) {
+        if let Some(connection) = self.0.lock().await.get_mut(&id) {
+            connection
+                .subscribers
+                .retain(|subscriber| subscriber.send(message.clone()).is_ok());
+        }
+        on_message(message);
+    }
+
+    async fn is_closing(&self, id: Id) -> bool {
+        self.0
+            .lock()
+            .await
+            .get(&id)
+            .map_or(true, |connection| connection.closing)
+    }
+
+    /// Keeps the connection open, re-establishing it if configured, and forwards
+    /// everything received to `on_message` and the subscribers until it is closed for good.
+    pub async fn run(
+        &self,
+        id: Id,
+        request: Request,
+        config: Option<ConnectionConfig>,
+        tls_connector: Option<Connector>,
+        read: WebSocketReader,
+        on_message: impl Fn(WebSocketMessage),
+    ) {
+        let reconnect = config.as_ref().and_then(|c| c.reconnect.clone());
+        let heartbeat = config.as_ref().and_then(|c| c.heartbeat.clone());
+        let state = WebSocketMessage::State;
+
+        let mut next_read = Some(read);
+        let mut attempt = 0;
+        while let Some(read) = next_read.take() {
+            self.notify(id, state(ConnectionState::Open), &on_message)
+                .await;
+
+            self.read_messages(id, read, heartbeat.as_ref(), &on_message)
+                .await;
+
+            // dropping the writer closes the socket once the sends in progress finish
+            if let Some(connection) = self.0.lock().await.get_mut(&id) {
+                connection.writer.take();
+            }
+
+            let Some(reconnect) = &reconnect else {
+                break;
+            };
+            while !self.is_closing(id).await {
+                attempt += 1;
+                if reconnect.max_attempts.is_some_and(|max| attempt > max) {
+                    break;
+                }
+                self.notify(id, state(ConnectionState::Reconnecting), &on_message)
+                    .await;
+                tokio::time::sleep(reconnect.delay(attempt)).await;
+                if self.is_closing(id).await {
+                    break;
+                }
+
+                self.notify(id, state(ConnectionState::Connecting), &on_message)
+                    .await;
+                // the original request is reused so its headers are sent again
+                match open(request.clone(), config.clone(), tls_connector.clone()).await {
+                    Ok((ws_stream, _)) => {
+                        attempt = 0;
+                        next_read.replace(self.reattach(id, ws_stream).await);
+                        break;
+                    }
+                    Err(e) => {
+                        self.notify(id, WebSocketMessage::Error(e.to_string()), &on_message)
+                            .await
+                    }
+                }
+            }
+        }
+
+        self.finish(id, &on_message).await;
+    }
+
+    /// Forwards everything received on an accepted connection to `on_message`
+    /// and the subscribers until it is closed.
+    pub async fn serve(
+        &self,
+        id: Id,
+        read: WebSocketReader,
+        on_message: impl Fn(WebSocketMessage),
+    ) {
+        self.notify(
+            id,
+            WebSocketMessage::State(ConnectionState::Open),
+            &on_message,
+        )
+        .await;
+        self.read_messages(id, read, None, &on_message).await;
+        self.finish(id, &on_message).await;
+    }
+
+    async fn finish(&self, id: Id, on_message: &impl Fn(WebSocketMessage)) {
+        let closed = WebSocketMessage::State(ConnectionState::Closed);
+        // dropping the subscribers ends their streams
+        let subscribers = self
+            .0
+            .lock()
+            .await
+            .remove(&id)
+            .map(|connection| connection.subscribers)
+            .unwrap_or_default();
+        for subscriber in subscribers {
+            let _ = subscriber.send(closed.clone());
+        }
+        on_message(closed);
+    }
+
+    /// Forwards the received messages to `on_message` until the connection drops.
+    async fn read_messages(
+        &self,
+        id: Id,
+        mut read: WebSocketReader,
+        heartbeat: Option<&HeartbeatConfig>,
+        on_message: &impl Fn(WebSocketMessage),
+    ) {
+        let interval = heartbeat.map(|h| Duration::from_millis(h.interval));
+        let timeout = heartbeat.map(|h| Duration::from_millis(h.timeout));
+        let mut ping_at = interval.map(|interval| Instant::now() + interval);
+        let mut pong_deadline: Option<Instant> = None;
+
+        loop {
+            let timer = pong_deadline.or(ping_at);
+            let message = tokio::select! {
+                message = read.next() => message,
+                _ = sleep_until(timer.unwrap_or_else(Instant::now)), if timer.is_some() => {
+                    if pong_deadline.is_some() {
+                        let error = WebSocketMessage::Error(Error::HeartbeatTimeout.to_string());
+                        self.notify(id, error, on_message).await;
+                        return;
+                    }
+                    let _ = self.send(id, Message::Ping(Default::default())).await;
+                    pong_deadline = timeout.map(|timeout| Instant::now() + timeout);
+                    continue;
+                }
+            };
+
+            // any message proves the connection is alive
+            pong_deadline = None;
+            ping_at = interval.map(|interval| Instant::now() + interval);
+
+            let message = match message {
+                Some(Ok(Message::Text(t))) => WebSocketMessage::Text(t.to_string()),
+                Some(Ok(Message::Binary(t))) => WebSocketMessage::Binary(t.to_vec()),
+                Some(Ok(Message::Ping(t))) => WebSocketMessage::Ping(t.to_vec()),
+                Some(Ok(Message::Pong(t))) => WebSocketMessage::Pong(t.to_vec()),
+                Some(Ok(Message::Close(t))) => WebSocketMessage::Close(t.map(|v| CloseFrame {
+                    code: v.code.into(),
+                    reason: v.reason.to_string(),
+                })),
+                // This value can't be received.
+                Some(Ok(Message::Frame(_))) => continue,
+                // read errors are fatal
+                Some(Err(e)) => {
+                    let error = WebSocketMessage::Error(Error::from(e).to_string());
+                    self.notify(id, error, on_message).await;
+                    return;
+                }
+                None => return,
+            };
+            self.notify(id, message, on_message).await;
+        }
+    }
+}
+
+/// Opens a connection, through the configured proxy if any,
+/// and returns it with the subprotocol selected by the server.
+pub(crate) async fn open(
+    request: Request,
+    config: Option<ConnectionConfig>,
+    tls_connector: Option<Connector>,
+) -> Result<(WebSocket, Option<String>)> {
+    let host = request.uri().host().unwrap_or_default().to_string();
+    let port = request.uri().port_u16().unwrap_or_else(|| {
+        if request.uri().scheme_str() == Some("wss") {
+            443
+        } else {
+            80
+        }
+    });
+    let proxy = config
+        .as_ref()
+        .and_then(|c| c.proxy.clone())
+        .map(ProxyConfig::from)
+        .filter(|proxy| !proxy.bypasses(&host));
+    let compression = config.as_ref().is_some_and(|c| c.compression);
+    let ws_config = config.map(Into::into);
+
+    let stream = match proxy {
+        Some(proxy) => proxy.connect(&host, port).await?,
+        // IPv6 addresses are in brackets in URLs
+        None => {
+            TcpStream::connect((host.trim_start_matches('[').trim_end_matches(']'), port)).await?
+        }
+    };
+    let stream = tls::wrap(stream, &request, tls_connector).await?;
+    let (ws_stream, response) =
+        client_async_with_config(request, DeflateStream::new(stream, compression), ws_config)
+            .await?;
+
+    let protocol = response
+        .headers()
+        .get(SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string);
+
+    Ok((ws_stream, protocol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_request;
+    use tokio::{net::TcpListener, sync::mpsc};
+    use tokio_tungstenite::tungstenite::{
+        handshake::server::{Request as ServerRequest, Response as ServerResponse},
+        http::header::SEC_WEBSOCKET_EXTENSIONS,
+    };
+
+    /// Spawns a local server echoing every message back to the client.
+    async fn spawn_echo_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let ws_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    let (write, read) = ws_stream.split();
+                    let _ = read.forward(write).await;
+                });
+            }
+        });
+        url
+    }
+
+    fn config(value: serde_json::Value) -> Option<ConnectionConfig> {
+        Some(serde_json::from_value(value).unwrap())
+    }
+
+    async fn connect_to(
+        manager: &'static ConnectionManager,
+        url: String,
+        config: Option<ConnectionConfig>,
+    ) -> (Id, mpsc::UnboundedReceiver<WebSocketMessage>) {
+        let request = build_request(url, config.as_ref()).unwrap();
+        let (ws_stream, _) = open(request.clone(), config.clone(), None).await.unwrap();
+        let id = rand::random();
+        let read = manager.insert(id, owner(), ws_stream).await;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(
+            manager.run(id, request, config, None, read, move |message| {
+                let _ = tx.send(message);
+            }),
+        );
+        (id, rx)
+    }
+
+    /// Receives the next message that is not a connection state change.
+    async fn next_message(
+        messages: &mut mpsc::UnboundedReceiver<WebSocketMessage>,
+    ) -> Option<WebSocketMessage> {
+        loop {
+            match messages.recv().await {
+                Some(WebSocketMessage::State(_)) => continue,
+                message => return message,
+            }
+        }
+    }
+
+    fn manager() -> &'static ConnectionManager {
+        Box::leak(Box::default())
+    }
+
+    fn owner() -> Owner {
+        Owner::Webview("main".into())
+    }
+
+    const fn state(state: ConnectionState) -> Option<WebSocketMessage> {
+        Some(WebSocketMessage::State(state))
+    }
+
+    #[tokio::test]
+    async fn delivers_echoed_messages() {
+        let manager = manager();
+        let (id, mut messages) = connect_to(manager, spawn_echo_server().await, None).await;
+        assert_eq!(messages.recv().await, state(ConnectionState::Open));
+
+        let text: WebSocketMessage =
+            serde_json::from_str(r#"{ "type": "Text", "data": "hello" }"#).unwrap();
+        manager.send(id, text.try_into().unwrap()).await.unwrap();
+        manager
+            .send(
+                id,
+                WebSocketMessage::Binary(vec![1, 2, 3]).try_into().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messages.recv().await,
+            Some(WebSocketMessage::Text("hello".into()))
+        );
+        assert_eq!(
+            messages.recv().await,
+            Some(WebSocketMessage::Binary(vec![1, 2, 3]))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_sends_from_other_owners() {
+        let manager = manager();
+        let (id, mut messages) = connect_to(manager, spawn_echo_server().await, None).await;
+        assert_eq!(messages.recv().await, state(ConnectionState::Open));
+
+        for other in [Owner::Rust, Owner::Webview("other".into())] {
+            assert!(matches!(
+                manager
+                    .send_as(&other, id, Message::Text("hijacked".into()))
+                    .await,
+                Err(Error::ConnectionNotFound(_))
+            ));
+            assert!(matches!(
+                manager.send_as(&other, id, Message::Close(None)).await,
+                Err(Error::ConnectionNotFound(_))
+            ));
+        }
+
+        manager
+            .send_as(&owner(), id, Message::Text("mine".into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            messages.recv().await,
+            Some(WebSocketMessage::Text("mine".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribers_observe_the_same_connection() {
+        let manager = manager();
+        let (id, mut messages) = connect_to(manager, spawn_echo_server().await, None).await;
+        assert_eq!(messages.recv().await, state(ConnectionState::Open));
+        assert!(matches!(
+            manager.subscribe(id.wrapping_add(1)).await,
+            Err(Error::ConnectionNotFound(_))
+        ));
+
+        let mut observer = manager.subscribe(id).await.unwrap();
+        manager
+            .send(id, Message::Text("shared".into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            messages.recv().await,
+            Some(WebSocketMessage::Text("shared".into()))
+        );
+        assert_eq!(
+            observer.next().await,
+            Some(WebSocketMessage::Text("shared".into()))
+        );
+
+        manager.send(id, Message::Close(None)).await.unwrap();
+        // the stream ends once the connection is closed
+        let rest = observer.collect::<Vec<_>>().await;
+        assert_eq!(rest.last(), state(ConnectionState::Closed).as_ref());
+    }
+
+    #[tokio::test]
+    async fn delivers_close_frame_and_forgets_connection() {
+        let manager = manager();
+        let config = config(serde_json::json!({ "reconnect": {} }));
+        let (id, mut messages) = connect_to(manager, spawn_echo_server().await, config).await;
+
+        let close = WebSocketMessage::Close(Some(CloseFrame {
+            code: 1000,
+            reason: "bye".into(),
+        }));
+        manager.send(id, close.try_into().unwrap()).await.unwrap();
+
+        assert_eq!(
+            next_message(&mut messages).await,
+            Some(WebSocketMessage::Close(Some(CloseFrame {
+                code: 1000,
+                reason: "bye".into(),
+            })))
+        );
+        // closed by the client so it isn't re-established
+        assert_eq!(messages.recv().await, state(ConnectionState::Closed));
+        assert_eq!(messages.recv().await, None);
+        assert!(matches!(
+            manager.send(id, Message::Text("late".into())).await,
+            Err(Error::ConnectionNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)]
+    async fn reconnects_with_original_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (headers_tx, mut headers) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut first = true;
+            while let Ok((stream, _)) = listener.accept().await {
+                let headers_tx = headers_tx.clone();
+                let callback = move |request: &ServerRequest, response: ServerResponse| {
+                    let token = request.headers().get("x-token").cloned();
+                    let _ = headers_tx.send(token);
+                    Ok(response)
+                };
+                let ws_stream = tokio_tungstenite::accept_hdr_async(stream, callback)
+                    .await
+                    .unwrap();
+                if std::mem::take(&mut first) {
+                    // drop the first connection without a closing handshake
+                    drop(ws_stream);
+                    continue;
+                }
+                tokio::spawn(async move {
+                    let (write, read) = ws_stream.split();
+                    let _ = read.forward(write).await;
+                });
+            }
+        });
+
+        let manager = manager();
+        let config = config(serde_json::json!({
+            "headers": [["x-token", "secret"]],
+            "reconnect": { "initialDelay": 10 }
+        }));
+        let (id, mut messages) = connect_to(manager, url, config).await;
+
+        assert_eq!(messages.recv().await, state(ConnectionState::Open));
+        assert!(matches!(
+            messages.recv().await,
+            Some(WebSocketMessage::Error(_))
+        ));
+        assert_eq!(messages.recv().await, state(ConnectionState::Reconnecting));
+        assert_eq!(messages.recv().await, state(ConnectionState::Connecting));
+        assert_eq!(messages.recv().await, state(ConnectionState::Open));
+
+        manager
+            .send(id, Message::Text("again".into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            messages.recv().await,
+            Some(WebSocketMessage::Text("again".into()))
+        );
+        for _ in 0..2 {
+            assert_eq!(headers.recv().await, Some(Some("secret".parse().unwrap())));
+        }
+    }
+
+    #[tokio::test]
+    async fn heartbeat_detects_unresponsive_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            // never read from the socket, so pings are not answered
+            let _ws_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let manager = manager();
+        let config = config(serde_json::json!({
+            "heartbeat": { "interval": 20, "timeout": 20 }
+        }));
+        let (_id, mut messages) = connect_to(manager, url, config).await;
+
+        assert_eq!(messages.recv().await, state(ConnectionState::Open));
+        assert_eq!(
+            messages.recv().await,
+            Some(WebSocketMessage::Error(Error::HeartbeatTimeout.to_string()))
+        );
+        assert_eq!(messages.recv().await, state(ConnectionState::Closed));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)]
+    async fn reports_negotiated_subprotocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let callback = |request: &ServerRequest, mut response: ServerResponse| {
+                let offered = request
+                    .headers()
+                    .get(SEC_WEBSOCKET_PROTOCOL)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default();
+                if offered
+                    .split(',')
+                    .any(|p| p.trim() == "graphql-transport-ws")
+                {
+                    response.headers_mut().insert(
+                        SEC_WEBSOCKET_PROTOCOL,
+                        "graphql-transport-ws".parse().unwrap(),
+                    );
+                }
+                Ok(response)
+            };
+            let _ws_stream = tokio_tungstenite::accept_hdr_async(stream, callback)
+                .await
+                .unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let config = config(serde_json::json!({
+            "protocols": ["graphql-ws", "graphql-transport-ws"]
+        }));
+        let request = build_request(url, config.as_ref()).unwrap();
+        let (_, protocol) = open(request, config, None).await.unwrap();
+        assert_eq!(protocol.as_deref(), Some("graphql-transport-ws"));
+    }
+
+    #[tokio::test]
+    async fn compresses_messages_when_negotiated() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    // accepts the extension as offered
+                    #[allow(clippy::result_large_err)]
+                    let callback = |request: &ServerRequest, mut response: ServerResponse| {
+                        if let Some(offer) = request.headers().get(SEC_WEBSOCKET_EXTENSIONS) {
+                            response
+                                .headers_mut()
+                                .insert(SEC_WEBSOCKET_EXTENSIONS, offer.clone());
+                        }
+                        Ok(response)
+                    };
+                    let mut ws_stream = tokio_tungstenite::accept_hdr_async(
+                        DeflateStream::new(stream, true),
+                        callback,
+                    )
+                    .await
+                    .unwrap();
+                    let compresses = ws_stream.get_ref().compresses();
+                    while let Some(Ok(message)) = ws_stream.next().await {
+                        if message.is_close() {
+                            break;
+                        }
+                        let message = if compresses {
+                            deflate::compress(message).unwrap()
+                        } else {
+                            message
+                        };
+                        ws_stream.send(message).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let manager = manager();
+        let text = "compressed ".repeat(1000);
+        // the echo server doesn't know about the extension
+        let servers = [
+            (url.clone(), true, true),
+            (url, false, false),
+            (spawn_echo_server().await, true, false),
+        ];
+        for (url, compression, negotiated) in servers {
+            let config = config(serde_json::json!({ "compression": compression }));
+            let (id, mut messages) = connect_to(manager, url, config).await;
+            assert_eq!(messages.recv().await, state(ConnectionState::Open));
+            assert_eq!(manager.0.lock().await[&id].compresses, negotiated);
+
+            manager
+                .send(id, Message::Text(text.clone().into()))
+                .await
+                .unwrap();
+            assert_eq!(
+                next_message(&mut messages).await,
+                Some(WebSocketMessage::Text(text.clone()))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_peer_does_not_block_other_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            // never read from the socket, so the client's sends eventually stall
+            let _ws_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let manager = manager();
+        let (slow, mut slow_messages) = connect_to(manager, url, None).await;
+        assert_eq!(slow_messages.recv().await, state(ConnectionState::Open));
+        tokio::spawn(async move {
+            loop {
+                let message = Message::Binary(vec![0; 1024 * 1024].into());
+                if manager.send(slow, message).await.is_err() {
+                    break;
+                }
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (id, mut messages) = connect_to(manager, spawn_echo_server().await, None).await;
+        assert_eq!(messages.recv().await, state(ConnectionState::Open));
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            manager.send(id, Message::Text("unblocked".into())),
+        )
+        .await
+        .expect("send blocked by another connection")
+        .unwrap();
+        assert_eq!(
+            messages.recv().await,
+            Some(WebSocketMessage::Text("unblocked".into()))
+        );
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let reconnect: ReconnectConfig = serde_json::from_value(serde_json::json!({
+            "initialDelay": 100,
+            "maxDelay": 1000
+        }))
+        .unwrap();
+        assert_eq!(reconnect.delay(1), Duration::from_millis(100));
+        assert_eq!(reconnect.delay(3), Duration::from_millis(400));
+        assert_eq!(reconnect.delay(10), Duration::from_millis(1000));
+    }
+}
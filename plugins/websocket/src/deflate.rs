@@ -0,0 +1,552 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! The `permessage-deflate` extension (RFC 7692), which tungstenite doesn't implement.
+//!
+//! [`DeflateStream`] sits below tungstenite and inflates the compressed messages before they are
+//! read, while the messages sent are compressed into frames of their own with [`compress`].
+
+use std::{
+    io::{self, Write},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use flate2::{write::DeflateEncoder, Compression, Decompress, FlushDecompress, Status};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::{
+    protocol::frame::{
+        coding::{Data, OpCode},
+        Frame,
+    },
+    Message,
+};
+
+/// The extension offered by the client.
+///
+/// The client compresses every message on its own, so it can't be asked to use a smaller window.
+pub(crate) const OFFER: &str = "permessage-deflate; client_no_context_takeover";
+
+/// Ends the payload of every compressed message, left out by the sender.
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Inflated messages are forwarded in frames of at most this size, so a small message
+/// inflating to a large one is bounded by the maximum message size of tungstenite.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The handshake is forwarded as is if its headers don't end within this size.
+const MAX_HANDSHAKE_SIZE: usize = 64 * 1024;
+
+enum State {
+    /// Reading the handshake request or response, up to the end of its headers.
+    Handshake,
+    /// The extension wasn't negotiated, everything is forwarded as is.
+    Plain,
+    /// Reading the header of the next frame.
+    Header,
+    /// Forwarding the payload of a frame that isn't compressed.
+    Forward { remaining: u64 },
+    /// Inflating the payload of a frame of a compressed message.
+    Inflate {
+        remaining: u64,
+        mask: Option<[u8; 4]>,
+        position: usize,
+        fin: bool,
+    },
+    /// Inflating the trailer of a compressed message, then ending it.
+    Trailer { fed: usize },
+}
+
+/// A stream whose compressed messages are inflated, once `permessage-deflate` was negotiated
+/// in the handshake going through it.
+pub(crate) struct DeflateStream<S> {
+    inner: S,
+    state: State,
+    /// Whether the messages sent may be compressed.
+    compresses: bool,
+    /// Bytes read from the inner stream that weren't processed yet.
+    input: Vec<u8>,
+    /// Bytes processed that weren't read yet, starting at `read`.
+    output: Vec<u8>,
+    read: usize,
+    decompress: Decompress,
+    /// Set once the compressed message being received ended its deflate stream.
+    ended: bool,
+    /// Whether the data message being received is compressed.
+    compressed: bool,
+    /// The opcode of the compressed message being received, until its first frame was forwarded.
+    opcode: Option<u8>,
+    /// Whether the frames of the compressed message being received are masked.
+    masked: bool,
+}
+
+impl<S> DeflateStream<S> {
+    /// Wraps a stream before the handshake. The extension is only used if `enabled`.
+    pub fn new(inner: S, enabled: bool) -> Self {
+        Self {
+            inner,
+            state: if enabled {
+                State::Handshake
+            } else {
+                State::Plain
+            },
+            compresses: false,
+            input: Vec::new(),
+            output: Vec::new(),
+            read: 0,
+            decompress: Decompress::new(false),
+            ended: false,
+            compressed: false,
+            opcode: None,
+            masked: false,
+        }
+    }
+
+    /// Whether the messages sent can be compressed with [`compress`].
+    pub fn compresses(&self) -> bool {
+        self.compresses
+    }
+
+    /// Processes the buffered input, returning whether anything changed.
+    fn process(&mut self) -> io::Result<bool> {
+        match self.state {
+            State::Handshake => {
+                let Some(end) = self.input.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    if self.input.len() > MAX_HANDSHAKE_SIZE {
+                        self.state = State::Plain;
+                        return Ok(true);
+                    }
+                    return Ok(false);
+                };
+                let head = self.input.drain(..end + 4).collect::<Vec<_>>();
+                self.state = match negotiated(&head) {
+                    Some(parameters) => {
+                        // the window of the messages sent can't be made smaller
+                        self.compresses = !parameters.contains("client_max_window_bits");
+                        State::Header
+                    }
+                    None => State::Plain,
+                };
+                self.output = head;
+                Ok(true)
+            }
+            State::Plain => {
+                if self.input.is_empty() {
+                    return Ok(false);
+                }
+                std::mem::swap(&mut self.output, &mut self.input);
+                Ok(true)
+            }
+            State::Header => Ok(self.read_header()),
+            State::Forward { remaining } => {
+                if remaining == 0 {
+                    self.state = State::Header;
+                    return Ok(true);
+                }
+                if self.input.is_empty() {
+                    return Ok(false);
+                }
+                let n = remaining.min(self.input.len() as u64);
+                self.output.extend(self.input.drain(..n as usize));
+                self.state = State::Forward {
+                    remaining: remaining - n,
+                };
+                Ok(true)
+            }
+            State::Inflate {
+                remaining,
+                mask,
+                position,
+                fin,
+            } => {
+                if remaining == 0 {
+                    // forward what the decompressor still holds
+                    let (_, full) = self.inflate(&[])?;
+                    if !full {
+                        self.state = if fin {
+                            State::Trailer { fed: 0 }
+                        } else {
+                            State::Header
+                        };
+                    }
+                    return Ok(true);
+                }
+                if self.input.is_empty() {
+                    return Ok(false);
+                }
+                let n = remaining.min(self.input.len() as u64) as usize;
+                let mut payload = self.input[..n].to_vec();
+                if let Some(mask) = mask {
+                    for (i, byte) in payload.iter_mut().enumerate() {
+                        *byte ^= mask[(position + i) % 4];
+                    }
+                }
+                let (consumed, _) = self.inflate(&payload)?;
+                self.input.drain(..consumed);
+                self.state = State::Inflate {
+                    remaining: remaining - consumed as u64,
+                    mask,
+                    position: position + consumed,
+                    fin,
+                };
+                Ok(true)
+            }
+            State::Trailer { fed } => {
+                let (consumed, full) = self.inflate(&TRAILER[fed..])?;
+                let fed = fed + consumed;
+                self.state = State::Trailer { fed };
+                if fed == TRAILER.len() && !full {
+                    self.emit(true, &[]);
+                    if self.ended {
+                        self.decompress.reset(false);
+                        self.ended = false;
+                    }
+                    self.compressed = false;
+                    self.state = State::Header;
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Reads the header of the next frame if it was received, returning whether it was.
+    fn read_header(&mut self) -> bool {
+        let [first, second] = match self.input.get(..2) {
+            Some(&[first, second]) => [first, second],
+            _ => return false,
+        };
+        let (length, mut size) = match second & 0x7f {
+            126 => match self.input.get(2..4) {
+                Some(bytes) => (u16::from_be_bytes([bytes[0], bytes[1]]) as u64, 4),
+                None => return false,
+            },
+            127 => match self.input.get(2..10) {
+                Some(bytes) => (u64::from_be_bytes(bytes.try_into().unwrap()), 10),
+                None => return false,
+            },
+            length => (length as u64, 2),
+        };
+        let masked = second & 0x80 != 0;
+        let mask = if masked {
+            match self.input.get(size..size + 4) {
+                Some(mask) => {
+                    size += 4;
+                    Some([mask[0], mask[1], mask[2], mask[3]])
+                }
+                None => return false,
+            }
+        } else {
+            None
+        };
+
+        let fin = first & 0x80 != 0;
+        let rsv1 = first & 0x40 != 0;
+        let opcode = first & 0x0f;
+        let inflated = match opcode {
+            // control frames are never compressed
+            0x8.. => false,
+            // continuation frames belong to the message they continue
+            0 => self.compressed,
+            _ => {
+                self.compressed = rsv1;
+                rsv1
+            }
+        };
+
+        if inflated {
+            self.input.drain(..size);
+            if opcode != 0 {
+                self.opcode = Some(opcode);
+                self.masked = masked;
+            }
+            self.state = State::Inflate {
+                remaining: length,
+                mask,
+                position: 0,
+                fin,
+            };
+        } else {
+            self.output.extend(self.input.drain(..size));
+            self.state = State::Forward { remaining: length };
+        }
+        true
+    }
+
+    /// Inflates the data, forwarding up to [`CHUNK_SIZE`] bytes in a frame.
+    ///
+    /// Returns how much of the data was consumed and whether more may be inflated from it.
+    fn inflate(&mut self, data: &[u8]) -> io::Result<(usize, bool)> {
+        // anything after the end of the deflate stream is ignored
+        if self.ended {
+            return Ok((data.len(), false));
+        }
+        let mut inflated = Vec::with_capacity(CHUNK_SIZE);
+        let before = self.decompress.total_in();
+        let status = self
+            .decompress
+            .decompress_vec(data, &mut inflated, FlushDecompress::Sync)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let consumed = (self.decompress.total_in() - before) as usize;
+        if status == Status::StreamEnd {
+            self.ended = true;
+        } else if consumed == 0 && inflated.is_empty() && !data.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid compressed message",
+            ));
+        }
+        let full = inflated.len() == inflated.capacity();
+        if !inflated.is_empty() {
+            self.emit(false, &inflated);
+        }
+        Ok((consumed, full))
+    }
+
+    /// Forwards a frame of the compressed message being received.
+    fn emit(&mut self, fin: bool, payload: &[u8]) {
+        let opcode = self.opcode.take().unwrap_or(0);
+        write_header(
+            &mut self.output,
+            if fin { 0x80 } else { 0 } | opcode,
+            self.masked,
+            payload.len(),
+        );
+        if self.masked {
+            // a zero mask leaves the payload as it is
+            self.output.extend([0; 4]);
+        }
+        self.output.extend(payload);
+    }
+}
+
+/// Writes the header of a frame, without its mask.
+fn write_header(output: &mut Vec<u8>, first: u8, masked: bool, length: usize) {
+    let mask = if masked { 0x80 } else { 0 };
+    output.push(first);
+    match length {
+        0..=125 => output.push(mask | length as u8),
+        126..=0xffff => {
+            output.push(mask | 126);
+            output.extend((length as u16).to_be_bytes());
+        }
+        _ => {
+            output.push(mask | 127);
+            output.extend((length as u64).to_be_bytes());
+        }
+    }
+}
+
+/// Returns the parameters of `permessage-deflate` if it is in the `Sec-WebSocket-Extensions`
+/// headers of the handshake.
+fn negotiated(head: &[u8]) -> Option<String> {
+    let head = std::str::from_utf8(head).ok()?;
+    head.split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-extensions"))
+        .flat_map(|(_, value)| value.split(','))
+        .find_map(|extension| {
+            let (name, parameters) = extension.split_once(';').unwrap_or((extension, ""));
+            (name.trim() == "permessage-deflate").then(|| parameters.to_ascii_lowercase())
+        })
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.read < this.output.len() {
+                let n = buf.remaining().min(this.output.len() - this.read);
+                buf.put_slice(&this.output[this.read..this.read + n]);
+                this.read += n;
+                if this.read == this.output.len() {
+                    this.output.clear();
+                    this.read = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if matches!(this.state, State::Plain) && this.input.is_empty() {
+                return Pin::new(&mut this.inner).poll_read(cx, buf);
+            }
+            if this.process()? {
+                continue;
+            }
+
+            let mut chunk = [0; 8 * 1024];
+            let mut chunk = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            if chunk.filled().is_empty() {
+                // the stream ended, what is left is forwarded for tungstenite to report it
+                this.state = State::Plain;
+                if this.input.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                continue;
+            }
+            this.input.extend_from_slice(chunk.filled());
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Compresses a text or binary message into a frame of its own. Other messages are returned as is.
+pub(crate) fn compress(message: Message) -> io::Result<Message> {
+    let (data, opcode) = match &message {
+        Message::Text(text) => (text.as_bytes(), Data::Text),
+        Message::Binary(data) => (&data[..], Data::Binary),
+        _ => return Ok(message),
+    };
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.flush()?;
+    let mut compressed = std::mem::take(encoder.get_mut());
+    if compressed.ends_with(&TRAILER) {
+        compressed.truncate(compressed.len() - TRAILER.len());
+    }
+
+    let mut frame = Frame::message(compressed, OpCode::Data(opcode), true);
+    frame.header_mut().rsv1 = true;
+    Ok(Message::Frame(frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_tungstenite::{tungstenite::protocol::Role, WebSocketStream};
+
+    const RESPONSE: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\n\
+        Sec-WebSocket-Extensions: permessage-deflate; client_no_context_takeover\r\n\r\n";
+
+    fn frame(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        write_header(&mut frame, first, false, payload.len());
+        frame.extend(payload);
+        frame
+    }
+
+    fn compressed(message: Message) -> Vec<u8> {
+        match compress(message).unwrap() {
+            Message::Frame(frame) => frame.payload().to_vec(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Reads the messages of `received` through a client stream.
+    async fn receive(enabled: bool, received: Vec<u8>) -> Vec<Message> {
+        let (client, mut server) = tokio::io::duplex(1 << 20);
+        server.write_all(&received).await.unwrap();
+        server.write_all(&frame(0x88, &[])).await.unwrap();
+        // the client waits for the server to close the connection after the close handshake
+        tokio::spawn(async move {
+            loop {
+                let mut header = [0; 6];
+                server.read_exact(&mut header).await.unwrap();
+                let mut payload = vec![0; (header[1] & 0x7f) as usize];
+                server.read_exact(&mut payload).await.unwrap();
+                if header[0] & 0x0f == 0x8 {
+                    break;
+                }
+            }
+        });
+
+        let mut stream = DeflateStream::new(client, enabled);
+        let mut head = vec![0; RESPONSE.len()];
+        stream.read_exact(&mut head).await.unwrap();
+        assert_eq!(head, RESPONSE);
+        assert_eq!(stream.compresses(), enabled);
+
+        let messages = WebSocketStream::from_raw_socket(stream, Role::Client, None)
+            .await
+            .take_while(|message| std::future::ready(message.is_ok()))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        messages
+    }
+
+    #[tokio::test]
+    async fn inflates_compressed_messages() {
+        let text = "hello world ".repeat(10_000);
+        let payload = compressed(Message::Text(text.clone().into()));
+        let (start, end) = payload.split_at(payload.len() / 2);
+
+        let mut received = RESPONSE.to_vec();
+        // a fragmented message with a ping in the middle
+        received.extend(frame(0x41, start));
+        received.extend(frame(0x89, b"ping"));
+        received.extend(frame(0x80, end));
+        received.extend(frame(0x81, b"uncompressed"));
+        received.extend(frame(
+            0xc2,
+            &compressed(Message::Binary(vec![1, 2, 3].into())),
+        ));
+        received.extend(frame(0xc1, &compressed(Message::Text("".into()))));
+
+        assert_eq!(
+            receive(true, received).await,
+            vec![
+                Message::Ping("ping".into()),
+                Message::Text(text.into()),
+                Message::Text("uncompressed".into()),
+                Message::Binary(vec![1, 2, 3].into()),
+                Message::Text("".into()),
+                Message::Close(None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn forwards_frames_unless_negotiated() {
+        let mut received = RESPONSE.to_vec();
+        received.extend(frame(0x81, b"uncompressed"));
+        received.extend(frame(0xc1, &compressed(Message::Text("hello".into()))));
+
+        // tungstenite fails on the reserved bit of the compressed message
+        assert_eq!(
+            receive(false, received).await,
+            vec![Message::Text("uncompressed".into())]
+        );
+    }
+
+    #[test]
+    fn reads_the_negotiated_parameters() {
+        assert_eq!(
+            negotiated(RESPONSE).as_deref(),
+            Some(" client_no_context_takeover")
+        );
+        assert_eq!(
+            negotiated(
+                b"HTTP/1.1 101\r\nsec-websocket-extensions: x-custom, permessage-deflate\r\n\r\n"
+            )
+            .as_deref(),
+            Some("")
+        );
+        assert_eq!(
+            negotiated(b"HTTP/1.1 101\r\nUpgrade: websocket\r\n\r\n"),
+            None
+        );
+    }
+}
@@ -1,289 +1,527 @@
-use serde_json::json;
-use futures_util::{stream::SplitSink, SinkExt, StreamExt};
-use http::header::{HeaderName, HeaderValue};
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Expose a WebSocket client to your Tauri frontend.
+
+#![doc(
+    html_logo_url = "https://github.com/tauri-apps/tauri/raw/dev/app-icon.png",
+    html_favicon_url = "https://github.com/tauri-apps/tauri/raw/dev/app-icon.png"
+)]
+
+use http::header::{HeaderName, HeaderValue, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_PROTOCOL};
 use serde::{ser::Serializer, Deserialize, Serialize};
 use tauri::{
-  AppHandle,
-  ipc::Channel,
-  plugin::{Builder as PluginBuilder, TauriPlugin},
-  Manager, Runtime, State, Window,
+    ipc::{Channel, CommandScope, GlobalScope},
+    plugin::{Builder as PluginBuilder, TauriPlugin},
+    AppHandle, Manager, Runtime, State, Webview,
 };
-use tokio::{net::TcpStream, sync::Mutex};
-#[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
-use tokio_tungstenite::connect_async_tls_with_config;
-#[cfg(not(any(feature = "rustls-tls", feature = "native-tls")))]
-use tokio_tungstenite::connect_async_with_config;
+use tokio::sync::Mutex;
 use tokio_tungstenite::{
-  tungstenite::{
-    client::IntoClientRequest,
-    protocol::{CloseFrame as ProtocolCloseFrame, WebSocketConfig},
-    Message,
-  },
-  Connector, MaybeTlsStream, WebSocketStream,
+    tungstenite::{
+        client::IntoClientRequest,
+        handshake::client::Request,
+        protocol::{CloseFrame as ProtocolCloseFrame, WebSocketConfig},
+        Message,
+    },
+    Connector,
 };
 
-use std::collections::HashMap;
-use std::str::FromStr;
+use url::Url;
+
+use std::{str::FromStr, sync::Arc};
+
+mod connection;
+mod deflate;
+mod proxy;
+mod scope;
+mod server;
+mod tls;
+use connection::{ConnectionManager, Owner};
+pub use connection::{ConnectionState, HeartbeatConfig, MessageStream, ReconnectConfig};
+pub use proxy::{BasicAuth, ProxyConfig, UrlOrConfig};
+use scope::{Entry, Scope};
+use server::ServerManager;
+pub use server::{ServerConfig, ServerEvent, ServerInfo};
 
-type Id = u32;
-type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
-type WebSocketWriter = SplitSink<WebSocket, Message>;
-type Result<T> = std::result::Result<T, Error>;
+pub type Id = u32;
+pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, thiserror::Error)]
-enum Error {
-  #[error(transparent)]
-  Websocket(#[from] tokio_tungstenite::tungstenite::Error),
-  #[error("connection not found for the given id: {0}")]
-  ConnectionNotFound(Id),
-  #[error(transparent)]
-  InvalidHeaderValue(#[from] tokio_tungstenite::tungstenite::http::header::InvalidHeaderValue),
-  #[error(transparent)]
-  InvalidHeaderName(#[from] tokio_tungstenite::tungstenite::http::header::InvalidHeaderName),
+pub enum Error {
+    #[error(transparent)]
+    Websocket(#[from] tokio_tungstenite::tungstenite::Error),
+    #[error("connection not found for the given id: {0}")]
+    ConnectionNotFound(Id),
+    #[error(transparent)]
+    InvalidHeaderValue(#[from] tokio_tungstenite::tungstenite::http::header::InvalidHeaderValue),
+    #[error(transparent)]
+    InvalidHeaderName(#[from] tokio_tungstenite::tungstenite::http::header::InvalidHeaderName),
+    #[error("connection {0} is not open")]
+    ConnectionNotOpen(Id),
+    #[error("no response to the heartbeat ping")]
+    HeartbeatTimeout,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    #[error(transparent)]
+    Socks(#[from] tokio_socks::Error),
+    #[error("proxy error: {0}")]
+    Proxy(String),
+    #[error("url not allowed on the configured scope: {0}")]
+    UrlNotAllowed(Url),
+    #[error("server not found for the given id: {0}")]
+    ServerNotFound(Id),
+    #[error("a token or allowed origins are required to authenticate the server peers")]
+    ServerUnauthenticated,
+    #[error("error and state messages can't be sent")]
+    UnsupportedMessage,
 }
 
 impl Serialize for Error {
-  fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
-      S: Serializer,
-  {
-    serializer.serialize_str(self.to_string().as_str())
-  }
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_str())
+    }
 }
 
-#[derive(Default)]
-struct ConnectionManager(Mutex<HashMap<Id, WebSocketWriter>>);
-
 #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
 struct TlsConnector(Mutex<Option<Connector>>);
 
+/// Scope entries configured on the plugin builder, applied to every webview.
+#[derive(Default)]
+struct DefaultScope {
+    allow: Vec<Arc<Entry>>,
+    deny: Vec<Arc<Entry>>,
+}
+
 #[derive(Deserialize)]
 #[serde(untagged, rename_all = "camelCase")]
-#[derive(Clone)]
-enum Max {
-  None,
-  Number(usize),
+#[derive(Debug, Clone)]
+pub enum Max {
+    None,
+    Number(usize),
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[derive(Clone)]
-pub(crate) struct ConnectionConfig {
-  pub read_buffer_size: Option<usize>,
-  pub write_buffer_size: Option<usize>,
-  pub max_write_buffer_size: Option<usize>,
-  pub max_message_size: Option<Max>,
-  pub max_frame_size: Option<Max>,
-  #[serde(default)]
-  pub accept_unmasked_frames: bool,
-  pub headers: Option<Vec<(String, String)>>,
-  pub agent_id: Option<String>,
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionConfig {
+    pub read_buffer_size: Option<usize>,
+    pub write_buffer_size: Option<usize>,
+    pub max_write_buffer_size: Option<usize>,
+    pub max_message_size: Option<Max>,
+    pub max_frame_size: Option<Max>,
+    #[serde(default)]
+    pub accept_unmasked_frames: bool,
+    pub headers: Option<Vec<(String, String)>>,
+    /// Subprotocols offered in the `Sec-WebSocket-Protocol` header, in order of preference.
+    pub protocols: Option<Vec<String>>,
+    /// Offers the `permessage-deflate` extension, compressing the messages if the server accepts it.
+    #[serde(default)]
+    pub compression: bool,
+    pub proxy: Option<UrlOrConfig>,
+    /// Re-establish the connection with the same request when it drops.
+    pub reconnect: Option<ReconnectConfig>,
+    pub heartbeat: Option<HeartbeatConfig>,
 }
 
 impl From<ConnectionConfig> for WebSocketConfig {
-  fn from(config: ConnectionConfig) -> Self {
-    let mut builder =
-      WebSocketConfig::default().accept_unmasked_frames(config.accept_unmasked_frames);
+    fn from(config: ConnectionConfig) -> Self {
+        let mut builder =
+            WebSocketConfig::default().accept_unmasked_frames(config.accept_unmasked_frames);
+
+        if let Some(read_buffer_size) = config.read_buffer_size {
+            builder = builder.read_buffer_size(read_buffer_size)
+        }
+
+        if let Some(write_buffer_size) = config.write_buffer_size {
+            builder = builder.write_buffer_size(write_buffer_size)
+        }
 
-    if let Some(read_buffer_size) = config.read_buffer_size {
-      builder = builder.read_buffer_size(read_buffer_size)
+        if let Some(max_write_buffer_size) = config.max_write_buffer_size {
+            builder = builder.max_write_buffer_size(max_write_buffer_size)
+        }
+
+        if let Some(max_message_size) = config.max_message_size {
+            let max_size = match max_message_size {
+                Max::None => Option::None,
+                Max::Number(n) => Some(n),
+            };
+            builder = builder.max_message_size(max_size);
+        }
+
+        if let Some(max_frame_size) = config.max_frame_size {
+            let max_size = match max_frame_size {
+                Max::None => Option::None,
+                Max::Number(n) => Some(n),
+            };
+            builder = builder.max_frame_size(max_size);
+        }
+
+        builder
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum WebSocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<CloseFrame>),
+    /// The connection failed, only sent to the `onMessage` channel.
+    #[serde(skip_deserializing)]
+    Error(String),
+    /// The connection state changed, only sent to the `onMessage` channel.
+    #[serde(skip_deserializing)]
+    State(ConnectionState),
+}
 
-    if let Some(write_buffer_size) = config.write_buffer_size {
-      builder = builder.write_buffer_size(write_buffer_size)
+impl TryFrom<WebSocketMessage> for Message {
+    type Error = Error;
+
+    fn try_from(message: WebSocketMessage) -> Result<Self> {
+        Ok(match message {
+            WebSocketMessage::Text(t) => Message::Text(t.into()),
+            WebSocketMessage::Binary(t) => Message::Binary(t.into()),
+            WebSocketMessage::Ping(t) => Message::Ping(t.into()),
+            WebSocketMessage::Pong(t) => Message::Pong(t.into()),
+            WebSocketMessage::Close(t) => Message::Close(t.map(|v| ProtocolCloseFrame {
+                code: v.code.into(),
+                reason: v.reason.into(),
+            })),
+            WebSocketMessage::Error(_) | WebSocketMessage::State(_) => {
+                return Err(Error::UnsupportedMessage)
+            }
+        })
     }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    pub id: Id,
+    /// The subprotocol selected by the server.
+    pub protocol: Option<String>,
+}
 
-    if let Some(max_write_buffer_size) = config.max_write_buffer_size {
-      builder = builder.max_write_buffer_size(max_write_buffer_size)
+#[allow(clippy::result_large_err)]
+fn build_request(url: String, config: Option<&ConnectionConfig>) -> Result<Request> {
+    let mut request = url.into_client_request()?;
+
+    if let Some(headers) = config.and_then(|c| c.headers.as_ref()) {
+        for (k, v) in headers {
+            let header_name = HeaderName::from_str(k.as_str())?;
+            let header_value = HeaderValue::from_str(v.as_str())?;
+            request.headers_mut().insert(header_name, header_value);
+        }
     }
 
-    if let Some(max_message_size) = config.max_message_size {
-      let max_size = match max_message_size {
-        Max::None => Option::None,
-        Max::Number(n) => Some(n),
-      };
-      builder = builder.max_message_size(max_size);
+    if let Some(protocols) = config
+        .and_then(|c| c.protocols.as_ref())
+        .filter(|p| !p.is_empty())
+    {
+        request.headers_mut().insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_str(&protocols.join(", "))?,
+        );
     }
 
-    if let Some(max_frame_size) = config.max_frame_size {
-      let max_size = match max_frame_size {
-        Max::None => Option::None,
-        Max::Number(n) => Some(n),
-      };
-      builder = builder.max_frame_size(max_size);
+    if config.is_some_and(|c| c.compression) {
+        request.headers_mut().insert(
+            SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static(deflate::OFFER),
+        );
     }
 
-    builder
-  }
+    Ok(request)
+}
+
+#[allow(clippy::result_large_err)]
+fn check_scope(
+    command_scope: &CommandScope<Entry>,
+    global_scope: &GlobalScope<Entry>,
+    default_scope: &DefaultScope,
+    url: &str,
+) -> Result<()> {
+    let url = Url::parse(url)?;
+    let scope = Scope::new(
+        command_scope
+            .allows()
+            .iter()
+            .chain(global_scope.allows())
+            .chain(&default_scope.allow)
+            .collect(),
+        command_scope
+            .denies()
+            .iter()
+            .chain(global_scope.denies())
+            .chain(&default_scope.deny)
+            .collect(),
+    );
+    if scope.is_allowed(&url) {
+        Ok(())
+    } else {
+        Err(Error::UrlNotAllowed(url))
+    }
 }
 
-#[derive(Deserialize, Serialize)]
-struct CloseFrame {
-  pub code: u16,
-  pub reason: String,
+#[tauri::command]
+async fn connect<R: Runtime>(
+    app: AppHandle<R>,
+    webview: Webview<R>,
+    url: String,
+    on_message: Channel<WebSocketMessage>,
+    config: Option<ConnectionConfig>,
+    command_scope: CommandScope<Entry>,
+    global_scope: GlobalScope<Entry>,
+) -> Result<ConnectionInfo> {
+    check_scope(
+        &command_scope,
+        &global_scope,
+        &app.state::<DefaultScope>(),
+        &url,
+    )?;
+
+    app.websocket()
+        .open(url, config, owner(&webview), move |message| {
+            let _ = on_message.send(message);
+        })
+        .await
 }
 
-#[derive(Deserialize, Serialize)]
-#[serde(tag = "type", content = "data")]
-enum WebSocketMessage {
-  Text(String),
-  Binary(Vec<u8>),
-  Ping(Vec<u8>),
-  Pong(Vec<u8>),
-  Close(Option<CloseFrame>),
+/// The webviews can only use the connections and servers they opened.
+fn owner<R: Runtime>(webview: &Webview<R>) -> Owner {
+    Owner::Webview(webview.label().to_string())
 }
-use std::sync::Arc;
 
 #[tauri::command]
-async fn connect<R: Runtime>(
-  app: &AppHandle<R>,
-  window: Window<R>,
-  url: String,
-  on_message: Channel<serde_json::Value>,
-  config: Option<ConnectionConfig>,
-) -> Result<Id> {
-  println!("connect: {:?}", config);
-  let id = rand::random();
-  let mut request = url.into_client_request()?;
-
-  if let Some(headers) = config.as_ref().and_then(|c| c.headers.as_ref()) {
-    for (k, v) in headers {
-      let header_name = HeaderName::from_str(k.as_str())?;
-      let header_value = HeaderValue::from_str(v.as_str())?;
-      request.headers_mut().insert(header_name, header_value);
+async fn send<R: Runtime>(
+    webview: Webview<R>,
+    manager: State<'_, ConnectionManager>,
+    id: Id,
+    message: WebSocketMessage,
+) -> Result<()> {
+    manager
+        .send_as(&owner(&webview), id, message.try_into()?)
+        .await
+}
+
+#[tauri::command]
+async fn listen<R: Runtime>(
+    webview: Webview<R>,
+    servers: State<'_, ServerManager>,
+    connections: State<'_, ConnectionManager>,
+    config: ServerConfig,
+    on_event: Channel<ServerEvent>,
+) -> Result<ServerInfo> {
+    servers
+        .listen(
+            owner(&webview),
+            connections.inner().clone(),
+            config,
+            move |event| {
+                let _ = on_event.send(event);
+            },
+        )
+        .await
+}
+
+#[tauri::command]
+async fn close_server<R: Runtime>(
+    webview: Webview<R>,
+    servers: State<'_, ServerManager>,
+    connections: State<'_, ConnectionManager>,
+    id: Id,
+) -> Result<()> {
+    servers.close(id, &owner(&webview), &connections).await
+}
+
+/// Access to the WebSocket client from Rust.
+///
+/// Rust can use every connection, including the ones opened by the webviews and accepted
+/// by their servers. A webview can only use the connections it opened itself.
+pub struct WebSocket<R: Runtime>(AppHandle<R>);
+
+impl<R: Runtime> WebSocket<R> {
+    /// Connects to `url`, returning the connection and a stream of its messages and state changes.
+    ///
+    /// The URL scope only applies to the webviews, so any URL can be connected to.
+    pub async fn connect(
+        &self,
+        url: impl Into<String>,
+        config: Option<ConnectionConfig>,
+    ) -> Result<(ConnectionInfo, MessageStream)> {
+        let (tx, messages) = MessageStream::channel();
+        let info = self
+            .open(url.into(), config, Owner::Rust, move |message| {
+                let _ = tx.send(message);
+            })
+            .await?;
+        Ok((info, messages))
+    }
+
+    /// Sends a message on the given connection.
+    pub async fn send(&self, id: Id, message: WebSocketMessage) -> Result<()> {
+        self.0
+            .state::<ConnectionManager>()
+            .send(id, message.try_into()?)
+            .await
+    }
+
+    /// Closes the given connection. It is not re-established afterwards.
+    pub async fn close(&self, id: Id, frame: Option<CloseFrame>) -> Result<()> {
+        self.send(id, WebSocketMessage::Close(frame)).await
+    }
+
+    /// Observes the messages and state changes of a connection, including connections opened by the webviews.
+    pub async fn messages(&self, id: Id) -> Result<MessageStream> {
+        self.0.state::<ConnectionManager>().subscribe(id).await
     }
-  }
-
-  #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
-    let tls_connector = match window.try_state::<TlsConnector>() {
-    Some(tls_connector) => tls_connector.0.lock().await.clone(),
-    None => None,
-  };
-
-  #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
-    let (ws_stream, _) =
-    connect_async_tls_with_config(request, config.clone().map(Into::into), false, tls_connector)
-      .await?;
-  #[cfg(not(any(feature = "rustls-tls", feature = "native-tls")))]
-    let (ws_stream, _) = connect_async_with_config(request, config.clone().map(Into::into), false).await?;
-
-  tauri::async_runtime::spawn(async move {
-    // 在建立 WebSocket 连接时，通常会将连接分为两个部分
-    // 一个用于发送消息（写入部分）
-    // 另一个用于接收消息（读取部分）。
-    // 这可以通过调用 split() 方法来实现
-    let (write, read) = ws_stream.split();
-    // 将 write 加入到 ConnectionManager 中
-    let manager = window.state::<ConnectionManager>();
-    manager.0.lock().await.insert(id, write);
-
-    // 将 window 和 config 转换为 Arc<Mutex<T>> 类型, 以便在异步闭包中使用
-    let window_arc = Arc::new(Mutex::new(window));
-    let config_arc = Arc::new(Mutex::new(config));
-
-    // read 是一个异步流，使用 for_each 处理方法来处理接收到的消息
-    read.for_each(move |message| {
-      let window_ = Arc::clone(&window_arc); // 克隆 Arc
-      let config_ = Arc::clone(&config_arc); // 克隆 Arc
-      let app_ = app.clone();
-      let on_message_ = on_message.clone();
-      async move {
-        if let Ok(Message::Close(_)) = message {
-          let manager_lock = window_.lock().await; // 将结果存储在变量中
-          let manager = manager_lock.state::<ConnectionManager>();
-          manager.0.lock().await.remove(&id);
-        }
 
-        let response = match message {
-          Ok(Message::Text(t)) => {
-            serde_json::to_value(WebSocketMessage::Text(t.to_string())).unwrap()
-          }
-          Ok(Message::Binary(t)) => {
-            serde_json::to_value(WebSocketMessage::Binary(t.to_vec())).unwrap()
-          }
-          Ok(Message::Ping(t)) => {
-            serde_json::to_value(WebSocketMessage::Ping(t.to_vec())).unwrap()
-          }
-          Ok(Message::Pong(t)) => {
-            serde_json::to_value(WebSocketMessage::Pong(t.to_vec())).unwrap()
-          }
-          Ok(Message::Close(t)) => {
-            serde_json::to_value(WebSocketMessage::Close(t.map(|v| CloseFrame {
-              code: v.code.into(),
-              reason: v.reason.to_string(),
-            })))
-              .unwrap()
-          }
-          Ok(Message::Frame(_)) => serde_json::Value::Null, // This value can't be recieved.
-          Err(e) => serde_json::to_value(Error::from(e)).unwrap(),
+    async fn open(
+        &self,
+        url: String,
+        config: Option<ConnectionConfig>,
+        owner: Owner,
+        on_message: impl Fn(WebSocketMessage) + Send + Sync + 'static,
+    ) -> Result<ConnectionInfo> {
+        let id = rand::random();
+        let request = build_request(url, config.as_ref())?;
+
+        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+        let tls_connector = match self.0.try_state::<TlsConnector>() {
+            Some(tls_connector) => tls_connector.0.lock().await.clone(),
+            None => None,
         };
-        if let Some(agent_id) = config_.lock().await.as_ref().and_then(|c| c.agent_id.as_ref()) {
-          println!("send to agent: {}", agent_id);
-          app_.get_webview_window("main").unwrap().eval(format!("window.frames['{}']?._ws_onmessage({}, {})", &agent_id, &response, id).as_str());
-        }
-      }
-    })
-      .await;
-  });
+        #[cfg(not(any(feature = "rustls-tls", feature = "native-tls")))]
+        let tls_connector = None;
+
+        on_message(WebSocketMessage::State(ConnectionState::Connecting));
+        let (ws_stream, protocol) =
+            connection::open(request.clone(), config.clone(), tls_connector.clone()).await?;
+        // register the connection before returning its id so it can be used right away
+        let read = self
+            .0
+            .state::<ConnectionManager>()
+            .insert(id, owner, ws_stream)
+            .await;
+
+        let app = self.0.clone();
+        tauri::async_runtime::spawn(async move {
+            app.state::<ConnectionManager>()
+                .run(id, request, config, tls_connector, read, on_message)
+                .await;
+        });
+
+        Ok(ConnectionInfo { id, protocol })
+    }
+}
 
-  Ok(id)
+/// Extensions to [`tauri::App`], [`tauri::AppHandle`], [`tauri::WebviewWindow`], [`tauri::Webview`] and [`tauri::Window`] to access the WebSocket APIs.
+pub trait WebSocketExt<R: Runtime> {
+    fn websocket(&self) -> &WebSocket<R>;
 }
 
-#[tauri::command]
-async fn send(
-  manager: State<'_, ConnectionManager>,
-  id: Id,
-  message: WebSocketMessage,
-) -> Result<()> {
-  if let Some(write) = manager.0.lock().await.get_mut(&id) {
-    write
-      .send(match message {
-        WebSocketMessage::Text(t) => Message::Text(t.into()),
-        WebSocketMessage::Binary(t) => Message::Binary(t.into()),
-        WebSocketMessage::Ping(t) => Message::Ping(t.into()),
-        WebSocketMessage::Pong(t) => Message::Pong(t.into()),
-        WebSocketMessage::Close(t) => Message::Close(t.map(|v| ProtocolCloseFrame {
-          code: v.code.into(),
-          reason: v.reason.into(),
-        })),
-      })
-      .await?;
-    Ok(())
-  } else {
-    Err(Error::ConnectionNotFound(id))
-  }
+impl<R: Runtime, T: Manager<R>> WebSocketExt<R> for T {
+    fn websocket(&self) -> &WebSocket<R> {
+        self.state::<WebSocket<R>>().inner()
+    }
 }
 
 pub fn init<R: Runtime>() -> TauriPlugin<R> {
-  Builder::default().build()
+    Builder::default().build()
 }
 
 #[derive(Default)]
 pub struct Builder {
-  tls_connector: Option<Connector>,
+    tls_connector: Option<Connector>,
+    allow: Vec<String>,
+    deny: Vec<String>,
 }
 
 impl Builder {
-  pub fn new() -> Self {
-    Self {
-      tls_connector: None,
+    pub fn new() -> Self {
+        Self {
+            tls_connector: None,
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+
+    pub fn tls_connector(mut self, connector: Connector) -> Self {
+        self.tls_connector.replace(connector);
+        self
+    }
+
+    /// Allows every webview to connect to URLs matching the given
+    /// [URL pattern](https://urlpattern.spec.whatwg.org/), in addition to the URLs allowed by its capabilities.
+    pub fn allow_url(mut self, pattern: impl Into<String>) -> Self {
+        self.allow.push(pattern.into());
+        self
+    }
+
+    /// Denies connecting to URLs matching the given [URL pattern](https://urlpattern.spec.whatwg.org/)
+    /// from every webview, even if they are allowed by its capabilities.
+    pub fn deny_url(mut self, pattern: impl Into<String>) -> Self {
+        self.deny.push(pattern.into());
+        self
+    }
+
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        PluginBuilder::new("websocket")
+            .invoke_handler(tauri::generate_handler![
+                connect,
+                send,
+                listen,
+                close_server
+            ])
+            .setup(move |app, _api| {
+                let parse = |patterns: Vec<String>| {
+                    patterns
+                        .into_iter()
+                        .map(|pattern| {
+                            Entry::from_str(&pattern)
+                                .map(Arc::new)
+                                .map_err(|e| format!("`{pattern}` is not a valid URL pattern: {e}"))
+                        })
+                        .collect::<std::result::Result<Vec<_>, _>>()
+                };
+                app.manage(DefaultScope {
+                    allow: parse(self.allow)?,
+                    deny: parse(self.deny)?,
+                });
+                app.manage(ConnectionManager::default());
+                app.manage(ServerManager::default());
+                app.manage(WebSocket(app.clone()));
+                #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+                app.manage(TlsConnector(Mutex::new(self.tls_connector)));
+                Ok(())
+            })
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_frames_are_sent() {
+        assert!(
+            serde_json::from_str::<WebSocketMessage>(r#"{ "type": "Error", "data": "" }"#).is_err()
+        );
+        assert!(Message::try_from(WebSocketMessage::Error(String::new())).is_err());
+        assert!(Message::try_from(WebSocketMessage::State(ConnectionState::Open)).is_err());
     }
-  }
-
-  pub fn tls_connector(mut self, connector: Connector) -> Self {
-    self.tls_connector.replace(connector);
-    self
-  }
-
-  pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
-    PluginBuilder::new("websocket")
-      .invoke_handler(tauri::generate_handler![connect, send])
-      .setup(move |app, _api| {
-        app.manage(ConnectionManager::default());
-        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
-        app.manage(TlsConnector(Mutex::new(self.tls_connector)));
-        Ok(())
-      })
-      .build()
-  }
 }
@@ -0,0 +1,231 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Tunnels connections through HTTP (`CONNECT`) and SOCKS5 proxies.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_socks::tcp::Socks5Stream;
+use url::Url;
+
+use crate::{Error, Result};
+
+/// Maximum size of the response to a `CONNECT` request.
+const MAX_CONNECT_RESPONSE_SIZE: usize = 8 * 1024;
+
+/// A proxy URL or its full configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum UrlOrConfig {
+    Url(String),
+    Config(ProxyConfig),
+}
+
+impl From<UrlOrConfig> for ProxyConfig {
+    fn from(proxy: UrlOrConfig) -> Self {
+        match proxy {
+            UrlOrConfig::Url(url) => Self {
+                url,
+                basic_auth: None,
+                no_proxy: None,
+            },
+            UrlOrConfig::Config(config) => config,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyConfig {
+    /// `http://`, `socks5://` or `socks5h://` URL of the proxy server.
+    /// Credentials can be included in the URL.
+    pub url: String,
+    pub basic_auth: Option<BasicAuth>,
+    /// Comma-separated list of hosts that are connected to directly.
+    /// `example.com` also matches its subdomains and `*` matches every host.
+    pub no_proxy: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: String,
+}
+
+impl ProxyConfig {
+    /// Whether connections to `host` should skip the proxy.
+    pub(crate) fn bypasses(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.no_proxy
+            .iter()
+            .flat_map(|no_proxy| no_proxy.split(','))
+            .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|entry| !entry.is_empty())
+            .any(|entry| {
+                entry == "*"
+                    || host == entry
+                    || host
+                        .strip_suffix(&entry)
+                        .is_some_and(|subdomain| subdomain.ends_with('.'))
+            })
+    }
+
+    fn credentials(&self, url: &Url) -> Option<(String, String)> {
+        match &self.basic_auth {
+            Some(auth) => Some((auth.username.clone(), auth.password.clone())),
+            None if !url.username().is_empty() => Some((
+                url.username().to_string(),
+                url.password().unwrap_or_default().to_string(),
+            )),
+            None => None,
+        }
+    }
+
+    /// Opens a tunnel to `host:port` through the proxy.
+    pub(crate) async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let url = Url::parse(&self.url)?;
+        let proxy_host = url
+            .host_str()
+            .ok_or_else(|| Error::Proxy(format!("missing host in {}", self.url)))?;
+        let credentials = self.credentials(&url);
+
+        match url.scheme() {
+            "http" => {
+                let proxy_port = url.port().unwrap_or(80);
+                let stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+                http_connect(stream, host, port, credentials).await
+            }
+            "socks5" | "socks5h" => {
+                let proxy_port = url.port().unwrap_or(1080);
+                let proxy = (proxy_host, proxy_port);
+                let stream = match &credentials {
+                    Some((username, password)) => {
+                        Socks5Stream::connect_with_password(proxy, (host, port), username, password)
+                            .await?
+                    }
+                    None => Socks5Stream::connect(proxy, (host, port)).await?,
+                };
+                Ok(stream.into_inner())
+            }
+            scheme => Err(Error::Proxy(format!("unsupported proxy scheme {scheme}"))),
+        }
+    }
+}
+
+async fn http_connect(
+    mut stream: TcpStream,
+    host: &str,
+    port: u16,
+    credentials: Option<(String, String)>,
+) -> Result<TcpStream> {
+    let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if let Some((username, password)) = credentials {
+        let token = STANDARD.encode(format!("{username}:{password}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {token}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // read byte by byte so nothing after the response head is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE_SIZE {
+            return Err(Error::Proxy("response to CONNECT is too large".into()));
+        }
+        let byte = stream.read_u8().await?;
+        response.push(byte);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(stream),
+        _ => Err(Error::Proxy(format!(
+            "CONNECT to {host}:{port} failed: {status_line}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        net::TcpListener,
+    };
+
+    fn config(value: serde_json::Value) -> ProxyConfig {
+        serde_json::from_value::<UrlOrConfig>(value).unwrap().into()
+    }
+
+    #[test]
+    fn no_proxy_matches_hosts_and_subdomains() {
+        let proxy = config(serde_json::json!({
+            "url": "http://proxy:3128",
+            "noProxy": "localhost, .internal.example.com"
+        }));
+        assert!(proxy.bypasses("localhost"));
+        assert!(proxy.bypasses("internal.example.com"));
+        assert!(proxy.bypasses("api.internal.example.com"));
+        assert!(!proxy.bypasses("example.com"));
+        assert!(!proxy.bypasses("notinternal.example.com"));
+        assert!(!config(serde_json::json!("http://proxy:3128")).bypasses("localhost"));
+    }
+
+    #[tokio::test]
+    async fn tunnels_through_http_proxy() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_port = target.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.unwrap();
+            stream.write_all(b"hello").await.unwrap();
+        });
+
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_url = format!("http://user:secret@{}", proxy.local_addr().unwrap());
+        let proxy_task = tokio::spawn(async move {
+            let (stream, _) = proxy.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut head = Vec::new();
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push(line.trim_end().to_string());
+            }
+            let mut upstream = TcpStream::connect(("127.0.0.1", target_port))
+                .await
+                .unwrap();
+            let mut stream = stream.into_inner();
+            stream
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await
+                .unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+            head
+        });
+
+        let mut stream = config(serde_json::json!(proxy_url))
+            .connect("127.0.0.1", target_port)
+            .await
+            .unwrap();
+        let mut greeting = [0; 5];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(&greeting, b"hello");
+        drop(stream);
+
+        let head = proxy_task.await.unwrap();
+        assert_eq!(head[0], format!("CONNECT 127.0.0.1:{target_port} HTTP/1.1"));
+        assert!(head.contains(&format!(
+            "Proxy-Authorization: Basic {}",
+            STANDARD.encode("user:secret")
+        )));
+    }
+}
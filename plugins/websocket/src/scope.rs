@@ -0,0 +1,133 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{str::FromStr, sync::Arc};
+
+use serde::{Deserialize, Deserializer};
+use url::Url;
+use urlpattern::{UrlPattern, UrlPatternMatchInput};
+
+#[allow(rustdoc::bare_urls)]
+#[derive(Debug)]
+pub struct Entry {
+    pub url: UrlPattern,
+}
+
+fn parse_url_pattern(s: &str) -> Result<UrlPattern, urlpattern::quirks::Error> {
+    let mut init = urlpattern::UrlPatternInit::parse_constructor_string::<regex::Regex>(s, None)?;
+    if init.search.as_ref().map(|p| p.is_empty()).unwrap_or(true) {
+        init.search.replace("*".to_string());
+    }
+    if init.hash.as_ref().map(|p| p.is_empty()).unwrap_or(true) {
+        init.hash.replace("*".to_string());
+    }
+    if init
+        .pathname
+        .as_ref()
+        .map(|p| p.is_empty() || p == "/")
+        .unwrap_or(true)
+    {
+        init.pathname.replace("*".to_string());
+    }
+    UrlPattern::parse(init, Default::default())
+}
+
+impl FromStr for Entry {
+    type Err = urlpattern::quirks::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = parse_url_pattern(s)?;
+        Ok(Self { url: pattern })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum EntryRaw {
+    Value(String),
+    Object { url: String },
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        EntryRaw::deserialize(deserializer).and_then(|raw| {
+            let url = match raw {
+                EntryRaw::Value(url) => url,
+                EntryRaw::Object { url } => url,
+            };
+            Ok(Entry {
+                url: parse_url_pattern(&url).map_err(|e| {
+                    serde::de::Error::custom(format!("`{}` is not a valid URL pattern: {e}", url))
+                })?,
+            })
+        })
+    }
+}
+
+/// Scope for the URLs WebSocket connections can be opened to.
+#[derive(Debug)]
+pub struct Scope<'a> {
+    allowed: Vec<&'a Arc<Entry>>,
+    denied: Vec<&'a Arc<Entry>>,
+}
+
+impl<'a> Scope<'a> {
+    /// Creates a new scope from the scope configuration.
+    pub(crate) fn new(allowed: Vec<&'a Arc<Entry>>, denied: Vec<&'a Arc<Entry>>) -> Self {
+        Self { allowed, denied }
+    }
+
+    /// Determines if the given URL is allowed on this scope.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let denied = self.denied.iter().any(|entry| {
+            entry
+                .url
+                .test(UrlPatternMatchInput::Url(url.clone()))
+                .unwrap_or_default()
+        });
+        if denied {
+            false
+        } else {
+            self.allowed.iter().any(|entry| {
+                entry
+                    .url
+                    .test(UrlPatternMatchInput::Url(url.clone()))
+                    .unwrap_or_default()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    #[test]
+    fn denied_takes_precedence() {
+        let allow = Arc::new("wss://*.example.com".parse().unwrap());
+        let deny = Arc::new("wss://admin.example.com".parse().unwrap());
+        let scope = super::Scope::new(vec![&allow], vec![&deny]);
+        assert!(scope.is_allowed(&"wss://chat.example.com/socket?room=1".parse().unwrap()));
+        assert!(!scope.is_allowed(&"wss://admin.example.com/socket".parse().unwrap()));
+        assert!(!scope.is_allowed(&"ws://chat.example.com/socket".parse().unwrap()));
+    }
+
+    #[test]
+    fn port_wildcard() {
+        let allow = Arc::new("ws://localhost:*".parse().unwrap());
+        let scope = super::Scope::new(vec![&allow], Vec::new());
+        assert!(scope.is_allowed(&"ws://localhost:9001/events".parse().unwrap()));
+        assert!(scope.is_allowed(&"ws://localhost".parse().unwrap()));
+        assert!(!scope.is_allowed(&"ws://localhost.example.com:9001".parse().unwrap()));
+    }
+
+    #[test]
+    fn empty_scope_denies_everything() {
+        let scope = super::Scope::new(Vec::new(), Vec::new());
+        assert!(!scope.is_allowed(&"wss://example.com".parse().unwrap()));
+    }
+}
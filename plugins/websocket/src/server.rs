@@ -0,0 +1,469 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Local servers accepting connections from other processes, e.g. a CLI or a browser extension.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex as StdMutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpListener, net::TcpStream, sync::Mutex, task::JoinHandle};
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{
+            header::{AUTHORIZATION, ORIGIN},
+            StatusCode,
+        },
+        Message,
+    },
+    MaybeTlsStream,
+};
+
+use crate::{
+    connection::{ConnectionManager, Owner},
+    deflate::DeflateStream,
+    Error, Id, Result, WebSocketMessage,
+};
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerConfig {
+    /// Address to bind to. Defaults to `127.0.0.1`.
+    pub host: Option<String>,
+    /// Port to bind to. Defaults to a random free port.
+    pub port: Option<u16>,
+    /// Secret peers must send as a bearer `Authorization` header or in the `token` query parameter.
+    pub token: Option<String>,
+    /// `Origin` headers peers are accepted from, e.g. `chrome-extension://<extension id>`.
+    pub allowed_origins: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerInfo {
+    pub id: Id,
+    /// The port the server is bound to.
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum ServerEvent {
+    /// A peer connected. Its messages are sent as [`ServerEvent::Message`] with the same id,
+    /// which is used to send messages to it too.
+    Connection {
+        id: Id,
+        address: String,
+        origin: Option<String>,
+    },
+    Message {
+        id: Id,
+        message: WebSocketMessage,
+    },
+}
+
+/// Ids of the connections accepted by a server, `None` once it is closed.
+type Accepted = Arc<StdMutex<Option<HashSet<Id>>>>;
+
+struct Server {
+    /// The webview that started the server, which also owns its connections.
+    owner: Owner,
+    listener: JoinHandle<()>,
+    accepted: Accepted,
+}
+
+#[derive(Default)]
+pub(crate) struct ServerManager(Mutex<HashMap<Id, Server>>);
+
+impl ServerManager {
+    /// Binds a server and forwards its connections and their messages to `on_event`.
+    pub async fn listen(
+        &self,
+        owner: Owner,
+        connections: ConnectionManager,
+        mut config: ServerConfig,
+        on_event: impl Fn(ServerEvent) + Send + Sync + 'static,
+    ) -> Result<ServerInfo> {
+        // empty values would accept any peer or none
+        config.token = config.token.filter(|token| !token.is_empty());
+        config.allowed_origins = config
+            .allowed_origins
+            .filter(|allowed_origins| !allowed_origins.is_empty());
+        if config.token.is_none() && config.allowed_origins.is_none() {
+            return Err(Error::ServerUnauthenticated);
+        }
+
+        let host = config.host.as_deref().unwrap_or(DEFAULT_HOST);
+        let listener = TcpListener::bind((host, config.port.unwrap_or(0))).await?;
+        let port = listener.local_addr()?.port();
+
+        let id = rand::random();
+        let accepted = Accepted::new(StdMutex::new(Some(HashSet::new())));
+        let listener = tokio::spawn(accept(
+            listener,
+            Arc::new(config),
+            owner.clone(),
+            connections,
+            accepted.clone(),
+            Arc::new(on_event),
+        ));
+        self.0.lock().await.insert(
+            id,
+            Server {
+                owner,
+                listener,
+                accepted,
+            },
+        );
+
+        Ok(ServerInfo { id, port })
+    }
+
+    /// Stops accepting connections and closes the open ones, if the server belongs to `owner`.
+    pub async fn close(
+        &self,
+        id: Id,
+        owner: &Owner,
+        connections: &ConnectionManager,
+    ) -> Result<()> {
+        let server = {
+            let mut servers = self.0.lock().await;
+            match servers.get(&id) {
+                Some(server) if server.owner == *owner => servers.remove(&id).unwrap(),
+                _ => return Err(Error::ServerNotFound(id)),
+            }
+        };
+        server.listener.abort();
+        // wait for the listener to be dropped so the port is released
+        let _ = server.listener.await;
+
+        let accepted = server.accepted.lock().unwrap().take().unwrap_or_default();
+        for id in accepted {
+            let _ = connections.send(id, Message::Close(None)).await;
+        }
+        Ok(())
+    }
+}
+
+async fn accept(
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+    owner: Owner,
+    connections: ConnectionManager,
+    accepted: Accepted,
+    on_event: Arc<dyn Fn(ServerEvent) + Send + Sync>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, address)) => {
+                tokio::spawn(handle(
+                    stream,
+                    address,
+                    config.clone(),
+                    owner.clone(),
+                    connections.clone(),
+                    accepted.clone(),
+                    on_event.clone(),
+                ));
+            }
+            Err(e) => log::warn!("failed to accept websocket connection: {e}"),
+        }
+    }
+}
+
+async fn handle(
+    stream: TcpStream,
+    address: SocketAddr,
+    config: Arc<ServerConfig>,
+    owner: Owner,
+    connections: ConnectionManager,
+    accepted: Accepted,
+    on_event: Arc<dyn Fn(ServerEvent) + Send + Sync>,
+) {
+    let mut origin = None;
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| {
+        authorize(&config, request)?;
+        origin = request
+            .headers()
+            .get(ORIGIN)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string);
+        Ok(response)
+    };
+    let ws_stream = match accept_hdr_async(
+        DeflateStream::new(MaybeTlsStream::Plain(stream), false),
+        callback,
+    )
+    .await
+    {
+        Ok(ws_stream) => ws_stream,
+        Err(e) => {
+            log::warn!("rejected websocket connection from {address}: {e}");
+            return;
+        }
+    };
+
+    let id = rand::random();
+    match accepted.lock().unwrap().as_mut() {
+        Some(accepted) => accepted.insert(id),
+        // the server was closed during the handshake
+        None => return,
+    };
+
+    let read = connections.insert(id, owner, ws_stream).await;
+    on_event(ServerEvent::Connection {
+        id,
+        address: address.to_string(),
+        origin,
+    });
+    connections
+        .serve(id, read, |message| {
+            on_event(ServerEvent::Message { id, message })
+        })
+        .await;
+
+    if let Some(accepted) = accepted.lock().unwrap().as_mut() {
+        accepted.remove(&id);
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn authorize(config: &ServerConfig, request: &Request) -> std::result::Result<(), ErrorResponse> {
+    if let Some(allowed_origins) = &config.allowed_origins {
+        let origin = request.headers().get(ORIGIN).and_then(|v| v.to_str().ok());
+        if !origin.is_some_and(|origin| allowed_origins.iter().any(|allowed| allowed == origin)) {
+            return Err(reject(StatusCode::FORBIDDEN));
+        }
+    }
+
+    if let Some(token) = &config.token {
+        let bearer = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(ToString::to_string);
+        // browsers can't set headers on WebSocket connections
+        let query = request.uri().query().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "token")
+                .map(|(_, value)| value.into_owned())
+        });
+        if !bearer
+            .or(query)
+            .is_some_and(|provided| tokens_match(provided.as_bytes(), token.as_bytes()))
+        {
+            return Err(reject(StatusCode::UNAUTHORIZED));
+        }
+    }
+
+    Ok(())
+}
+
+fn reject(status: StatusCode) -> ErrorResponse {
+    let mut response = ErrorResponse::new(None);
+    *response.status_mut() = status;
+    response
+}
+
+/// Compares in constant time so the token can't be guessed from response times.
+fn tokens_match(provided: &[u8], token: &[u8]) -> bool {
+    provided.len() == token.len()
+        && provided
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConnectionState;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error as WsError};
+
+    async fn listen(
+        config: ServerConfig,
+    ) -> (
+        &'static ServerManager,
+        ConnectionManager,
+        ServerInfo,
+        mpsc::UnboundedReceiver<ServerEvent>,
+    ) {
+        let servers: &'static ServerManager = Box::leak(Box::default());
+        let connections = ConnectionManager::default();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let info = servers
+            .listen(owner(), connections.clone(), config, move |event| {
+                let _ = tx.send(event);
+            })
+            .await
+            .unwrap();
+        (servers, connections, info, rx)
+    }
+
+    fn owner() -> Owner {
+        Owner::Webview("main".into())
+    }
+
+    fn with_token() -> ServerConfig {
+        ServerConfig {
+            token: Some("secret".into()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn requires_authentication() {
+        let servers = ServerManager::default();
+        assert!(matches!(
+            servers
+                .listen(
+                    owner(),
+                    ConnectionManager::default(),
+                    ServerConfig::default(),
+                    |_| {}
+                )
+                .await,
+            Err(Error::ServerUnauthenticated)
+        ));
+
+        let empty = ServerConfig {
+            token: Some(String::new()),
+            allowed_origins: Some(Vec::new()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            servers
+                .listen(owner(), ConnectionManager::default(), empty, |_| {})
+                .await,
+            Err(Error::ServerUnauthenticated)
+        ));
+    }
+
+    #[tokio::test]
+    async fn exchanges_messages_with_authenticated_peers() {
+        let (_servers, connections, info, mut events) = listen(with_token()).await;
+
+        let url = format!("ws://127.0.0.1:{}/?token=secret", info.port);
+        let (mut peer, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let Some(ServerEvent::Connection { id, origin, .. }) = events.recv().await else {
+            panic!("expected a connection");
+        };
+        assert_eq!(origin, None);
+        assert_eq!(
+            events.recv().await,
+            Some(ServerEvent::Message {
+                id,
+                message: WebSocketMessage::State(ConnectionState::Open)
+            })
+        );
+
+        peer.send(Message::Text("ping".into())).await.unwrap();
+        assert_eq!(
+            events.recv().await,
+            Some(ServerEvent::Message {
+                id,
+                message: WebSocketMessage::Text("ping".into())
+            })
+        );
+
+        connections
+            .send(id, Message::Text("pong".into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            peer.next().await.unwrap().unwrap(),
+            Message::Text("pong".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_unauthorized_peers() {
+        let (_servers, _connections, info, _events) = listen(ServerConfig {
+            allowed_origins: Some(vec!["chrome-extension://abc".into()]),
+            ..with_token()
+        })
+        .await;
+
+        let connect = |origin: &str, token: &str| {
+            let mut request = format!("ws://127.0.0.1:{}", info.port)
+                .into_client_request()
+                .unwrap();
+            request
+                .headers_mut()
+                .insert(ORIGIN, origin.parse().unwrap());
+            request
+                .headers_mut()
+                .insert(AUTHORIZATION, format!("Bearer {token}").parse().unwrap());
+            tokio_tungstenite::connect_async(request)
+        };
+        let status = |result: std::result::Result<_, WsError>| match result {
+            Err(WsError::Http(response)) => Some(response.status()),
+            _ => None,
+        };
+
+        assert_eq!(
+            status(connect("https://evil.example", "secret").await),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            status(connect("chrome-extension://abc", "secre").await),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert!(connect("chrome-extension://abc", "secret").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn closing_the_server_closes_its_connections() {
+        let (servers, connections, info, mut events) = listen(with_token()).await;
+
+        let mut request = format!("ws://127.0.0.1:{}", info.port)
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let (mut peer, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let Some(ServerEvent::Connection { id, .. }) = events.recv().await else {
+            panic!("expected a connection");
+        };
+
+        // only the webview that started the server can close it or use its connections
+        let other = Owner::Webview("other".into());
+        assert!(matches!(
+            servers.close(info.id, &other, &connections).await,
+            Err(Error::ServerNotFound(_))
+        ));
+        assert!(matches!(
+            connections.send_as(&other, id, Message::Close(None)).await,
+            Err(Error::ConnectionNotFound(_))
+        ));
+
+        servers
+            .close(info.id, &owner(), &connections)
+            .await
+            .unwrap();
+        assert!(matches!(peer.next().await, Some(Ok(Message::Close(_)))));
+        assert!(
+            tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{}", info.port))
+                .await
+                .is_err()
+        );
+        assert!(matches!(
+            servers.close(info.id, &owner(), &connections).await,
+            Err(Error::ServerNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! TLS for the client connections, set up here rather than by tokio-tungstenite so the
+//! [`crate::deflate::DeflateStream`] can sit between it and the WebSocket protocol.
+
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::{
+        client::uri_mode, error::UrlError, handshake::client::Request, stream::Mode,
+        Error as WsError,
+    },
+    Connector, MaybeTlsStream,
+};
+
+use crate::Result;
+#[cfg(any(
+    feature = "native-tls",
+    feature = "rustls-tls",
+    feature = "rustls-tls-native-roots"
+))]
+use tokio_tungstenite::tungstenite::error::TlsError;
+
+/// Upgrades the stream to TLS if the request is for a `wss://` URL.
+///
+/// Without a connector, native-tls is used if it is enabled, and rustls otherwise.
+pub(crate) async fn wrap(
+    stream: TcpStream,
+    request: &Request,
+    #[allow(unused_variables)] connector: Option<Connector>,
+) -> Result<MaybeTlsStream<TcpStream>> {
+    if let Mode::Plain = uri_mode(request.uri())? {
+        return Ok(MaybeTlsStream::Plain(stream));
+    }
+    #[allow(unused_variables)]
+    let domain = request
+        .uri()
+        .host()
+        .ok_or(WsError::Url(UrlError::NoHostName))?;
+
+    #[allow(unreachable_patterns)]
+    match connector {
+        #[cfg(feature = "native-tls")]
+        Some(Connector::NativeTls(connector)) => native_tls(stream, domain, connector).await,
+        #[cfg(any(feature = "rustls-tls", feature = "rustls-tls-native-roots"))]
+        Some(Connector::Rustls(config)) => rustls(stream, domain, config).await,
+        #[cfg(feature = "native-tls")]
+        None => {
+            let connector = native_tls_crate::TlsConnector::new()
+                .map_err(|e| WsError::Tls(TlsError::Native(e)))?;
+            native_tls(stream, domain, connector).await
+        }
+        #[cfg(all(
+            any(feature = "rustls-tls", feature = "rustls-tls-native-roots"),
+            not(feature = "native-tls")
+        ))]
+        None => rustls(stream, domain, default_rustls_config()?).await,
+        _ => Err(WsError::Url(UrlError::TlsFeatureNotEnabled).into()),
+    }
+}
+
+#[cfg(feature = "native-tls")]
+async fn native_tls(
+    stream: TcpStream,
+    domain: &str,
+    connector: native_tls_crate::TlsConnector,
+) -> Result<MaybeTlsStream<TcpStream>> {
+    let stream = tokio_native_tls::TlsConnector::from(connector)
+        .connect(domain, stream)
+        .await
+        .map_err(|e| WsError::Tls(TlsError::Native(e)))?;
+    Ok(MaybeTlsStream::NativeTls(stream))
+}
+
+#[cfg(any(feature = "rustls-tls", feature = "rustls-tls-native-roots"))]
+async fn rustls(
+    stream: TcpStream,
+    domain: &str,
+    config: std::sync::Arc<rustls::ClientConfig>,
+) -> Result<MaybeTlsStream<TcpStream>> {
+    // rustls expects IPv6 addresses without their brackets
+    let domain = domain.trim_start_matches('[').trim_end_matches(']');
+    let domain = rustls_pki_types::ServerName::try_from(domain)
+        .map_err(|_| WsError::Tls(TlsError::InvalidDnsName))?
+        .to_owned();
+    let stream = tokio_rustls::TlsConnector::from(config)
+        .connect(domain, stream)
+        .await?;
+    Ok(MaybeTlsStream::Rustls(stream))
+}
+
+/// The configuration tokio-tungstenite uses when no connector is given.
+#[cfg(all(
+    any(feature = "rustls-tls", feature = "rustls-tls-native-roots"),
+    not(feature = "native-tls")
+))]
+#[allow(clippy::result_large_err)]
+fn default_rustls_config() -> Result<std::sync::Arc<rustls::ClientConfig>> {
+    #[allow(unused_mut)]
+    let mut root_store = rustls::RootCertStore::empty();
+    #[cfg(feature = "rustls-tls-native-roots")]
+    {
+        let rustls_native_certs::CertificateResult { certs, errors, .. } =
+            rustls_native_certs::load_native_certs();
+        if !errors.is_empty() {
+            log::warn!("native root CA certificate loading errors: {errors:?}");
+        }
+        // not finding any is only fatal without the bundled roots
+        #[cfg(not(feature = "rustls-tls"))]
+        if certs.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no native root CA certificates found (errors: {errors:?})"),
+            )
+            .into());
+        }
+        root_store.add_parsable_certificates(certs);
+    }
+    #[cfg(feature = "rustls-tls")]
+    root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    Ok(std::sync::Arc::new(
+        rustls::ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    ))
+}